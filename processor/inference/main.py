@@ -209,8 +209,8 @@ def callback(element, buffer, data):
             logger.debug(f"[{camera_id}] No persons detected in frame {frame_index}")
             return
         
-        # save frame as evidence image with camera_id
-        cv2.imwrite(f"/tmp/{camera_id}.jpg", frame)
+        # Encode the frame so it travels with its detections to the Main Runtime
+        encoded, image = cv2.imencode(".jpg", frame)
 
         # Assign body parts and PPE to each person
         person_assignments = assign_detections_to_persons(persons, others)
@@ -223,7 +223,8 @@ def callback(element, buffer, data):
             camera_id=camera_id,
             frame_id=frame_id,
             timestamp=timestamp,
-            person=persons
+            person=persons,
+            image=image.tobytes() if encoded else None
         )
         if success:
             logger.info(f"[{camera_id}] Sent violation for frame {frame_id} via UDP")
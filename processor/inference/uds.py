@@ -6,14 +6,20 @@ Sends violation messages to Rust runtime via UDS in JSON format.
 
 import json
 import socket
+import struct
 import time
 import os
-from typing import Dict, List, Any
+from typing import Dict, List, Any, Optional
 
 from inference.core.common.hailo_logger import get_logger
 
 logger = get_logger(__name__)
 
+# Streaming protocol, see processor/src/uds.rs
+UDS_MAGIC = b"GSCM"
+UDS_VERSION = 2
+UDS_FORMAT_JSON = 0
+
 class UDSSender:
     """
     Sends evidence messages to Rust runtime via UDS.
@@ -54,14 +60,20 @@ class UDSSender:
         """
         Initialize UDS sender.
 
-        Args:
-            host: Rust runtime host (default: localhost)
-            port: Rust runtime port (default: 8888)
+        The connection is kept open and every message is sent as a
+        length-prefixed frame after a version handshake:
+
+            handshake: b"GSCM" | version (u8) | format (u8, 0 = JSON)
+            response:  b"GSCM" | version (u8, 0 = rejected)
+            frame:     length (u32 big-endian) | payload
+
+        From version 2 the payload is the JSON length (u32 big-endian), the
+        JSON and the JPEG of the frame, so the stored evidence image always
+        matches its detections.
         """
         self.path = "/tmp/gidence-scm_uds.sock"
-
-        # Create UDS socket
-        self.sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        self.sock = None
+        self.version = 0
 
         while not os.path.exists(self.path):
             print(f"Waiting for {self.path} to be created...")
@@ -72,10 +84,48 @@ class UDSSender:
         self.messages_sent = 0
         self.messages_failed = 0
 
-    def send(self, camera_id: str, frame_id: str, timestamp: int, person: List[Dict[str, Any]]) -> bool:
+    def connect(self) -> bool:
+        """
+        Open the persistent connection and perform the version handshake.
+
+        Returns:
+            True if the Main Runtime accepted the handshake, False otherwise
+        """
+        try:
+            if self.sock:
+                self.sock.close()
+            self.sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+            self.sock.connect(self.path)
+            self.sock.sendall(UDS_MAGIC + bytes([UDS_VERSION, UDS_FORMAT_JSON]))
+
+            response = b""
+            while len(response) < len(UDS_MAGIC) + 1:
+                chunk = self.sock.recv(len(UDS_MAGIC) + 1 - len(response))
+                if not chunk:
+                    break
+                response += chunk
+
+            if response[:len(UDS_MAGIC)] != UDS_MAGIC or response[len(UDS_MAGIC):] in (b"", b"\x00"):
+                raise ConnectionError(f"Handshake rejected: {response!r}")
+
+            # Version accepted by the Main Runtime
+            self.version = response[len(UDS_MAGIC)]
+
+            return True
+
+        except (socket.error, ConnectionError, FileNotFoundError) as e:
+            logger.error(f"Failed to connect to {self.path}: {e}")
+            if self.sock:
+                self.sock.close()
+            self.sock = None
+            return False
+
+    def send(self, camera_id: str, frame_id: str, timestamp: int, person: List[Dict[str, Any]], image: Optional[bytes] = None) -> bool:
         """
         Send a single violation message.
 
+        Blocks while the Main Runtime is applying backpressure.
+
         Args:
             camera_id: Camera identifier
             frame_id: Frame identifier (e.g., "frame_001")
@@ -87,6 +137,7 @@ class UDSSender:
                 - part: List[Dict] (body parts)
                 - equipment: List[Dict] (PPE items)
                 - violation: List[str] (violation types)
+            image: JPEG encoded frame the detections were made on
 
         Returns:
             True if sent successfully, False otherwise
@@ -98,13 +149,18 @@ class UDSSender:
             "person": person
         }
 
+        if not self.sock and not self.connect():
+            self.messages_failed += 1
+            return False
+
         try:
             # Serialize to JSON
             payload = json.dumps(message).encode('utf-8')
+            if self.version >= 2:
+                payload = struct.pack(">I", len(payload)) + payload + (image or b"")
 
-            # Send via UDS
-            self.sock.connect(self.path)
-            self.sock.sendall(payload)
+            # Send via UDS as a length-prefixed frame
+            self.sock.sendall(struct.pack(">I", len(payload)) + payload)
 
             self.messages_sent += 1
             logger.debug(f"Sent violation: camera={camera_id}, frame={frame_id}, person_count={len(person)}")
@@ -112,11 +168,23 @@ class UDSSender:
             return True
 
         except Exception as e:
+            # Reconnect on the next message
             self.messages_failed += 1
             logger.error(f"Failed to send violation: {e}")
+            self.sock.close()
+            self.sock = None
             return False
 
+    def get_stats(self) -> Dict[str, int]:
+        """Get sender statistics."""
+        return {
+            "sent": self.messages_sent,
+            "failed": self.messages_failed,
+        }
+
     def close(self):
         """Close the UDS socket."""
-        self.sock.close()
+        if self.sock:
+            self.sock.close()
+            self.sock = None
         logger.info(f"UDS Sender closed. Stats: {self.get_stats()}")
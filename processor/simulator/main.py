@@ -15,6 +15,7 @@ import json
 import os
 import shutil
 import socket
+import struct
 import sys
 import termios
 import time
@@ -65,6 +66,7 @@ class UDSSender:
     def __init__(self, path: str = "/tmp/gidence-scm_uds.sock"):
         self.path = path
         self.sock: Optional[socket.socket] = None
+        self.version = 0
 
     def connect(self) -> bool:
         """Connect to the UDS socket."""
@@ -73,21 +75,31 @@ class UDSSender:
                 self.sock.close()
             self.sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
             self.sock.connect(self.path)
+
+            # Handshake for the persistent length-prefixed protocol
+            self.sock.sendall(b"GSCM" + bytes([2, 0]))
+            response = self.sock.recv(5)
+            if response[:4] != b"GSCM" or response[4:] in (b"", b"\x00"):
+                raise ConnectionError(f"Handshake rejected: {response!r}")
+            self.version = response[4]
             return True
-        except (socket.error, FileNotFoundError) as e:
+        except (socket.error, ConnectionError, FileNotFoundError) as e:
+            if self.sock:
+                self.sock.close()
             self.sock = None
             return False
 
-    def send(self, evidence: dict) -> bool:
-        """Send evidence JSON via UDS."""
+    def send(self, evidence: dict, image: Optional[bytes] = None) -> bool:
+        """Send evidence JSON, and from protocol version 2 its frame, via UDS."""
         if not self.sock:
             if not self.connect():
                 return False
 
         try:
             payload = json.dumps(evidence).encode('utf-8')
-            self.sock.sendall(payload)
-            self.sock.close()
+            if self.version >= 2:
+                payload = struct.pack(">I", len(payload)) + payload + (image or b"")
+            self.sock.sendall(struct.pack(">I", len(payload)) + payload)
             return True
         except (socket.error, BrokenPipeError):
             self.sock = None
@@ -246,10 +258,11 @@ class InferenceSimulator:
         )
 
         # Save evidence image
-        self.save_evidence_image(frame_id, timestamp)
+        image_path = self.save_evidence_image(frame_id, timestamp)
+        image = image_path.read_bytes() if image_path else None
 
         # Send via UDS
-        success = self.uds.send(evidence)
+        success = self.uds.send(evidence, image)
         if success:
             self.send_success += 1
         else:
@@ -0,0 +1,190 @@
+use std::{convert::Infallible, io::Cursor};
+
+use embedded_graphics::{
+    Drawable,
+    mono_font::{
+        MonoFont, MonoTextStyle,
+        ascii::{FONT_6X10, FONT_10X20},
+    },
+    pixelcolor::{Rgb888, RgbColor},
+    prelude::{DrawTarget, OriginDimensions, Pixel, Point, Primitive, Size},
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+use image::{ImageFormat, Rgb, RgbImage, codecs::jpeg::JpegEncoder};
+
+use crate::models::evidence::{
+    Evidence, EvidencePersonEquipmentLabel, EvidencePersonPartLabel, EvidencePersonViolation,
+};
+
+const ANNOTATION_QUALITY: u8 = 85;
+const ANNOTATION_SUCCESS: Rgb888 = Rgb888::new(34, 197, 94);
+const ANNOTATION_ERROR: Rgb888 = Rgb888::new(239, 68, 68);
+
+// Adapter so embedded-graphics primitives can be drawn onto an image buffer
+struct Canvas<'a>(&'a mut RgbImage);
+
+impl OriginDimensions for Canvas<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.0.width(), self.0.height())
+    }
+}
+impl DrawTarget for Canvas<'_> {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0
+                && point.y >= 0
+                && (point.x as u32) < self.0.width()
+                && (point.y as u32) < self.0.height()
+            {
+                self.0.put_pixel(
+                    point.x as u32,
+                    point.y as u32,
+                    Rgb([color.r(), color.g(), color.b()]),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// Render the evidence's boxes and violation labels onto its JPEG, colored the same
+// way as the processor web overlay
+pub fn annotate(evidence: &Evidence, image: &[u8]) -> Option<Vec<u8>> {
+    let mut image = image::load_from_memory_with_format(image, ImageFormat::Jpeg)
+        .ok()?
+        .to_rgb8();
+    let (width, height) = image.dimensions();
+    let stroke = (width / 400).max(2);
+    let font: &MonoFont = if width >= 1280 {
+        &FONT_10X20
+    } else {
+        &FONT_6X10
+    };
+
+    let mut canvas = Canvas(&mut image);
+
+    for person in evidence.person.iter() {
+        let color = if person.violation.is_empty() {
+            ANNOTATION_SUCCESS
+        } else {
+            ANNOTATION_ERROR
+        };
+        let area = rectangle(&person.bbox, width, height);
+        let _ = area
+            .into_styled(PrimitiveStyle::with_stroke(color, stroke))
+            .draw(&mut canvas);
+
+        for part in person.part.iter() {
+            let color = part_color(&part.label, &person.violation);
+            let _ = rectangle(&part.bbox, width, height)
+                .into_styled(PrimitiveStyle::with_stroke(color, stroke))
+                .draw(&mut canvas);
+        }
+        for equipment in person.equipment.iter() {
+            let color = equipment_color(&equipment.label, &person.violation);
+            let _ = rectangle(&equipment.bbox, width, height)
+                .into_styled(PrimitiveStyle::with_stroke(color, stroke))
+                .draw(&mut canvas);
+        }
+
+        // One label per violation, stacked above the person box
+        let line = font.character_size.height as i32 + 2;
+        let mut y = (area.top_left.y - line * person.violation.len() as i32).max(0);
+        for violation in person.violation.iter() {
+            let text = label(violation);
+            let background = Rectangle::new(
+                Point::new(area.top_left.x, y),
+                Size::new(
+                    font.character_size.width * text.len() as u32 + 4,
+                    line as u32,
+                ),
+            );
+            let _ = background
+                .into_styled(PrimitiveStyle::with_fill(ANNOTATION_ERROR))
+                .draw(&mut canvas);
+            let _ = Text::with_baseline(
+                &text,
+                Point::new(area.top_left.x + 2, y + 1),
+                MonoTextStyle::new(font, Rgb888::WHITE),
+                Baseline::Top,
+            )
+            .draw(&mut canvas);
+            y += line;
+        }
+    }
+
+    let mut buffer = Cursor::new(Vec::new());
+    JpegEncoder::new_with_quality(&mut buffer, ANNOTATION_QUALITY)
+        .encode_image(&image)
+        .ok()?;
+    Some(buffer.into_inner())
+}
+
+// Bounding boxes are normalized [xmin, ymin, xmax, ymax]
+fn rectangle(bbox: &[f32; 4], width: u32, height: u32) -> Rectangle {
+    let x = (bbox[0].clamp(0.0, 1.0) * width as f32) as i32;
+    let y = (bbox[1].clamp(0.0, 1.0) * height as f32) as i32;
+    let w = ((bbox[2] - bbox[0]).clamp(0.0, 1.0) * width as f32) as u32;
+    let h = ((bbox[3] - bbox[1]).clamp(0.0, 1.0) * height as f32) as u32;
+    Rectangle::new(Point::new(x, y), Size::new(w, h))
+}
+
+fn label(violation: &EvidencePersonViolation) -> String {
+    serde_json::to_value(violation)
+        .ok()
+        .and_then(|v| v.as_str().map(|v| v.to_string()))
+        .unwrap_or_default()
+}
+
+fn part_color(label: &EvidencePersonPartLabel, violation: &[EvidencePersonViolation]) -> Rgb888 {
+    use EvidencePersonViolation::*;
+
+    let error = match label {
+        EvidencePersonPartLabel::Head => violation.contains(&MissingHardhat),
+        EvidencePersonPartLabel::Hand => {
+            violation.contains(&MissingGloves) || violation.contains(&ImproperlyWornGloves)
+        }
+        EvidencePersonPartLabel::Foot => {
+            violation.contains(&MissingShoes) || violation.contains(&ImproperlyWornShoes)
+        }
+        EvidencePersonPartLabel::Face => {
+            violation.contains(&MissingFacemask) || violation.contains(&ImproperlyWornFacemask)
+        }
+        EvidencePersonPartLabel::Ear => {
+            violation.contains(&MissingEarmuffs) || violation.contains(&ImproperlyWornEarmuffs)
+        }
+    };
+    if error {
+        ANNOTATION_ERROR
+    } else {
+        ANNOTATION_SUCCESS
+    }
+}
+
+fn equipment_color(
+    label: &EvidencePersonEquipmentLabel,
+    violation: &[EvidencePersonViolation],
+) -> Rgb888 {
+    use EvidencePersonViolation::*;
+
+    let error = match label {
+        EvidencePersonEquipmentLabel::Hardhat => violation.contains(&ImproperlyWornHardhat),
+        EvidencePersonEquipmentLabel::Gloves => violation.contains(&ImproperlyWornGloves),
+        EvidencePersonEquipmentLabel::Shoes => violation.contains(&ImproperlyWornShoes),
+        EvidencePersonEquipmentLabel::Facemask => violation.contains(&ImproperlyWornFacemask),
+        EvidencePersonEquipmentLabel::Earmuffs => violation.contains(&ImproperlyWornEarmuffs),
+        _ => false,
+    };
+    if error {
+        ANNOTATION_ERROR
+    } else {
+        ANNOTATION_SUCCESS
+    }
+}
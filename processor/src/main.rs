@@ -3,9 +3,8 @@ use actix_files::Files;
 use actix_web::{App, HttpServer, middleware::Logger, web::Data};
 use chrono::Local;
 use models::processor::Processor;
-use serde_json::from_slice;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     env,
     net::SocketAddr,
     process::{Command, Stdio},
@@ -13,17 +12,28 @@ use std::{
 };
 use tokio::{
     fs,
-    io::AsyncReadExt,
     net::UnixListener,
     sync::RwLock,
     time::{Duration, sleep},
 };
 use uuid::Uuid;
 
-use crate::models::{Device, Reading, camera::Camera, evidence::Evidence};
+use crate::models::{
+    Device, Reading,
+    camera::Camera,
+    clip::Clip,
+    confirmation::Confirmation,
+    deduplication::Deduplication,
+    evidence::Evidence,
+    outbox::{Outbox, OutboxStatus},
+    retention::Retention,
+};
+use crate::uds::{UDS_QUEUE_MAXIMUM, UdsConnection};
 
+mod annotation;
 mod models;
 mod routes;
+mod uds;
 
 #[tokio::main]
 async fn main() {
@@ -44,9 +54,11 @@ async fn main() {
     let timestamp = Local::now().timestamp_millis();
 
     // Shared state across threads
-    let violation = HashMap::<String, Evidence>::new(); // camera_id+person_id -> Evidence
+    let confirmation = Confirmation::default();
+    let deduplication = Deduplication::default();
     let queue = VecDeque::<Evidence>::new();
     let processor = Processor::load();
+    let outbox = Outbox::load();
 
     let mut reading = Reading {
         camera: HashMap::new(),
@@ -54,6 +66,7 @@ async fn main() {
     let mut device = Device {
         processor: processor.clone(),
         camera: HashMap::new(),
+        retention: Retention::default(),
     };
 
     let mut cameras_raw = Camera::load();
@@ -64,85 +77,127 @@ async fn main() {
     }
     device.camera = cameras;
 
-    let violation = Arc::new(RwLock::new(violation));
+    let confirmation = Arc::new(RwLock::new(confirmation));
+    let deduplication = Arc::new(RwLock::new(deduplication));
     let queue = Arc::new(RwLock::new(queue));
     let reading = Arc::new(RwLock::new(reading));
     let device = Arc::new(RwLock::new(device));
+    let outbox = Arc::new(RwLock::new(outbox));
 
     // UDS THREAD: UDS listener for receiving Evidence structs
     let device_clone = Arc::clone(&device);
     let reading_clone = Arc::clone(&reading);
     let queue_clone = Arc::clone(&queue);
-    let _ = tokio::spawn(async move {
+    tokio::spawn(async move {
         loop {
-            let (mut stream, _) = match listener.accept().await {
+            let (stream, _) = match listener.accept().await {
                 Ok(conn) => conn,
                 Err(e) => {
                     println!("[UDS] Failed to accept connection: {}", e);
                     continue;
                 }
             };
-            let mut buffer = vec![];
 
-            if let Err(e) = stream.read_to_end(&mut buffer).await {
-                println!("Error reading from stream: {}", e);
-                continue;
-            }
+            // Each connection is served on its own task, streaming clients stay connected
+            let device_clone = Arc::clone(&device_clone);
+            let reading_clone = Arc::clone(&reading_clone);
+            let queue_clone = Arc::clone(&queue_clone);
+            tokio::spawn(async move {
+                let mut connection = match UdsConnection::accept(stream).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        println!("[UDS] Handshake failed: {}", e);
+                        return;
+                    }
+                };
 
-            let mut evidence = match from_slice::<Evidence>(&buffer) {
-                Ok(v) => v,
-                Err(e) => {
-                    println!("Error parsing JSON: {}", e);
-                    continue;
-                }
-            };
-            evidence.id = Uuid::new_v4().to_string();
+                loop {
+                    let buffer = match connection.next().await {
+                        Ok(Some(v)) => v,
+                        Ok(None) => break,
+                        Err(e) => {
+                            println!("Error reading from stream: {}", e);
+                            break;
+                        }
+                    };
 
-            // Update the reading with the new evidence
-            {
-                let device = device_clone.read().await;
-                if device.camera.get(&evidence.camera_id).is_some() {
-                    let mut queue = queue_clone.write().await;
-                    queue.push_back(evidence.clone());
-                } else {
-                    continue; // Camera not found, skip
-                }
-            }
-            {
-                let mut reading = reading_clone.write().await;
-                if let Some(entry) = reading.camera.get_mut(&evidence.camera_id) {
-                    // Count FPS
-                    let old_timestamp = entry.1;
-                    let new_timestamp = Local::now().timestamp_millis();
-                    let fps = if old_timestamp == 0 {
-                        0.0
-                    } else {
-                        1000.0 / ((new_timestamp - old_timestamp) as f64)
+                    let mut evidence = match connection.decode(&buffer) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("Error parsing evidence: {}", e);
+                            continue;
+                        }
                     };
-                    entry.0 = Some(evidence);
-                    entry.1 = new_timestamp;
-                    entry.2 = fps;
-                } else {
-                    reading.camera.insert(
-                        evidence.camera_id.clone(),
-                        (Some(evidence), Local::now().timestamp_millis(), 0.0),
-                    );
+                    evidence.id = Uuid::new_v4().to_string();
+
+                    // Backpressure: stop reading while the queue is full so the socket buffer
+                    // fills up and the Inference Engine blocks on send
+                    while queue_clone.read().await.len() >= UDS_QUEUE_MAXIMUM {
+                        sleep(Duration::from_millis(10)).await;
+                    }
+
+                    // Update the reading with the new evidence
+                    {
+                        let device = device_clone.read().await;
+                        if device.camera.contains_key(&evidence.camera_id) {
+                            let mut queue = queue_clone.write().await;
+                            queue.push_back(evidence.clone());
+                        } else {
+                            continue; // Camera not found, skip
+                        }
+                    }
+                    {
+                        let mut reading = reading_clone.write().await;
+                        if let Some(entry) = reading.camera.get_mut(&evidence.camera_id) {
+                            // Count FPS
+                            let old_timestamp = entry.1;
+                            let new_timestamp = Local::now().timestamp_millis();
+                            let fps = if old_timestamp == 0 {
+                                0.0
+                            } else {
+                                1000.0 / ((new_timestamp - old_timestamp) as f64)
+                            };
+                            entry.0 = Some(evidence);
+                            entry.1 = new_timestamp;
+                            entry.2 = fps;
+                        } else {
+                            reading.camera.insert(
+                                evidence.camera_id.clone(),
+                                (Some(evidence), Local::now().timestamp_millis(), 0.0),
+                            );
+                        }
+                    }
                 }
-            }
+            });
         }
     });
 
     // QUEUE PROCESSOR THREAD: Process evidence from the queue
-    let violation_queue = Arc::clone(&violation);
+    let device_clone = Arc::clone(&device);
+    let confirmation_clone = Arc::clone(&confirmation);
+    let deduplication_clone = Arc::clone(&deduplication);
     let queue_clone = Arc::clone(&queue);
-    let _ = tokio::spawn(async move {
+    let outbox_clone = Arc::clone(&outbox);
+    tokio::spawn(async move {
+        // Only this thread sees every frame, so the clip ring buffers live here
+        let mut clip = Clip::default();
+
         loop {
+            // Clips are finished by time, also when their camera stopped sending frames
+            for recording in clip.expire() {
+                if !recording.save() {
+                    println!("[Clip] Failed to save the clip of {}", recording.id);
+                }
+                let mut outbox = outbox_clone.write().await;
+                outbox.insert(&recording.id);
+            }
+
             let evidence = {
                 let mut queue = queue_clone.write().await;
                 queue.pop_front()
             };
 
-            let evidence = match evidence {
+            let mut evidence = match evidence {
                 Some(e) => e,
                 None => {
                     sleep(Duration::from_millis(100)).await;
@@ -150,37 +205,43 @@ async fn main() {
                 }
             };
 
-            let mut new_violation = false;
-            for person in evidence.person.iter() {
-                let key = format!("{}-{}", evidence.camera_id, person.id);
-
-                // Check if a violation by this person on this camera already exists
-                if let Some(existing) = {
-                    let violation = violation_queue.read().await;
-                    violation.get(&key).cloned()
-                } {
-                    // Check if the old evidence is older than 10 minutes than the new one
-                    if evidence.timestamp - existing.timestamp < 10 * 60 * 1000 {
-                        // Skip inserting this evidence as it's a duplicate within 10 minutes
-                        continue;
-                    }
+            let camera = {
+                let device = device_clone.read().await;
+                match device.camera.get(&evidence.camera_id) {
+                    Some(v) => v.clone(),
+                    None => continue,
                 }
+            };
 
-                // Insert or update the violation record
-                if person.violation.len() > 0 {
-                    new_violation = true;
-                    {
-                        let mut violation = violation_queue.write().await;
-                        violation.insert(key, evidence.clone());
+            clip.push(&camera, &evidence);
+
+            // Drop violations that have not persisted long enough to rule out detector flicker
+            {
+                let mut confirmation = confirmation_clone.write().await;
+                confirmation.confirm(&camera, &mut evidence);
+            }
+
+            let mut new_violation = false;
+            {
+                let mut deduplication = deduplication_clone.write().await;
+                for person in evidence.person.iter() {
+                    // Every person has to be checked so their state stays up to date
+                    if deduplication.check(&camera, evidence.timestamp, person) {
+                        new_violation = true;
                     }
                 }
             }
 
             // If no new violation detected, skip processing
             if new_violation {
-                let image = match fs::read(format!("/tmp/{}.jpg", evidence.camera_id)).await {
-                    Ok(d) => d,
-                    Err(_) => continue,
+                // Prefer the frame sent along with the detections, /tmp is only written by
+                // engines that predate protocol version 2 and may already hold a newer frame
+                let image = match &evidence.image {
+                    Some(v) => v.to_vec(),
+                    None => match fs::read(format!("/tmp/{}.jpg", evidence.camera_id)).await {
+                        Ok(d) => d,
+                        Err(_) => continue,
+                    },
                 };
 
                 // Save the evidence and the image to ./evidence
@@ -195,86 +256,132 @@ async fn main() {
                 )
                 .await
                 .unwrap();
+
+                // Render the annotated copy off the runtime, decoding and encoding is CPU bound
+                let evidence_clone = evidence.clone();
+                if let Ok(Some(annotated)) = tokio::task::spawn_blocking(move || {
+                    annotation::annotate(&evidence_clone, &image)
+                })
+                .await
+                {
+                    let _ = fs::write(
+                        &format!("./evidence/{}.annotated.jpg", evidence.id),
+                        &annotated,
+                    )
+                    .await;
+                }
+
+                if camera.clip.as_ref().is_some_and(|v| v.enabled()) && evidence.image.is_some() {
+                    // Journaled once the clip is complete so both are uploaded together
+                    clip.start(&camera, &evidence.id);
+                } else {
+                    // Journal the evidence for upload
+                    let mut outbox = outbox_clone.write().await;
+                    outbox.insert(&evidence.id);
+                }
             }
 
             sleep(Duration::from_millis(100)).await;
         }
     });
 
-    // WEBHOOK SENDER THREAD: Send the saved evidence to configured webhooks
+    // WEBHOOK SENDER THREAD: Send the journaled evidence to configured webhooks
     let device_clone = Arc::clone(&device);
-    let _ = tokio::spawn(async move {
+    let outbox_clone = Arc::clone(&outbox);
+    tokio::spawn(async move {
         loop {
-            // Load all evidences from ./evidence/{}.json (NOT ./evidence/uploaded.{}.json) that have not been sent yet
-            let mut evidences = {
-                let mut evidences = Vec::new();
-                let entries = match fs::read_dir("./evidence").await {
-                    Ok(entries) => entries,
-                    Err(_) => {
-                        sleep(Duration::from_millis(100)).await;
-                        continue;
-                    }
-                };
-
-                let mut dir = entries;
-                while let Ok(Some(entry)) = dir.next_entry().await {
-                    let file_name = entry.file_name().into_string().unwrap();
-                    if file_name.ends_with(".json") && !file_name.starts_with("uploaded.") {
-                        let data = match fs::read_to_string(entry.path()).await {
-                            Ok(d) => d,
-                            Err(_) => continue,
-                        };
-                        let evidence: Evidence = match serde_json::from_str(&data) {
-                            Ok(e) => e,
-                            Err(_) => continue,
-                        };
-                        evidences.push(evidence);
-                    }
+            let webhook = {
+                let device = device_clone.read().await;
+                device.processor.webhook.clone()
+            };
+            let webhook = match webhook {
+                Some(v) => v,
+                None => {
+                    sleep(Duration::from_secs(1)).await;
+                    continue;
                 }
-                evidences
             };
 
-            for evidence in evidences.drain(..) {
-                let webhook = {
-                    let device = device_clone.read().await;
-                    match device.processor.webhook.clone() {
-                        Some(v) => v,
-                        None => continue,
+            // Only items whose backoff has elapsed, so an unreachable server is not hammered
+            let item = {
+                let outbox = outbox_clone.read().await;
+                outbox.due()
+            };
+
+            for item in item.iter() {
+                let sent = match (
+                    fs::read_to_string(format!("./evidence/{}.json", item.id)).await,
+                    fs::read(format!("./evidence/{}.jpg", item.id)).await,
+                ) {
+                    (Ok(payload), Ok(image)) => {
+                        let annotated = fs::read(format!("./evidence/{}.annotated.jpg", item.id))
+                            .await
+                            .ok();
+                        let clip = fs::read(format!("./evidence/{}.avi", item.id)).await.ok();
+                        webhook
+                            .send_evidence(payload, image, annotated, clip, &item.id)
+                            .await
                     }
+                    _ => false,
                 };
 
-                // Send evidence and captured image via webhook
-                let image = match fs::read(format!("./evidence/{}.jpg", evidence.id)).await {
-                    Ok(d) => d,
-                    Err(_) => continue,
-                };
-                let payload = serde_json::to_string(&evidence).unwrap();
+                let mut outbox = outbox_clone.write().await;
+                if sent {
+                    outbox.deliver(&item.id);
+                } else {
+                    outbox.fail(&item.id);
+                }
+            }
 
-                if webhook
-                    .send_evidence(payload, image.clone(), &evidence.id)
-                    .await
-                {
-                    // Rename the evidence files to mark them as uploaded
-                    let _ = fs::rename(
-                        format!("./evidence/{}.json", evidence.id),
-                        format!("./evidence/uploaded.{}.json", evidence.id),
-                    )
-                    .await;
-                    let _ = fs::rename(
-                        format!("./evidence/{}.jpg", evidence.id),
-                        format!("./evidence/uploaded.{}.jpg", evidence.id),
-                    )
-                    .await;
+            sleep(Duration::from_secs(1)).await;
+        }
+    });
+
+    // RETENTION THREAD: Keep ./evidence within the configured age and size limits
+    let device_clone = Arc::clone(&device);
+    let outbox_clone = Arc::clone(&outbox);
+    tokio::spawn(async move {
+        loop {
+            let (policy, mut retention) = {
+                let device = device_clone.read().await;
+                (device.processor.retention.clone(), device.retention.clone())
+            };
+            // Uploaded evidence is known by its delivered marker
+            let pending = {
+                let outbox = outbox_clone.read().await;
+                outbox
+                    .item
+                    .values()
+                    .filter(|i| i.status == OutboxStatus::Pending)
+                    .map(|i| i.id.clone())
+                    .collect::<HashSet<String>>()
+            };
+
+            let dropped = retention.dropped;
+            let deleted = retention.enforce(&policy, &pending);
+            if !deleted.is_empty() {
+                println!("[Retention] Deleted {} evidence(s)", deleted.len());
+                if retention.dropped > dropped {
+                    println!(
+                        "[Retention] Dropped {} evidence(s) before upload to stay within the size limit",
+                        retention.dropped - dropped
+                    );
                 }
+                let mut outbox = outbox_clone.write().await;
+                outbox.remove(&deleted);
+            }
+            {
+                let mut device = device_clone.write().await;
+                device.retention = retention;
             }
 
-            sleep(Duration::from_millis(100)).await;
+            sleep(Duration::from_secs(60)).await;
         }
     });
 
     // WEBHOOK UPDATER THREAD: Periodically update webhook info from Device
     let device_clone = Arc::clone(&device);
-    let _ = tokio::spawn(async move {
+    tokio::spawn(async move {
         loop {
             let (processor, camera, webhook) = {
                 let device = device_clone.read().await;
@@ -297,7 +404,7 @@ async fn main() {
                 "camera": camera,
             });
 
-            let _ = webhook.send_update(payload.to_string()).await;
+            webhook.send_update(payload.to_string()).await;
 
             sleep(Duration::from_secs(10)).await;
         }
@@ -305,7 +412,7 @@ async fn main() {
 
     // INFERENCE ENGINE THREAD: Spawn inference engine thread with auto-restart capability
     let device_clone = Arc::clone(&device);
-    let _ = tokio::spawn(async move {
+    tokio::spawn(async move {
         // In simulation mode, just wait indefinitely (run simulator manually in another terminal)
         if simulation_mode {
             println!("[Inference Engine] Simulation mode active, not starting real engine");
@@ -421,7 +528,8 @@ async fn main() {
             .wrap(cors)
             .app_data(Data::new(device.clone()))
             .app_data(Data::new(reading.clone()))
-            .app_data(Data::new(violation.clone()))
+            .app_data(Data::new(deduplication.clone()))
+            .app_data(Data::new(outbox.clone()))
             .wrap(Logger::default())
             .configure(routes::configure_routes)
             .service(Files::new("/evidence", "./evidence").show_files_listing())
@@ -1,12 +1,34 @@
-use std::fs::{read_to_string, write};
+use std::{
+    collections::HashMap,
+    fs::{read_to_string, write},
+};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::models::evidence::EvidencePersonViolation;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Camera {
     pub id: String,
     pub address: CameraAddress,
     pub name: String,
+    #[serde(default)]
+    pub deduplication: CameraDeduplication,
+    #[serde(default)]
+    pub confirmation: CameraConfirmation,
+    #[serde(default)]
+    pub clip: Option<CameraClip>, // Clips are only recorded when set
+}
+// Changes to a camera, the fields left out keep their value
+#[derive(Debug, Deserialize)]
+pub struct CameraRequest {
+    pub id: String,
+    pub address: Option<CameraAddress>,
+    pub name: Option<String>,
+    pub deduplication: Option<CameraDeduplication>,
+    pub confirmation: Option<CameraConfirmation>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub clip: Option<Option<CameraClip>>, // Kept as is when not set, clips stop when null
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CameraAddress {
@@ -16,7 +38,115 @@ pub struct CameraAddress {
     pub authentication: Option<(String, String)>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CameraClip {
+    pub pre: i64,  // Milliseconds of footage kept before the violation
+    pub post: i64, // Milliseconds of footage recorded after the violation
+    pub fps: u32,  // Frames per second kept in the clip, 0 disables clips
+}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CameraConfirmation {
+    pub frame: u32,    // Frames a violation has to be observed in
+    pub duration: i64, // Milliseconds a violation has to persist
+    pub gap: i64,      // Milliseconds a violation may be missing before its count restarts
+}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CameraDeduplication {
+    pub kind: CameraDeduplicationKind,
+    pub cooldown: i64, // Milliseconds before the same person may alert again
+    #[serde(default)]
+    pub violation: HashMap<EvidencePersonViolation, i64>, // Per violation cooldown override
+}
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraDeduplicationKind {
+    Person,    // One alert per person per cooldown
+    Violation, // One alert per person and violation kind per cooldown
+    Change,    // Alert only when the person's set of violations changes
+}
+
+// Tells a field set to null apart from one left out
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl Default for CameraClip {
+    fn default() -> Self {
+        Self {
+            pre: 5 * 1000,
+            post: 5 * 1000,
+            fps: 5,
+        }
+    }
+}
+
+impl CameraClip {
+    pub fn enabled(&self) -> bool {
+        self.fps > 0 && (self.pre > 0 || self.post > 0)
+    }
+}
+
+impl Default for CameraConfirmation {
+    fn default() -> Self {
+        // Confirm on the first frame, same as having no confirmation stage
+        Self {
+            frame: 1,
+            duration: 0,
+            gap: 1000,
+        }
+    }
+}
+
+impl Default for CameraDeduplication {
+    fn default() -> Self {
+        Self {
+            kind: CameraDeduplicationKind::Person,
+            cooldown: 10 * 60 * 1000,
+            violation: HashMap::new(),
+        }
+    }
+}
+
+impl CameraDeduplication {
+    pub fn cooldown(&self, violation: &EvidencePersonViolation) -> i64 {
+        self.violation
+            .get(violation)
+            .copied()
+            .unwrap_or(self.cooldown)
+    }
+    // How long a person's state has to be kept to honour every cooldown
+    pub fn lifetime(&self) -> i64 {
+        self.violation
+            .values()
+            .copied()
+            .fold(self.cooldown, i64::max)
+    }
+}
+
 impl Camera {
+    pub fn apply(&mut self, request: CameraRequest) {
+        if let Some(address) = request.address {
+            self.address = address;
+        }
+        if let Some(name) = request.name {
+            self.name = name;
+        }
+        if let Some(deduplication) = request.deduplication {
+            self.deduplication = deduplication;
+        }
+        if let Some(confirmation) = request.confirmation {
+            self.confirmation = confirmation;
+        }
+        if let Some(clip) = request.clip {
+            self.clip = clip;
+        }
+    }
+
     pub fn insert_many(camera: &Vec<Self>) {
         let camera_json = serde_json::to_string(&camera).unwrap();
         write("camera.json", camera_json).unwrap();
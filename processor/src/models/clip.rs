@@ -0,0 +1,214 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::write,
+    io::Cursor,
+    sync::Arc,
+};
+
+use chrono::Local;
+use image::ImageReader;
+
+use crate::models::{camera::Camera, evidence::Evidence};
+
+#[derive(Debug, Default)]
+pub struct Clip {
+    pub buffer: HashMap<String, VecDeque<ClipFrame>>, // camera_id -> recent frames
+    pub recording: Vec<ClipRecording>,
+}
+#[derive(Debug, Clone)]
+pub struct ClipFrame {
+    pub timestamp: i64,
+    pub image: Arc<Vec<u8>>, // JPEG
+}
+#[derive(Debug)]
+pub struct ClipRecording {
+    pub id: String, // evidence_id
+    pub camera_id: String,
+    pub frame: Vec<ClipFrame>,
+    pub deadline: i64, // When the post-violation footage is complete
+}
+
+impl Clip {
+    // Keep the frame in the camera's ring buffer and feed it to the clips being recorded
+    pub fn push(&mut self, camera: &Camera, evidence: &Evidence) {
+        let timestamp = Local::now().timestamp_millis();
+
+        let (image, setting) = match (&evidence.image, &camera.clip) {
+            (Some(v), Some(setting)) if setting.enabled() => (Arc::clone(v), setting),
+            _ => return,
+        };
+
+        let buffer = self.buffer.entry(camera.id.clone()).or_default();
+
+        // Throttle to the clip frame rate, the engine usually runs much faster
+        let interval = 1000 / setting.fps as i64;
+        if buffer
+            .back()
+            .is_some_and(|f| timestamp - f.timestamp < interval)
+        {
+            return;
+        }
+
+        let frame = ClipFrame { timestamp, image };
+        buffer.push_back(frame.clone());
+        while buffer
+            .front()
+            .is_some_and(|f| f.timestamp < timestamp - setting.pre)
+        {
+            buffer.pop_front();
+        }
+
+        for recording in self.recording.iter_mut() {
+            if recording.camera_id == camera.id {
+                recording.frame.push(frame.clone());
+            }
+        }
+    }
+    // Start a clip for the evidence from the frames already buffered
+    pub fn start(&mut self, camera: &Camera, id: &str) {
+        let frame = self
+            .buffer
+            .get(&camera.id)
+            .map(|b| b.iter().cloned().collect::<Vec<ClipFrame>>())
+            .unwrap_or_default();
+
+        self.recording.push(ClipRecording {
+            id: id.to_string(),
+            camera_id: camera.id.clone(),
+            frame,
+            deadline: Local::now().timestamp_millis()
+                + camera.clip.as_ref().map(|v| v.post).unwrap_or_default(),
+        });
+    }
+    // Clips whose post-violation window has elapsed, also when the camera stopped sending frames
+    pub fn expire(&mut self) -> Vec<ClipRecording> {
+        let timestamp = Local::now().timestamp_millis();
+        let (finished, recording) = self
+            .recording
+            .drain(..)
+            .partition(|r| r.deadline <= timestamp);
+        self.recording = recording;
+        finished
+    }
+}
+
+impl ClipRecording {
+    // Write the clip to ./evidence/{id}.avi next to the evidence image
+    pub fn save(&self) -> bool {
+        match self.encode() {
+            Some(avi) => write(format!("./evidence/{}.avi", self.id), avi).is_ok(),
+            None => false,
+        }
+    }
+    // Motion JPEG in an AVI container, the frames are stored as they came from the engine
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let first = self.frame.first()?;
+        let (width, height) = ImageReader::new(Cursor::new(first.image.as_slice()))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()?;
+
+        let count = self.frame.len() as u32;
+        // Frames are throttled rather than evenly spaced, use the average rate
+        let duration = self
+            .frame
+            .last()
+            .map(|f| f.timestamp - first.timestamp)
+            .unwrap_or_default()
+            .max(1) as u32;
+        let (rate, scale) = if count > 1 {
+            ((count - 1) * 1000, duration)
+        } else {
+            (1, 1)
+        };
+        let buffer_size = self
+            .frame
+            .iter()
+            .map(|f| f.image.len() as u32)
+            .max()
+            .unwrap_or_default();
+
+        let mut avih = Vec::with_capacity(56);
+        for v in [
+            (scale as u64 * 1_000_000 / rate as u64) as u32, // dwMicroSecPerFrame
+            0,                                               // dwMaxBytesPerSec
+            0,                                               // dwPaddingGranularity
+            0x10,                                            // dwFlags: AVIF_HASINDEX
+            count,                                           // dwTotalFrames
+            0,                                               // dwInitialFrames
+            1,                                               // dwStreams
+            buffer_size,                                     // dwSuggestedBufferSize
+            width,
+            height,
+            0,
+            0,
+            0,
+            0,
+        ] {
+            avih.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let mut strh = Vec::with_capacity(56);
+        strh.extend_from_slice(b"vids");
+        strh.extend_from_slice(b"MJPG");
+        for v in [0u32, 0, 0, scale, rate, 0, count, buffer_size, u32::MAX, 0] {
+            strh.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [0u16, 0, width as u16, height as u16] {
+            strh.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let mut strf = Vec::with_capacity(40);
+        strf.extend_from_slice(&40u32.to_le_bytes());
+        strf.extend_from_slice(&width.to_le_bytes());
+        strf.extend_from_slice(&height.to_le_bytes());
+        strf.extend_from_slice(&1u16.to_le_bytes());
+        strf.extend_from_slice(&24u16.to_le_bytes());
+        strf.extend_from_slice(b"MJPG");
+        for v in [width * height * 3, 0, 0, 0, 0] {
+            strf.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let strl = list(
+            b"strl",
+            &[chunk(b"strh", &strh), chunk(b"strf", &strf)].concat(),
+        );
+        let hdrl = list(b"hdrl", &[chunk(b"avih", &avih), strl].concat());
+
+        let mut movi = Vec::new();
+        let mut idx1 = Vec::new();
+        for frame in self.frame.iter() {
+            // Offsets are relative to the "movi" fourcc
+            idx1.extend_from_slice(b"00dc");
+            idx1.extend_from_slice(&0x10u32.to_le_bytes()); // AVIIF_KEYFRAME
+            idx1.extend_from_slice(&(movi.len() as u32 + 4).to_le_bytes());
+            idx1.extend_from_slice(&(frame.image.len() as u32).to_le_bytes());
+            movi.extend_from_slice(&chunk(b"00dc", &frame.image));
+        }
+        let movi = list(b"movi", &movi);
+
+        let mut avi = Vec::new();
+        avi.extend_from_slice(b"AVI ");
+        avi.extend_from_slice(&hdrl);
+        avi.extend_from_slice(&movi);
+        avi.extend_from_slice(&chunk(b"idx1", &idx1));
+
+        Some(chunk(b"RIFF", &avi))
+    }
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 9);
+    chunk.extend_from_slice(id);
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    // Chunks are word aligned
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+fn list(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    chunk(b"LIST", &[&kind[..], data].concat())
+}
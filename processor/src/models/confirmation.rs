@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::models::{
+    camera::Camera,
+    evidence::{Evidence, EvidencePersonConfirmation, EvidencePersonViolation},
+};
+
+// Minimum interval between eviction sweeps
+const CONFIRMATION_EVICTION_INTERVAL: i64 = 1000;
+
+#[derive(Debug, Default)]
+pub struct Confirmation {
+    pub entry: HashMap<String, ConfirmationEntry>, // camera_id-person_id -> ConfirmationEntry
+    pub timestamp: i64,                            // Last eviction sweep
+}
+#[derive(Debug, Default)]
+pub struct ConfirmationEntry {
+    pub expiry: i64, // When every streak of the person has lapsed
+    pub violation: HashMap<EvidencePersonViolation, EvidencePersonConfirmation>,
+}
+
+impl Confirmation {
+    // Keeps only the violations that persisted long enough on each tracked person
+    // and attaches their confirmation stats
+    pub fn confirm(&mut self, camera: &Camera, evidence: &mut Evidence) {
+        self.evict(evidence.timestamp);
+
+        let policy = &camera.confirmation;
+        let timestamp = evidence.timestamp;
+
+        for person in evidence.person.iter_mut() {
+            if person.violation.is_empty() {
+                continue;
+            }
+
+            let entry = self
+                .entry
+                .entry(format!("{}-{}", camera.id, person.id))
+                .or_default();
+            entry.expiry = timestamp + policy.gap;
+
+            let mut violation = Vec::new();
+            let mut confirmation = Vec::new();
+            for v in person.violation.drain(..) {
+                let streak =
+                    entry
+                        .violation
+                        .entry(v.clone())
+                        .or_insert(EvidencePersonConfirmation {
+                            violation: v.clone(),
+                            frame: 0,
+                            timestamp,
+                            duration: 0,
+                        });
+
+                // Restart the streak if the violation was missing for too long
+                if timestamp - (streak.timestamp + streak.duration) > policy.gap {
+                    streak.frame = 0;
+                    streak.timestamp = timestamp;
+                }
+                streak.frame += 1;
+                streak.duration = timestamp - streak.timestamp;
+
+                if streak.frame >= policy.frame && streak.duration >= policy.duration {
+                    confirmation.push(streak.clone());
+                    violation.push(v);
+                }
+            }
+
+            person.violation = violation;
+            person.confirmation = confirmation;
+        }
+    }
+
+    fn evict(&mut self, timestamp: i64) {
+        if timestamp - self.timestamp < CONFIRMATION_EVICTION_INTERVAL {
+            return;
+        }
+        self.timestamp = timestamp;
+
+        self.entry.retain(|_, e| e.expiry >= timestamp);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::models::{
+    camera::{Camera, CameraDeduplicationKind},
+    evidence::{EvidencePerson, EvidencePersonViolation},
+};
+
+// Upper bound of tracked persons, the least recently seen quarter is dropped when reached
+const DEDUPLICATION_CAPACITY: usize = 4096;
+// Minimum interval between eviction sweeps
+const DEDUPLICATION_EVICTION_INTERVAL: i64 = 1000;
+
+#[derive(Debug, Default)]
+pub struct Deduplication {
+    pub entry: HashMap<String, DeduplicationEntry>, // camera_id-person_id -> DeduplicationEntry
+    pub timestamp: i64,                             // Last eviction sweep
+}
+#[derive(Debug, Default)]
+pub struct DeduplicationEntry {
+    pub timestamp: i64,     // Last time the person was seen
+    pub expiry: i64,        // When the entry may be evicted
+    pub alert: Option<i64>, // Last time the person alerted
+    pub violation: HashMap<EvidencePersonViolation, i64>, // Violation -> last alert timestamp
+    pub state: HashSet<EvidencePersonViolation>, // Violations seen on the last frame
+}
+
+impl Deduplication {
+    // Returns true when the person's violations should produce a new evidence
+    pub fn check(&mut self, camera: &Camera, timestamp: i64, person: &EvidencePerson) -> bool {
+        self.evict(timestamp);
+
+        let policy = &camera.deduplication;
+        let entry = self
+            .entry
+            .entry(format!("{}-{}", camera.id, person.id))
+            .or_default();
+        entry.timestamp = timestamp;
+        entry.expiry = timestamp + policy.lifetime();
+
+        let state = person
+            .violation
+            .iter()
+            .cloned()
+            .collect::<HashSet<EvidencePersonViolation>>();
+
+        let alert = !state.is_empty()
+            && match policy.kind {
+                CameraDeduplicationKind::Person => {
+                    entry.alert.is_none_or(|t| timestamp - t >= policy.cooldown)
+                }
+                CameraDeduplicationKind::Violation => state.iter().any(|v| {
+                    entry
+                        .violation
+                        .get(v)
+                        .is_none_or(|t| timestamp - t >= policy.cooldown(v))
+                }),
+                CameraDeduplicationKind::Change => state != entry.state,
+            };
+
+        if alert {
+            entry.alert = Some(timestamp);
+            for v in state.iter() {
+                entry.violation.insert(v.clone(), timestamp);
+            }
+        }
+        entry.state = state;
+
+        alert
+    }
+
+    fn evict(&mut self, timestamp: i64) {
+        if timestamp - self.timestamp < DEDUPLICATION_EVICTION_INTERVAL
+            && self.entry.len() < DEDUPLICATION_CAPACITY
+        {
+            return;
+        }
+        self.timestamp = timestamp;
+
+        self.entry.retain(|_, e| e.expiry > timestamp);
+
+        if self.entry.len() >= DEDUPLICATION_CAPACITY {
+            let mut entry = self
+                .entry
+                .iter()
+                .map(|(k, e)| (e.timestamp, k.clone()))
+                .collect::<Vec<(i64, String)>>();
+            entry.sort();
+            let count = self.entry.len() - DEDUPLICATION_CAPACITY * 3 / 4;
+            for (_, key) in entry.into_iter().take(count) {
+                self.entry.remove(&key);
+            }
+        }
+    }
+}
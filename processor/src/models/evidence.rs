@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -9,6 +11,9 @@ pub struct Evidence {
     pub frame_id: String,
     pub timestamp: i64,
     pub person: Vec<EvidencePerson>,
+    // JPEG of the frame the detections were made on, only kept in memory
+    #[serde(skip)]
+    pub image: Option<Arc<Vec<u8>>>,
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EvidencePerson {
@@ -18,6 +23,8 @@ pub struct EvidencePerson {
     pub part: Vec<EvidencePersonPart>,
     pub equipment: Vec<EvidencePersonEquipment>,
     pub violation: Vec<EvidencePersonViolation>,
+    #[serde(default)]
+    pub confirmation: Vec<EvidencePersonConfirmation>,
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EvidencePersonPart {
@@ -32,6 +39,13 @@ pub struct EvidencePersonEquipment {
     pub confidence: f32,
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvidencePersonConfirmation {
+    pub violation: EvidencePersonViolation,
+    pub frame: u32,     // Frames the violation was observed in
+    pub timestamp: i64, // When the violation was first observed
+    pub duration: i64,  // Milliseconds the violation persisted before it was confirmed
+}
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum EvidencePersonPartLabel {
     Head,
@@ -53,7 +67,7 @@ pub enum EvidencePersonEquipmentLabel {
     Earmuffs,
     Glasses,
 }
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum EvidencePersonViolation {
     MissingHardhat,
@@ -68,3 +82,8 @@ pub enum EvidencePersonViolation {
     ImproperlyWornFacemask,
     ImproperlyWornEarmuffs,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct EvidenceQuery {
+    pub annotated: Option<bool>,
+}
@@ -2,16 +2,24 @@ use std::collections::HashMap;
 
 use serde::Serialize;
 
-use crate::models::{camera::Camera, evidence::Evidence, processor::Processor};
+use crate::models::{
+    camera::Camera, evidence::Evidence, processor::Processor, retention::Retention,
+};
 
 pub mod camera;
+pub mod clip;
+pub mod confirmation;
+pub mod deduplication;
 pub mod evidence;
+pub mod outbox;
 pub mod processor;
+pub mod retention;
 
 #[derive(Clone, Serialize)]
 pub struct Device {
     pub processor: Processor,
     pub camera: HashMap<String, Camera>,
+    pub retention: Retention,
 }
 
 // Reading struct to hold the state of evidence per camera
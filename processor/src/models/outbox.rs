@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    fs::{read_dir, read_to_string, remove_file, rename, write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+
+// Delay before the first retry, doubled on every failed attempt
+const OUTBOX_BACKOFF_BASE: i64 = 5 * 1000;
+// Upper bound of the retry delay
+const OUTBOX_BACKOFF_MAXIMUM: i64 = 10 * 60 * 1000;
+// Failed attempts before an item is moved to the dead-letter state
+const OUTBOX_ATTEMPT_MAXIMUM: u32 = 10;
+
+// Journal of the evidence still to be uploaded. Delivered evidence leaves the journal and is
+// marked by an empty ./evidence/{id}.delivered file instead, so the journal stays small and can
+// be rebuilt from ./evidence.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Outbox {
+    pub item: HashMap<String, OutboxItem>, // evidence_id -> OutboxItem
+    #[serde(skip)]
+    version: u64,        // Bumped on every change
+    #[serde(skip)]
+    written: Arc<Mutex<u64>>, // Version last written, so a stale write never lands last
+}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutboxItem {
+    pub id: String,
+    pub status: OutboxStatus,
+    pub attempt: u32,
+    pub timestamp: i64,                 // When the item was enqueued
+    pub attempt_timestamp: Option<i64>, // When the last attempt was made
+    pub next_timestamp: i64,            // When the next attempt is due
+}
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    Pending,
+    Delivered, // Only in journals written before delivered items were dropped
+    Dead,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutboxQuery {
+    pub status: Option<OutboxStatus>,
+}
+
+impl OutboxItem {
+    pub fn new(id: String) -> Self {
+        let timestamp = Local::now().timestamp_millis();
+        Self {
+            id,
+            status: OutboxStatus::Pending,
+            attempt: 0,
+            timestamp,
+            attempt_timestamp: None,
+            next_timestamp: timestamp,
+        }
+    }
+}
+
+impl Outbox {
+    pub fn load() -> Self {
+        let mut outbox = match read_to_string("outbox.json") {
+            Ok(outbox) => match serde_json::from_str::<Self>(&outbox) {
+                Ok(v) => v,
+                Err(e) => {
+                    // Kept aside for inspection, the journal is rebuilt from ./evidence below
+                    println!(
+                        "[Outbox] outbox.json is corrupt, moved to outbox.json.corrupt: {}",
+                        e
+                    );
+                    let _ = rename("outbox.json", "outbox.json.corrupt");
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        };
+        let mut changed = false;
+
+        // Delivered items of older journals become markers
+        let delivered = outbox
+            .item
+            .values()
+            .filter(|i| i.status == OutboxStatus::Delivered)
+            .map(|i| i.id.clone())
+            .collect::<Vec<String>>();
+        for id in delivered.iter() {
+            let _ = write(Self::marker(id), "");
+            outbox.item.remove(id);
+            changed = true;
+        }
+
+        // Recover evidence that was written to disk but never journaled, e.g. pending uploads
+        // from before the outbox existed or a crash in between
+        if let Ok(entries) = read_dir("./evidence") {
+            for entry in entries.flatten() {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                if file_name.starts_with("uploaded.") {
+                    continue;
+                }
+                if let Some(id) = file_name.strip_suffix(".json")
+                    && !outbox.item.contains_key(id)
+                    && !Path::new(&Self::marker(id)).exists()
+                {
+                    outbox
+                        .item
+                        .insert(id.to_string(), OutboxItem::new(id.to_string()));
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            outbox.update();
+        }
+
+        outbox
+    }
+    pub fn update(&mut self) {
+        self.write(None);
+    }
+    // The journal is written on a blocking thread, after the delivered marker if there is one
+    fn write(&mut self, delivered: Option<String>) {
+        self.version += 1;
+        let (version, written) = (self.version, Arc::clone(&self.written));
+        let outbox = serde_json::to_string(self).unwrap();
+
+        spawn_blocking(move || {
+            if let Some(id) = delivered {
+                let _ = write(Self::marker(&id), "");
+            }
+
+            let mut written = written.lock().unwrap();
+            if *written >= version {
+                return;
+            }
+            // Write to a temporary file first so a power loss never leaves a truncated journal
+            if write("outbox.json.tmp", outbox).is_ok() {
+                let _ = rename("outbox.json.tmp", "outbox.json");
+                *written = version;
+            }
+        });
+    }
+    // Marks the evidence as uploaded, removed together with its other files
+    pub fn marker(id: &str) -> String {
+        format!("./evidence/{}.delivered", id)
+    }
+
+    pub fn insert(&mut self, id: &str) {
+        self.item
+            .insert(id.to_string(), OutboxItem::new(id.to_string()));
+        self.update();
+    }
+    // Items that are pending and whose backoff has elapsed, oldest first
+    pub fn due(&self) -> Vec<OutboxItem> {
+        let timestamp = Local::now().timestamp_millis();
+        let mut item = self
+            .item
+            .values()
+            .filter(|i| i.status == OutboxStatus::Pending && i.next_timestamp <= timestamp)
+            .cloned()
+            .collect::<Vec<OutboxItem>>();
+        item.sort_by_key(|i| i.timestamp);
+        item
+    }
+    pub fn deliver(&mut self, id: &str) {
+        if self.item.remove(id).is_some() {
+            self.write(Some(id.to_string()));
+        }
+    }
+    pub fn fail(&mut self, id: &str) {
+        if let Some(item) = self.item.get_mut(id) {
+            let timestamp = Local::now().timestamp_millis();
+            item.attempt += 1;
+            item.attempt_timestamp = Some(timestamp);
+
+            if item.attempt >= OUTBOX_ATTEMPT_MAXIMUM {
+                item.status = OutboxStatus::Dead;
+            } else {
+                let backoff = OUTBOX_BACKOFF_BASE
+                    .saturating_mul(1 << (item.attempt - 1).min(16))
+                    .min(OUTBOX_BACKOFF_MAXIMUM);
+                item.next_timestamp = timestamp + backoff;
+            }
+            self.update();
+        }
+    }
+    // Forget items whose evidence files were removed elsewhere
+    pub fn remove(&mut self, id: &[String]) {
+        let count = self.item.len();
+        for id in id.iter() {
+            self.item.remove(id);
+        }
+        if self.item.len() != count {
+            self.update();
+        }
+    }
+    // Move an item back to pending with a fresh attempt budget
+    pub fn retry(&mut self, id: &str) -> Option<OutboxItem> {
+        let item = self.item.get_mut(id)?;
+        item.status = OutboxStatus::Pending;
+        item.attempt = 0;
+        item.next_timestamp = Local::now().timestamp_millis();
+        let item = item.clone();
+        self.update();
+        Some(item)
+    }
+    // Remove an item from the journal together with its evidence files
+    pub fn discard(&mut self, id: &str) -> Option<OutboxItem> {
+        let item = self.item.remove(id)?;
+        let _ = remove_file(format!("./evidence/{}.json", id));
+        let _ = remove_file(format!("./evidence/{}.jpg", id));
+        let _ = remove_file(format!("./evidence/{}.annotated.jpg", id));
+        let _ = remove_file(format!("./evidence/{}.avi", id));
+        let _ = remove_file(Self::marker(id));
+        self.update();
+        Some(item)
+    }
+}
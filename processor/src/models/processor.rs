@@ -1,16 +1,18 @@
 use std::{
+    fmt,
     fs::{read_to_string, write},
     net::IpAddr,
-    os::macos::raw::stat,
 };
 
 use chrono::Local;
 use get_if_addrs::get_if_addrs;
+use hmac::{Hmac, Mac};
 use reqwest::{
-    Client,
+    Client, StatusCode,
     multipart::{Form, Part},
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -20,6 +22,8 @@ pub struct Processor {
     pub model: String,
     pub address: ProcessorAddress,
     pub webhook: Option<ProcessorWebhook>,
+    #[serde(default)]
+    pub retention: ProcessorRetention,
     pub version: i64,
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -28,11 +32,18 @@ pub struct ProcessorAddress {
     pub port: u16,
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProcessorRetention {
+    pub age: Option<i64>,  // Maximum evidence age in milliseconds
+    pub size: Option<u64>, // Maximum total evidence size in bytes
+}
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ProcessorWebhook {
     pub host: ProcessorWebhookHost,
     pub port: Option<u16>,
     pub secure: bool,
     pub path: ProcessorWebhookPath,
+    #[serde(default)]
+    pub secret: Option<String>, // Provisioned by the server, signs every request
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
@@ -40,11 +51,11 @@ pub enum ProcessorWebhookHost {
     Domain(String),
     IPv4([u8; 4]),
 }
-impl ProcessorWebhookHost {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for ProcessorWebhookHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ProcessorWebhookHost::Domain(domain) => domain.clone(),
-            ProcessorWebhookHost::IPv4(ip) => format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]),
+            ProcessorWebhookHost::Domain(domain) => write!(f, "{}", domain),
+            ProcessorWebhookHost::IPv4(ip) => write!(f, "{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]),
         }
     }
 }
@@ -53,6 +64,30 @@ pub struct ProcessorWebhookPath {
     pub evidence: String,
     pub update: String,
 }
+// One-time code issued by the server and where to redeem it
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProcessorPairing {
+    pub host: ProcessorWebhookHost,
+    pub port: Option<u16>,
+    pub secure: bool,
+    #[serde(default)]
+    pub path: String, // Base path of the server API, e.g. "/api"
+    pub code: String,
+}
+#[derive(Debug, Deserialize)]
+struct ProcessorPairingCredential {
+    secret: String,
+    path: ProcessorWebhookPath,
+}
+
+impl Default for ProcessorRetention {
+    fn default() -> Self {
+        Self {
+            age: Some(30 * 24 * 60 * 60 * 1000),
+            size: Some(4 * 1024 * 1024 * 1024),
+        }
+    }
+}
 
 impl Processor {
     pub fn load() -> Self {
@@ -65,11 +100,10 @@ impl Processor {
                 if let Ok(interfaces) = get_if_addrs() {
                     for iface in interfaces {
                         let ip = iface.ip();
-                        if !ip.is_loopback() {
-                            match ip {
-                                IpAddr::V4(ip) => host = ip.octets(),
-                                _ => (),
-                            }
+                        if let IpAddr::V4(ip) = ip
+                            && !ip.is_loopback()
+                        {
+                            host = ip.octets();
                         }
                     }
                 }
@@ -80,6 +114,7 @@ impl Processor {
                     model: "yolov8n.hef".to_string(),
                     address: ProcessorAddress { host, port: 8000 },
                     webhook: None,
+                    retention: ProcessorRetention::default(),
                     version: Local::now().timestamp_millis(),
                 };
 
@@ -100,13 +135,99 @@ impl Processor {
     }
 }
 
+impl ProcessorPairing {
+    // Exchange the code for the webhook configuration of the cluster it was issued for
+    pub async fn redeem(&self, processor: &Processor) -> Result<ProcessorWebhook, String> {
+        let mut url = format!(
+            "{}://{}",
+            if self.secure { "https" } else { "http" },
+            self.host
+        );
+
+        if let Some(port) = self.port {
+            url = format!("{}:{}", url, port);
+        }
+
+        let base = self.path.trim_matches('/');
+        let address = if base.is_empty() {
+            format!("{}/enrollments", url)
+        } else {
+            format!("{}/{}/enrollments", url, base)
+        };
+
+        let payload = serde_json::json!({
+            "code": self.code.trim(),
+            "processor": {
+                "id": processor.id,
+                "name": processor.name,
+                "model": processor.model,
+                "address": processor.address,
+                "version": processor.version,
+            },
+        });
+
+        let text = payload.to_string();
+
+        // A processor that is already paired proves it with its current secret to pair again
+        let signature = match &processor.webhook {
+            Some(webhook) => webhook.sign(&hex::encode(Sha256::digest(text.as_bytes()))),
+            None => Vec::new(),
+        };
+
+        let client = Client::new();
+        let mut request = client
+            .post(&address)
+            .header("Content-Type", "application/json")
+            .body(text);
+        for (name, value) in signature {
+            request = request.header(name, value);
+        }
+
+        let response = match request.send().await {
+            Ok(v) => v,
+            Err(e) => return Err(format!("SERVER_UNREACHABLE: {}", e)),
+        };
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(String::from("INVALID_CODE"));
+        }
+        if response.status() == StatusCode::CONFLICT {
+            return Err(String::from("ENROLLED_IN_ANOTHER_CLUSTER"));
+        }
+        if !response.status().is_success() {
+            return Err(format!("PAIRING_FAILED: {}", response.status()));
+        }
+
+        let credential = response
+            .text()
+            .await
+            .ok()
+            .and_then(|v| serde_json::from_str::<ProcessorPairingCredential>(&v).ok())
+            .ok_or_else(|| String::from("INVALID_RESPONSE"))?;
+
+        Ok(ProcessorWebhook {
+            host: self.host.clone(),
+            port: self.port,
+            secure: self.secure,
+            path: credential.path,
+            secret: Some(credential.secret),
+        })
+    }
+}
+
 impl ProcessorWebhook {
     // Send multipart/form-data with text and file
-    pub async fn send_evidence(&self, text: String, file: Vec<u8>, evidence_id: &String) -> bool {
+    pub async fn send_evidence(
+        &self,
+        text: String,
+        file: Vec<u8>,
+        annotated: Option<Vec<u8>>,
+        clip: Option<Vec<u8>>,
+        evidence_id: &String,
+    ) -> bool {
         let mut url = format!(
             "{}://{}",
             if self.secure { "https" } else { "http" },
-            self.host.to_string()
+            self.host
         );
 
         if let Some(port) = self.port {
@@ -115,21 +236,53 @@ impl ProcessorWebhook {
 
         let address = format!("{}/{}", url, self.path.evidence.trim_start_matches('/'));
 
+        // Digest of the parts in the order they are sent
+        let mut digest = String::new();
+        for (name, data) in [
+            ("data", Some(text.as_bytes())),
+            ("image", Some(file.as_slice())),
+            ("annotated", annotated.as_deref()),
+            ("clip", clip.as_deref()),
+        ] {
+            if let Some(data) = data {
+                digest.push_str(&format!("{}:{}\n", name, hex::encode(Sha256::digest(data))));
+            }
+        }
+        let signature = self.sign(&hex::encode(Sha256::digest(digest.as_bytes())));
+
         let file = Part::bytes(file).file_name(format!("{}.jpg", evidence_id));
 
         println!("[WEBHOOK] Sending evidence to {}", address);
 
         let client = Client::new();
-        let form = Form::new().text("data", text).part("image", file);
+        let mut form = Form::new().text("data", text).part("image", file);
+        // The annotated image is optional, servers that do not know the part ignore it
+        if let Some(annotated) = annotated {
+            let annotated =
+                Part::bytes(annotated).file_name(format!("{}.annotated.jpg", evidence_id));
+            form = form.part("annotated", annotated);
+        }
+        if let Some(clip) = clip {
+            let clip = Part::bytes(clip)
+                .file_name(format!("{}.avi", evidence_id))
+                .mime_str("video/x-msvideo")
+                .unwrap();
+            form = form.part("clip", clip);
+        }
+
+        let mut request = client.post(&address).multipart(form);
+        for (name, value) in signature {
+            request = request.header(name, value);
+        }
 
-        match client.post(&address).multipart(form).send().await {
+        match request.send().await {
             Ok(response) => {
                 let status = response.status();
                 println!(
                     "Webhook response status: {}",
                     response.text().await.unwrap_or_default()
                 );
-                if status.is_success() { true } else { false }
+                status.is_success()
             }
             Err(e) => {
                 println!("Failed to send evidence to webhook: {:?}", e);
@@ -138,12 +291,13 @@ impl ProcessorWebhook {
         }
     }
 
-    // Send processor's information to the webhook
+    // Send processor's information to the webhook. The secret is provisioned by pairing or
+    // rotated by an admin, the server never hands it out here.
     pub async fn send_update(&self, text: String) -> bool {
         let mut url = format!(
             "{}://{}",
             if self.secure { "https" } else { "http" },
-            self.host.to_string()
+            self.host
         );
 
         if let Some(port) = self.port {
@@ -152,21 +306,49 @@ impl ProcessorWebhook {
 
         let address = format!("{}/{}", url, self.path.update.trim_start_matches('/'));
 
+        let signature = self.sign(&hex::encode(Sha256::digest(text.as_bytes())));
+
         let client = Client::new();
-        match client
+        let mut request = client
             .post(&address)
             .header("Content-Type", "application/json")
-            .body(text)
-            .send()
-            .await
-        {
+            .body(text);
+        for (name, value) in signature {
+            request = request.header(name, value);
+        }
+
+        match request.send().await {
             Ok(response) => {
                 let status = response.status();
-                if status.is_success() { true } else { false }
-            }
-            Err(_) => {
-                return false;
+                if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+                    println!(
+                        "[WEBHOOK] Update rejected, the processor secret is missing or invalid"
+                    );
+                }
+                status.is_success()
             }
+            Err(_) => false,
         }
     }
+
+    // Signature headers over the request digest, empty until a secret is provisioned
+    fn sign(&self, digest: &str) -> Vec<(&'static str, String)> {
+        let secret = match &self.secret {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        let timestamp = Local::now().timestamp_millis();
+
+        // HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.{}", timestamp, digest).as_bytes());
+
+        vec![
+            ("X-Gidence-Timestamp", timestamp.to_string()),
+            (
+                "X-Gidence-Signature",
+                hex::encode(mac.finalize().into_bytes()),
+            ),
+        ]
+    }
 }
@@ -0,0 +1,141 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{read_dir, remove_file},
+    path::PathBuf,
+    time::UNIX_EPOCH,
+};
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::models::processor::ProcessorRetention;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Retention {
+    pub size: u64,       // Total bytes used by ./evidence
+    pub count: usize,    // Number of evidences stored
+    pub uploaded: usize, // Number of evidences already delivered
+    pub deleted: usize,  // Number of evidences deleted since startup
+    pub dropped: usize,  // Of those, evidences deleted before they were uploaded
+    pub timestamp: i64,  // When the usage was last measured
+}
+struct RetentionEntry {
+    id: String,
+    file: Vec<PathBuf>,
+    size: u64,
+    timestamp: i64,
+    uploaded: bool,
+    pending: bool, // Still waiting in the outbox
+}
+
+impl Retention {
+    // Apply the retention policy to ./evidence and return the ids that were deleted
+    pub fn enforce(
+        &mut self,
+        policy: &ProcessorRetention,
+        pending: &HashSet<String>,
+    ) -> Vec<String> {
+        let mut entry = Self::scan(pending);
+        let mut deleted = Vec::new();
+        let mut dropped = 0;
+
+        // Age limit spares evidence still waiting in the outbox, so an outage does not lose it
+        if let Some(age) = policy.age {
+            let timestamp = Local::now().timestamp_millis() - age;
+            entry.retain(|e| {
+                if e.timestamp < timestamp && !e.pending {
+                    Self::delete(e);
+                    deleted.push(e.id.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        // Byte budget evicts uploaded evidence first, then pending evidence, oldest first
+        if let Some(size) = policy.size {
+            let mut total = entry.iter().map(|e| e.size).sum::<u64>();
+            if total > size {
+                entry.sort_by_key(|e| (!e.uploaded, e.timestamp));
+                let mut count = 0;
+                for e in entry.iter() {
+                    if total <= size {
+                        break;
+                    }
+                    Self::delete(e);
+                    deleted.push(e.id.clone());
+                    if !e.uploaded {
+                        dropped += 1;
+                    }
+                    total -= e.size;
+                    count += 1;
+                }
+                entry.drain(..count);
+            }
+        }
+
+        self.size = entry.iter().map(|e| e.size).sum();
+        self.count = entry.len();
+        self.uploaded = entry.iter().filter(|e| e.uploaded).count();
+        self.deleted += deleted.len();
+        self.dropped += dropped;
+        self.timestamp = Local::now().timestamp_millis();
+
+        deleted
+    }
+
+    // Group the files in ./evidence by evidence id
+    fn scan(pending: &HashSet<String>) -> Vec<RetentionEntry> {
+        let mut entry = HashMap::<String, RetentionEntry>::new();
+
+        let dir = match read_dir("./evidence") {
+            Ok(dir) => dir,
+            Err(_) => return Vec::new(),
+        };
+
+        for file in dir.flatten() {
+            let metadata = match file.metadata() {
+                Ok(v) if v.is_file() => v,
+                _ => continue,
+            };
+            let file_name = file.file_name().to_string_lossy().to_string();
+
+            // Files marked by the legacy rename scheme are uploaded
+            let (name, legacy) = match file_name.strip_prefix("uploaded.") {
+                Some(name) => (name.to_string(), true),
+                None => (file_name, false),
+            };
+            let (id, delivered) = match name.split_once('.') {
+                Some((id, extension)) => (id.to_string(), extension == "delivered"),
+                None => continue,
+            };
+            let timestamp = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or_default();
+
+            let e = entry.entry(id.clone()).or_insert_with(|| RetentionEntry {
+                uploaded: false,
+                pending: pending.contains(&id),
+                id,
+                file: Vec::new(),
+                size: 0,
+                timestamp,
+            });
+            e.file.push(file.path());
+            e.size += metadata.len();
+            e.timestamp = e.timestamp.min(timestamp);
+            e.uploaded |= legacy || delivered;
+        }
+
+        entry.into_values().collect()
+    }
+    fn delete(entry: &RetentionEntry) {
+        for file in entry.file.iter() {
+            let _ = remove_file(file);
+        }
+    }
+}
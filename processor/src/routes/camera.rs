@@ -4,7 +4,11 @@ use actix_web::{HttpResponse, delete, get, post, put, web};
 use tokio::{fs, sync::RwLock};
 use uuid::Uuid;
 
-use crate::models::{Device, camera::Camera, evidence::Evidence};
+use crate::models::{
+    Device,
+    camera::{Camera, CameraRequest},
+    evidence::Evidence,
+};
 
 #[post("")]
 pub async fn create_camera(
@@ -15,7 +19,7 @@ pub async fn create_camera(
     new_camera.id = Uuid::new_v4().to_string();
 
     let mut device = device.write().await;
-    if device.camera.get(&new_camera.id).is_some() {
+    if device.camera.contains_key(&new_camera.id) {
         drop(device);
         return HttpResponse::Conflict().finish();
     }
@@ -31,22 +35,16 @@ pub async fn create_camera(
 
 #[put("")]
 pub async fn update_camera(
-    payload: web::Json<Camera>,
+    payload: web::Json<CameraRequest>,
     device: web::Data<Arc<RwLock<Device>>>,
 ) -> HttpResponse {
-    let new_camera = payload.into_inner();
+    let request = payload.into_inner();
 
     let mut device = device.write().await;
-    if let Some(camera) = device.camera.get_mut(&new_camera.id) {
-        *camera = new_camera.clone();
-        Camera::insert_many(
-            &device
-                .camera
-                .values()
-                .cloned()
-                .into_iter()
-                .collect::<Vec<Camera>>(),
-        );
+    if let Some(camera) = device.camera.get_mut(&request.id) {
+        camera.apply(request);
+        let new_camera = camera.clone();
+        Camera::insert_many(&device.camera.values().cloned().collect::<Vec<Camera>>());
         device.processor.update_version(); // Update processor version on change
         HttpResponse::Ok().json(new_camera)
     } else {
@@ -107,14 +105,7 @@ pub async fn delete_camera(
 
     let mut device = device.write().await;
     if device.camera.remove(&camera_id).is_some() {
-        Camera::insert_many(
-            &device
-                .camera
-                .values()
-                .cloned()
-                .into_iter()
-                .collect::<Vec<Camera>>(),
-        );
+        Camera::insert_many(&device.camera.values().cloned().collect::<Vec<Camera>>());
         device.processor.update_version(); // Update processor version on change
         HttpResponse::NoContent().finish()
     } else {
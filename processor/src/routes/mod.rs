@@ -4,9 +4,16 @@ use actix_files::NamedFile;
 use actix_web::{HttpRequest, HttpResponse, get, web};
 use tokio::sync::RwLock;
 
-use crate::models::{Device, Reading};
+use crate::{
+    annotation,
+    models::{
+        Device, Reading,
+        evidence::{Evidence, EvidenceQuery},
+    },
+};
 
 pub mod camera;
+pub mod outbox;
 pub mod processor;
 
 #[get("/reading")]
@@ -23,35 +30,94 @@ pub async fn get_device(device: web::Data<Arc<RwLock<Device>>>) -> HttpResponse
 
     let processor = device.processor;
     let camera = device.camera.values().collect::<Vec<_>>();
+    let retention = device.retention;
     // FORM A JSON OBJECT
     let device_json = serde_json::json!({
         "processor": processor,
         "camera": camera,
+        "retention": retention,
     });
     HttpResponse::Ok().json(device_json)
 }
 #[get("/frame/{camera_id}")]
-pub async fn get_frame(camera_id: web::Path<String>, req: HttpRequest) -> HttpResponse {
+pub async fn get_frame(
+    camera_id: web::Path<String>,
+    reading: web::Data<Arc<RwLock<Reading>>>,
+    req: HttpRequest,
+) -> HttpResponse {
     let camera_id = camera_id.into_inner();
 
-    NamedFile::open(&format!("/tmp/{}.jpg", camera_id))
+    // Latest frame received over UDS, matching the detections in the reading
+    let image = {
+        let reading = reading.read().await;
+        reading
+            .camera
+            .get(&camera_id)
+            .and_then(|(evidence, _, _)| evidence.as_ref())
+            .and_then(|evidence| evidence.image.clone())
+    };
+    if let Some(image) = image {
+        return HttpResponse::Ok()
+            .content_type("image/jpeg")
+            .body(image.to_vec());
+    }
+
+    NamedFile::open(format!("/tmp/{}.jpg", camera_id))
         .map(|file| file.into_response(&req))
         .unwrap_or_else(|_| HttpResponse::NotFound().finish())
 }
 
 #[get("/evidence/{evidence_id}")]
-pub async fn get_evidence(evidence_id: web::Path<String>, req: HttpRequest) -> HttpResponse {
+pub async fn get_evidence(
+    evidence_id: web::Path<String>,
+    query: web::Query<EvidenceQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
     let evidence_id = evidence_id.into_inner();
 
-    match NamedFile::open(&format!("./evidence/{}.jpg", evidence_id)) {
+    if query.annotated.unwrap_or(false) {
+        if let Ok(file) = NamedFile::open(format!("./evidence/{}.annotated.jpg", evidence_id)) {
+            return file.into_response(&req);
+        }
+
+        // Evidence stored before annotation existed is rendered on demand
+        let (evidence, image) = match (
+            tokio::fs::read(format!("./evidence/{}.json", evidence_id)).await,
+            tokio::fs::read(format!("./evidence/{}.jpg", evidence_id)).await,
+        ) {
+            (Ok(evidence), Ok(image)) => match serde_json::from_slice::<Evidence>(&evidence) {
+                Ok(evidence) => (evidence, image),
+                Err(_) => return HttpResponse::NotFound().finish(),
+            },
+            _ => return HttpResponse::NotFound().finish(),
+        };
+        return match web::block(move || annotation::annotate(&evidence, &image)).await {
+            Ok(Some(annotated)) => HttpResponse::Ok()
+                .content_type("image/jpeg")
+                .body(annotated),
+            _ => HttpResponse::NotFound().finish(),
+        };
+    }
+
+    match NamedFile::open(format!("./evidence/{}.jpg", evidence_id)) {
         Ok(file) => file.into_response(&req),
-        Err(_) => match NamedFile::open(&format!("./evidence/uploaded.{}.jpg", evidence_id)) {
+        Err(_) => match NamedFile::open(format!("./evidence/uploaded.{}.jpg", evidence_id)) {
             Ok(file) => file.into_response(&req),
             Err(_) => HttpResponse::NotFound().finish(),
         },
     }
 }
 
+#[get("/evidence/{evidence_id}/clip")]
+pub async fn get_evidence_clip(evidence_id: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    let evidence_id = evidence_id.into_inner();
+
+    match NamedFile::open(format!("./evidence/{}.avi", evidence_id)) {
+        Ok(file) => file.into_response(&req),
+        Err(_) => HttpResponse::NotFound().finish(),
+    }
+}
+
 #[get("/ping")]
 pub async fn ping() -> HttpResponse {
     HttpResponse::Ok().body("pong")
@@ -63,10 +129,18 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
         .service(get_device)
         .service(get_frame)
         .service(get_evidence)
+        .service(get_evidence_clip)
         .service(
             web::scope("/processor")
                 .service(processor::get_processor)
-                .service(processor::update_processor),
+                .service(processor::update_processor)
+                .service(processor::pair_processor),
+        )
+        .service(
+            web::scope("/outbox")
+                .service(outbox::get_outbox)
+                .service(outbox::retry_outbox_item)
+                .service(outbox::delete_outbox_item),
         )
         .service(
             web::scope("/camera")
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use actix_web::{HttpResponse, delete, get, post, web};
+use tokio::sync::RwLock;
+
+use crate::models::outbox::{Outbox, OutboxItem, OutboxQuery};
+
+#[get("")]
+pub async fn get_outbox(
+    query: web::Query<OutboxQuery>,
+    outbox: web::Data<Arc<RwLock<Outbox>>>,
+) -> HttpResponse {
+    let mut item = {
+        let outbox = outbox.read().await;
+        outbox
+            .item
+            .values()
+            .filter(|i| query.status.as_ref().is_none_or(|s| i.status == *s))
+            .cloned()
+            .collect::<Vec<OutboxItem>>()
+    };
+    item.sort_by_key(|i| i.timestamp);
+
+    HttpResponse::Ok().json(item)
+}
+
+#[post("/{evidence_id}/retry")]
+pub async fn retry_outbox_item(
+    evidence_id: web::Path<String>,
+    outbox: web::Data<Arc<RwLock<Outbox>>>,
+) -> HttpResponse {
+    let evidence_id = evidence_id.into_inner();
+
+    let mut outbox = outbox.write().await;
+    match outbox.retry(&evidence_id) {
+        Some(item) => HttpResponse::Ok().json(item),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+// Drops the item from the outbox and deletes its evidence files
+#[delete("/{evidence_id}")]
+pub async fn delete_outbox_item(
+    evidence_id: web::Path<String>,
+    outbox: web::Data<Arc<RwLock<Outbox>>>,
+) -> HttpResponse {
+    let evidence_id = evidence_id.into_inner();
+
+    let mut outbox = outbox.write().await;
+    match outbox.discard(&evidence_id) {
+        Some(_) => HttpResponse::NoContent().finish(),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
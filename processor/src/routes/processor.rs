@@ -1,21 +1,34 @@
 use std::sync::Arc;
 
-use actix_web::{HttpResponse, get, put, web};
+use actix_web::{HttpResponse, get, post, put, web};
 use tokio::sync::RwLock;
 
-use crate::models::{Device, processor::Processor};
+use crate::models::{
+    Device,
+    processor::{Processor, ProcessorPairing},
+};
 
 #[put("")]
 pub async fn update_processor(
     payload: web::Json<Processor>,
     device: web::Data<Arc<RwLock<Device>>>,
 ) -> HttpResponse {
-    let new_processor = payload.into_inner();
+    let mut new_processor = payload.into_inner();
+
+    let mut device = device.write().await;
+
+    // Keep the provisioned secret when the webhook is edited without it
+    if let (Some(webhook), Some(current)) = (
+        new_processor.webhook.as_mut(),
+        device.processor.webhook.as_ref(),
+    ) && webhook.secret.is_none()
+    {
+        webhook.secret = current.secret.clone();
+    }
 
     new_processor.update();
 
-    let mut device = device.write().await;
-    (*device).processor = new_processor.clone();
+    device.processor = new_processor.clone();
     device.processor.update_version(); // Update processor version on change
 
     drop(device);
@@ -30,3 +43,29 @@ pub async fn get_processor(device: web::Data<Arc<RwLock<Device>>>) -> HttpRespon
     drop(device);
     HttpResponse::Ok().json(processor)
 }
+
+// Join a cluster with the one-time code from the server, replaces the webhook configuration
+#[post("/pair")]
+pub async fn pair_processor(
+    payload: web::Json<ProcessorPairing>,
+    device: web::Data<Arc<RwLock<Device>>>,
+) -> HttpResponse {
+    let processor = {
+        let device = device.read().await;
+        device.processor.clone()
+    };
+
+    let webhook = match payload.redeem(&processor).await {
+        Ok(v) => v,
+        Err(e) => {
+            println!("[PAIRING] {}", e);
+            return HttpResponse::BadRequest().body(e);
+        }
+    };
+
+    let mut device = device.write().await;
+    device.processor.webhook = Some(webhook);
+    device.processor.update();
+
+    HttpResponse::Ok().json(device.processor.clone())
+}
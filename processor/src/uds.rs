@@ -0,0 +1,162 @@
+use std::{
+    io::{self, ErrorKind},
+    sync::Arc,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use crate::models::evidence::Evidence;
+
+// Streaming protocol between the Inference Engine and the Main Runtime
+//
+// Handshake (client -> runtime): "GSCM" | version: u8 | format: u8
+// Handshake (runtime -> client): "GSCM" | version: u8 (0 when rejected)
+// Frame (client -> runtime):     length: u32 big-endian | payload
+//
+// Version 1 payload: evidence
+// Version 2 payload: evidence length: u32 big-endian | evidence | JPEG of the frame
+//
+// A connection that does not start with the magic bytes is treated as the
+// legacy one-shot mode: a single JSON evidence followed by EOF.
+pub const UDS_MAGIC: &[u8; 4] = b"GSCM";
+pub const UDS_VERSION: u8 = 2;
+// Largest frame accepted, big enough for an evidence with its JPEG
+const UDS_FRAME_MAXIMUM: usize = 16 * 1024 * 1024;
+// Evidence waiting in the queue before the runtime stops reading from the socket
+pub const UDS_QUEUE_MAXIMUM: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UdsFormat {
+    Json,
+    MessagePack,
+}
+
+pub struct UdsConnection {
+    stream: UnixStream,
+    format: UdsFormat,
+    version: u8,
+    legacy: Option<Vec<u8>>, // Pending one-shot message, None when streaming
+}
+
+impl UdsFormat {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(UdsFormat::Json),
+            1 => Some(UdsFormat::MessagePack),
+            _ => None,
+        }
+    }
+}
+
+impl UdsConnection {
+    // Detect the protocol and complete the handshake
+    pub async fn accept(mut stream: UnixStream) -> io::Result<Self> {
+        let mut magic = Vec::with_capacity(UDS_MAGIC.len());
+        while magic.len() < UDS_MAGIC.len() {
+            let mut byte = [0u8; 1];
+            if stream.read(&mut byte).await? == 0 {
+                break;
+            }
+            magic.push(byte[0]);
+            if magic[..] != UDS_MAGIC[..magic.len()] {
+                break;
+            }
+        }
+
+        if magic[..] != UDS_MAGIC[..] {
+            // Legacy one-shot mode, the bytes read so far belong to the JSON message
+            stream.read_to_end(&mut magic).await?;
+            return Ok(Self {
+                stream,
+                format: UdsFormat::Json,
+                version: 0,
+                legacy: Some(magic),
+            });
+        }
+
+        let version = stream.read_u8().await?;
+        let format = stream.read_u8().await?;
+
+        let format = match UdsFormat::from_u8(format) {
+            Some(v) if version >= 1 => v,
+            _ => {
+                stream.write_all(&[&UDS_MAGIC[..], &[0]].concat()).await?;
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unsupported version {} or format {}", version, format),
+                ));
+            }
+        };
+
+        let version = version.min(UDS_VERSION);
+        stream
+            .write_all(&[&UDS_MAGIC[..], &[version]].concat())
+            .await?;
+
+        Ok(Self {
+            stream,
+            format,
+            version,
+            legacy: None,
+        })
+    }
+
+    // Read the next frame payload, None once the client has disconnected
+    pub async fn next(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.legacy.is_some() {
+            return Ok(self.legacy.take().filter(|b| !b.is_empty()));
+        }
+
+        let length = match self.stream.read_u32().await {
+            Ok(v) => v as usize,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if length > UDS_FRAME_MAXIMUM {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("frame of {} bytes exceeds the limit", length),
+            ));
+        }
+
+        let mut payload = vec![0u8; length];
+        self.stream.read_exact(&mut payload).await?;
+        Ok(Some(payload))
+    }
+
+    pub fn decode(&self, payload: &[u8]) -> Result<Evidence, String> {
+        // From version 2 the frame's JPEG travels in the same payload as its detections
+        let (payload, image) = if self.version >= 2 {
+            if payload.len() < 4 {
+                return Err(String::from("missing evidence length"));
+            }
+            let length = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            let length = length as usize;
+            if payload.len() < 4 + length {
+                return Err(format!("evidence length {} exceeds the frame", length));
+            }
+            let image = &payload[4 + length..];
+            (
+                &payload[4..4 + length],
+                (!image.is_empty()).then(|| Arc::new(image.to_vec())),
+            )
+        } else {
+            (payload, None)
+        };
+
+        let mut evidence = match self.format {
+            UdsFormat::Json => {
+                serde_json::from_slice::<Evidence>(payload).map_err(|e| e.to_string())
+            }
+            UdsFormat::MessagePack => {
+                rmp_serde::from_slice::<Evidence>(payload).map_err(|e| e.to_string())
+            }
+        }?;
+        evidence.image = image;
+
+        Ok(evidence)
+    }
+}
@@ -8,7 +8,7 @@
 import SwiftUI
 
 @Observable class EvidenceManager {
-    func getMany(_ network: Network, cluster_id: String? = nil, processor_id: String? = nil, camera_id: String? = nil, date_minimum: Int? = nil, date_maximum: Int? = nil, f: @escaping (([ViewEvidence]?, Error?) -> Void)) -> Void {
+    func getMany(_ network: Network, cluster_id: String? = nil, processor_id: String? = nil, camera_id: String? = nil, date_minimum: Int? = nil, date_maximum: Int? = nil, limit: Int? = nil, cursor: String? = nil, f: @escaping ((ViewPage<ViewEvidence>?, Error?) -> Void)) -> Void {
         var path = "/evidences?"
         
         if let cluster_id {
@@ -26,8 +26,14 @@ import SwiftUI
         if let date_maximum {
             path += "date_maximum=\(date_maximum)&"
         }
+        if let limit {
+            path += "limit=\(limit)&"
+        }
+        if let cursor {
+            path += "cursor=\(cursor)&"
+        }
         
-        network.req(path, method: .get, f: f) 
+        network.req(path, method: .get, f: f)
     }
     func get(_ network: Network, _ id: String, f: @escaping ((ViewEvidence?, Error?) -> Void)) -> Void {
         var path = "/evidences/\(id)"
@@ -39,7 +39,7 @@ import NIO
         
         network.req(path, method: .get, f: f)
     }
-    func getMany(_ network: Network, cluster_id: String? = nil, date_minimum: Int? = nil, date_maximum: Int? = nil, limit: Int? = nil, skip: Int? = nil, f: @escaping (([ViewProcessor]?, Error?) -> Void)) -> Void {
+    func getMany(_ network: Network, cluster_id: String? = nil, date_minimum: Int? = nil, date_maximum: Int? = nil, limit: Int? = nil, cursor: String? = nil, f: @escaping ((ViewPage<ViewProcessor>?, Error?) -> Void)) -> Void {
         var path = "/processors?"
         
         if let cluster_id {
@@ -54,8 +54,8 @@ import NIO
         if let limit {
             path += "limit=\(limit)&"
         }
-        if let skip {
-            path += "skip=\(skip)&"
+        if let cursor {
+            path += "cursor=\(cursor)&"
         }
         
         network.req(path, method: .get, f: f)
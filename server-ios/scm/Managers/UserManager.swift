@@ -66,7 +66,7 @@ import SwiftUI
         network.req(path, method: .get, f: f)
     }
     
-    func getMany(_ network: Network, text: String? = nil, cluster_id: String? = nil, cluster_eid: String? = nil, limit: Int? = nil, skip: Int? = nil, f: @escaping (([ViewUser]?, Error?) -> Void)) -> Void {
+    func getMany(_ network: Network, text: String? = nil, cluster_id: String? = nil, cluster_eid: String? = nil, limit: Int? = nil, cursor: String? = nil, f: @escaping ((ViewPage<ViewUser>?, Error?) -> Void)) -> Void {
         var path = "/users?"
         
         if let text {
@@ -81,8 +81,8 @@ import SwiftUI
         if let limit {
             path += "limit=\(limit)&"
         }
-        if let skip {
-            path += "skip=\(skip)&"
+        if let cursor {
+            path += "cursor=\(cursor)&"
         }
         
         network.req(path, method: .get, f: f)
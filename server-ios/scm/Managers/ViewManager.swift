@@ -27,10 +27,12 @@ import SwiftUI
 
 struct EvidenceListData {
     var evidences: [ViewEvidence] = []
+    var cursor: String? // Of the next page, nil once the last one is loaded
 }
 struct ClusterListData {
     var clusters: [ViewCluster] = []
 }
 struct UserListData {
     var users: [ViewUser] = []
+    var cursor: String? // Of the next page, nil once the last one is loaded
 }
@@ -0,0 +1,11 @@
+//
+//  Page.swift
+//  scm
+//
+
+// Listings are paginated, pass `cursor` back to get the next page
+struct ViewPage<T: Decodable>: Decodable {
+    var data: [T]
+    var total: Int
+    var cursor: String?
+}
@@ -43,7 +43,12 @@ class Network {
     
     func load(_ name: String, f: @escaping (Data?, URLResponse?, Error?) -> Void) -> Void {
         if let url = URL(string: "http\(API_BASE)/static/\(name)") {
-            let request = URLRequest(url: url)
+            var request = URLRequest(url: url)
+            
+            // Evidence files are only served to users of the evidence's cluster
+            if let authentication = self.authentication {
+                request.addValue("Bearer \(authentication.atk)", forHTTPHeaderField: "Authorization")
+            }
             
             if let cachedResponse = URLCache.shared.cachedResponse(for: request) {
                 f(cachedResponse.data, cachedResponse.response, nil)
@@ -147,7 +152,10 @@ class Network {
     
     func connect() {
         guard let url = URL(string: "ws\(API_BASE)/ws") else { return }
-        let request = URLRequest(url: url)
+        var request = URLRequest(url: url)
+        if let authentication = self.authentication {
+            request.addValue("Bearer \(authentication.atk)", forHTTPHeaderField: "Authorization")
+        }
         ws = URLSession.shared.webSocketTask(with: request)
         ws?.resume()
         
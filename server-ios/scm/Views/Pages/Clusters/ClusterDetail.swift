@@ -19,6 +19,11 @@ struct ClusterDetail: View {
     @State private var processors: [ViewProcessor] = []
     @State private var evidences: [ViewEvidence] = []
     @State private var users: [ViewUser] = []
+    @State private var userTotal = 0
+
+    // Of the next page of each listing, nil once the last one is loaded
+    @State private var evidenceCursor: String?
+    @State private var processorCursor: String?
     
     @State private var granularity = InputOption(key: "Hari ini", value: Date.now.currentTimeMillis())
     @State private var granularities = [
@@ -67,6 +72,11 @@ struct ClusterDetail: View {
                                         ClusterEvidenceCard(evidence) {
                                             self.app.path.append(.EvidenceDetail(evidence))
                                         }
+                                        .onAppear {
+                                            if evidence == self.evidences.last {
+                                                self.loadEvidences()
+                                            }
+                                        }
                                     }
                                 }
                                 .scrollTargetLayout()
@@ -140,6 +150,11 @@ struct ClusterDetail: View {
                                     ForEach(self.processors, id: \.self) { processor in
                                         ProcessorCard(processor, true, depth: .two) { _ in
                                         }
+                                        .onAppear {
+                                            if processor == self.processors.last {
+                                                self.loadProcessors()
+                                            }
+                                        }
                                     }
                                 }
                             }
@@ -150,7 +165,7 @@ struct ClusterDetail: View {
                             header: {
                                 VStack(alignment: .leading, spacing: 3) {
                                     TextHeadlineFour("Daftar Petugas")
-                                    TextBodyFour("\(self.users.count) petugas terdaftar", color: .fontSecondary)
+                                    TextBodyFour("\(self.userTotal) petugas terdaftar", color: .fontSecondary)
                                 }
                                 Spacer()
                             },
@@ -196,28 +211,56 @@ struct ClusterDetail: View {
             if let cluster {
                 self.cluster = cluster
             }
-            self.evidenceManager.getMany(self.network, cluster_id: self.cluster.id, date_minimum: self.date_minimum, date_maximum: self.date_maximum) { evidences, _ in
-                if let evidences {
-                    self.evidences = evidences
-                } else {
-                    self.evidences = []
-                }
-                self.processorManager.getMany(self.network, cluster_id: self.cluster.id, date_minimum: self.date_minimum) { processors, _ in
-                    if let processors {
-                        self.processors = processors
-                    } else {
-                        self.processors = []
-                    }
-                    self.userManager.getMany(self.network, cluster_id: self.cluster.id, limit: 3) { users, _ in
+            self.evidenceManager.getMany(self.network, cluster_id: self.cluster.id, date_minimum: self.date_minimum, date_maximum: self.date_maximum) { page, _ in
+                self.evidences = page?.data ?? []
+                self.evidenceCursor = page?.cursor
+                self.processorManager.getMany(self.network, cluster_id: self.cluster.id, date_minimum: self.date_minimum) { page, _ in
+                    self.processors = page?.data ?? []
+                    self.processorCursor = page?.cursor
+                    self.userManager.getMany(self.network, cluster_id: self.cluster.id, limit: 3) { page, _ in
                         self.loading = false
-                        if let users {
-                            self.users = users
+                        if let page {
+                            self.users = page.data
+                            self.userTotal = page.total
                         }
                     }
                 }
             }
         }
     }
+
+    // Append the next page of a listing once its last card is shown. The cursor is cleared while
+    // the page loads so the same page is not requested twice.
+    private func loadEvidences() {
+        guard let cursor = self.evidenceCursor else {
+            return
+        }
+        self.evidenceCursor = nil
+
+        self.evidenceManager.getMany(self.network, cluster_id: self.cluster.id, date_minimum: self.date_minimum, date_maximum: self.date_maximum, cursor: cursor) { page, _ in
+            if let page {
+                self.evidences += page.data
+                self.evidenceCursor = page.cursor
+            } else {
+                self.evidenceCursor = cursor
+            }
+        }
+    }
+    private func loadProcessors() {
+        guard let cursor = self.processorCursor else {
+            return
+        }
+        self.processorCursor = nil
+
+        self.processorManager.getMany(self.network, cluster_id: self.cluster.id, date_minimum: self.date_minimum, cursor: cursor) { page, _ in
+            if let page {
+                self.processors += page.data
+                self.processorCursor = page.cursor
+            } else {
+                self.processorCursor = cursor
+            }
+        }
+    }
 }
 
 
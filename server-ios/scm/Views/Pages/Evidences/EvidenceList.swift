@@ -16,6 +16,7 @@ struct EvidenceList: View {
     @Environment(UserManager.self) private var userManager
 
     @State private var loading = false
+    @State private var loadingMore = false
 
     @State private var date_minimum: Int
     @State private var date_maximum: Int
@@ -70,10 +71,20 @@ struct EvidenceList: View {
                                 Array(
                                     self.viewManager.evidenceList.evidences
                                         .enumerated()), id: \.offset
-                            ) { _, evidence in
+                            ) { index, evidence in
                                 EvidenceCard(evidence) {
                                     self.app.path.append(.EvidenceDetail(evidence))
                                 }
+                                .onAppear {
+                                    if index == self.viewManager.evidenceList.evidences.count - 1 {
+                                        self.loadMore()
+                                    }
+                                }
+                            }
+
+                            if self.loadingMore {
+                                ProgressView()
+                                    .frame(maxWidth: .infinity)
                             }
                         }
                     }
@@ -86,7 +97,7 @@ struct EvidenceList: View {
         )
         .onChange(of: self.network.authentication) {
             if self.network.authentication == nil {
-                self.viewManager.evidenceList.evidences = []
+                self.viewManager.evidenceList = EvidenceListData()
             } else {
                 self.load()
             }
@@ -107,13 +118,34 @@ struct EvidenceList: View {
             self.evidenceManager.getMany(
                 self.network, date_minimum: self.date_minimum,
                 date_maximum: self.date_maximum
-            ) { evidences, _ in
+            ) { page, _ in
                 self.loading = false
 
-                if let evidences {
-                    self.viewManager.evidenceList.evidences = evidences
+                if let page {
+                    self.viewManager.evidenceList.evidences = page.data
+                    self.viewManager.evidenceList.cursor = page.cursor
                 }
             }
         }
     }
+
+    // Appends the next page once the last card is shown
+    private func loadMore() {
+        guard let cursor = self.viewManager.evidenceList.cursor, !self.loadingMore else {
+            return
+        }
+        self.loadingMore = true
+
+        self.evidenceManager.getMany(
+            self.network, date_minimum: self.date_minimum,
+            date_maximum: self.date_maximum, cursor: cursor
+        ) { page, _ in
+            self.loadingMore = false
+
+            if let page {
+                self.viewManager.evidenceList.evidences += page.data
+                self.viewManager.evidenceList.cursor = page.cursor
+            }
+        }
+    }
 }
@@ -14,6 +14,7 @@ struct UserList: View {
     @Environment(UserManager.self) private var userManager
 
     @State private var loading = true
+    @State private var loadingMore = false
     @State private var search = ""
     
     @State private var users: [ViewUser] = []
@@ -63,16 +64,27 @@ struct UserList: View {
                             InputText("Cari nama petugas...", text: $search)
 
                             ForEach(self.usersFilter, id: \.self) { user in
-                                if network.authentication?.user.id == user.id {
-                                    UserCard(user)
-                                } else {
-                                    UserCard(
-                                        user,
-                                        open: {
-                                            self.app.path.append(.UserForm(user))
-                                        }
-                                    )
+                                Group {
+                                    if network.authentication?.user.id == user.id {
+                                        UserCard(user)
+                                    } else {
+                                        UserCard(
+                                            user,
+                                            open: {
+                                                self.app.path.append(.UserForm(user))
+                                            }
+                                        )
+                                    }
                                 }
+                                .onAppear {
+                                    if user.id == self.usersFilter.last?.id {
+                                        self.loadMore()
+                                    }
+                                }
+                            }
+
+                            if self.loadingMore {
+                                ProgressView()
                             }
                         }
                         Rectangle()
@@ -90,9 +102,9 @@ struct UserList: View {
                 self.loading = false
             }
             
-            self.userManager.getMany(self.network) { users, err in
+            self.userManager.getMany(self.network) { page, err in
                 self.loading = false
-                if var users {
+                if var users = page?.data {
                     if var user = self.network.authentication?.user {
                         if let index = users.firstIndex(where: { $0.id == user.id }) {
                             users.remove(at: index)
@@ -101,8 +113,27 @@ struct UserList: View {
                         users.insert(user, at: 0)
                     }
                     self.viewManager.userList.users = users
+                    self.viewManager.userList.cursor = page?.cursor
                 }
             }
         }
     }
+
+    // Appends the next page once the last card is shown, the signed in user is already first
+    private func loadMore() {
+        guard let cursor = self.viewManager.userList.cursor, !self.loadingMore else {
+            return
+        }
+        self.loadingMore = true
+
+        self.userManager.getMany(self.network, cursor: cursor) { page, _ in
+            self.loadingMore = false
+
+            if let page {
+                let id = self.network.authentication?.user.id
+                self.viewManager.userList.users += page.data.filter { $0.id != id }
+                self.viewManager.userList.cursor = page.cursor
+            }
+        }
+    }
 }
@@ -1,11 +1,18 @@
 use actix::{Actor, Addr, AsyncContext, Handler, Recipient, StreamHandler, prelude::Message};
-use actix_web::{Error, HttpRequest, HttpResponse, web};
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse, web};
 use actix_web_actors::ws;
+use mongodb::Database;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::RwLock;
 
-use crate::views::evidence::ViewEvidence;
+use crate::{
+    models::{
+        processor::Processor,
+        user::{User, UserAuthentication, UserRole},
+    },
+    views::evidence::ViewEvidence,
+};
 
 #[derive(Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -19,38 +26,147 @@ pub enum CentralWebSocketRequest {
 pub enum CentralWebSocketResponse {
     Processor(HashMap<String, i64>),
     Evidence(ViewEvidence),
+    Review(ViewEvidence), // Evidence whose review status changed
 }
 
 #[derive(Clone, Message)]
 #[rtype(result = "()")]
 pub struct CentralWebSocketMessage(pub String);
 
+// Connected clients with the id of their user
+pub type CentralClient =
+    Arc<RwLock<HashMap<Recipient<CentralWebSocketMessage>, (String, Addr<CentralWebSocket>)>>>;
+
 #[derive(Clone, Message)]
 #[rtype(result = "()")]
 pub struct CentralWebSocket {
     processor: Arc<RwLock<HashMap<String, i64>>>, // Processor's last seen
-    client:
-        Arc<RwLock<HashMap<Recipient<CentralWebSocketMessage>, (String, Addr<CentralWebSocket>)>>>,
+    client: CentralClient,
+    user_id: String, // From the token the socket was opened with
+    db: Database,
 }
 
 pub async fn ws_index(
     req: HttpRequest,
     stream: web::Payload,
     processor: web::Data<Arc<RwLock<HashMap<String, i64>>>>,
-    client: web::Data<
-        Arc<RwLock<HashMap<Recipient<CentralWebSocketMessage>, (String, Addr<CentralWebSocket>)>>>,
-    >,
+    client: web::Data<CentralClient>,
+    db: web::Data<Database>,
 ) -> Result<HttpResponse, Error> {
+    let user_id = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.id.clone(),
+        None => return Ok(HttpResponse::Unauthorized().body("UNAUTHORIZED")),
+    };
     let processor = processor.get_ref().clone();
     let client = client.get_ref().clone();
 
     ws::start(
-        CentralWebSocket::new(processor.clone(), client.clone()),
+        CentralWebSocket::new(
+            processor.clone(),
+            client.clone(),
+            user_id,
+            db.get_ref().clone(),
+        ),
         &req,
         stream,
     )
 }
 
+// Sends the payload to the clients whose user belongs to the cluster
+pub async fn broadcast(
+    client: &CentralClient,
+    cluster_id: &String,
+    payload: &CentralWebSocketResponse,
+    db: &Database,
+) {
+    let payload = serde_json::to_string(payload).unwrap();
+    let recipient = {
+        let client = client.read().await;
+        (*client).values().cloned().collect::<Vec<_>>()
+    };
+
+    // Users are looked up once, a user may be connected from several devices
+    let mut allowed = HashMap::<String, bool>::new();
+    for (user_id, address) in recipient {
+        if !allowed.contains_key(&user_id) {
+            let allows = match User::find_by_id(&user_id, db).await {
+                Ok(user) => {
+                    user.role == UserRole::SuperAdmin || user.cluster_id.contains(cluster_id)
+                }
+                Err(_) => false,
+            };
+            allowed.insert(user_id.clone(), allows);
+        }
+        if allowed[&user_id] {
+            address.do_send(CentralWebSocketMessage(payload.clone()));
+        }
+    }
+}
+
+// Sends each client the last seen of the processors in the clusters of its user
+pub async fn broadcast_processor(
+    client: &CentralClient,
+    processor: &HashMap<String, i64>,
+    db: &Database,
+) {
+    let recipient = {
+        let client = client.read().await;
+        (*client).values().cloned().collect::<Vec<_>>()
+    };
+    let cluster = processor_cluster(processor, db).await;
+
+    // Built once per user, a user may be connected from several devices
+    let mut payload = HashMap::<String, Option<String>>::new();
+    for (user_id, address) in recipient {
+        if !payload.contains_key(&user_id) {
+            let data = match User::find_by_id(&user_id, db).await {
+                Ok(user) => Some(
+                    serde_json::to_string(&processor_allowed(&user, processor, &cluster)).unwrap(),
+                ),
+                Err(_) => None,
+            };
+            payload.insert(user_id.clone(), data);
+        }
+        if let Some(payload) = &payload[&user_id] {
+            address.do_send(CentralWebSocketMessage(payload.clone()));
+        }
+    }
+}
+
+// The cluster of each processor in the map, processors that were deleted are left out
+async fn processor_cluster(
+    processor: &HashMap<String, i64>,
+    db: &Database,
+) -> HashMap<String, String> {
+    let mut cluster = HashMap::new();
+    for id in processor.keys() {
+        if let Ok(v) = Processor::find_by_id(id, db).await {
+            cluster.insert(id.clone(), v.cluster_id);
+        }
+    }
+    cluster
+}
+
+// The part of the map the user may see, a super admin sees every processor
+fn processor_allowed(
+    user: &User,
+    processor: &HashMap<String, i64>,
+    cluster: &HashMap<String, String>,
+) -> CentralWebSocketResponse {
+    CentralWebSocketResponse::Processor(
+        processor
+            .iter()
+            .filter(|(id, _)| {
+                user.role == UserRole::SuperAdmin
+                    || cluster
+                        .get(*id)
+                        .is_some_and(|v| user.cluster_id.contains(v))
+            })
+            .map(|(k, v)| (k.clone(), *v))
+            .collect(),
+    )
+}
+
 impl Actor for CentralWebSocket {
     type Context = ws::WebsocketContext<Self>;
 
@@ -74,6 +190,8 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for CentralWebSocket
                     let processor = self.processor.clone();
                     let client = self.client.clone();
                     let address = ctx.address();
+                    let authenticated = self.user_id.clone();
+                    let db = self.db.clone();
 
                     tokio::spawn(async move {
                         let req = match serde_json::from_str::<CentralWebSocketRequest>(&msg) {
@@ -89,21 +207,21 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for CentralWebSocket
 
                         match req {
                             CentralWebSocketRequest::Connect(user_id) => {
+                                // A socket only ever receives what its own user may see
+                                if user_id != authenticated {
+                                    return;
+                                }
                                 println!("WS CONNECTED");
-                                let processor = processor.read().await;
-                                let mut data = HashMap::new();
+                                let processor = processor.read().await.clone();
 
-                                for (k, v) in processor.iter() {
-                                    data.insert(k.to_string(), v.clone());
+                                if let Ok(user) = User::find_by_id(&user_id, &db).await {
+                                    let cluster = processor_cluster(&processor, &db).await;
+                                    let payload = processor_allowed(&user, &processor, &cluster);
+                                    address.do_send(CentralWebSocketMessage(
+                                        serde_json::to_string(&payload).unwrap(),
+                                    ));
                                 }
 
-                                drop(processor);
-
-                                let payload = CentralWebSocketResponse::Processor(data);
-                                address.do_send(CentralWebSocketMessage(
-                                    serde_json::to_string(&payload).unwrap(),
-                                ));
-
                                 let mut client = client.write().await;
                                 let recipient = address.clone().recipient();
                                 (*client).insert(recipient, (user_id, address));
@@ -136,11 +254,16 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for CentralWebSocket
 impl CentralWebSocket {
     fn new(
         processor: Arc<RwLock<HashMap<String, i64>>>,
-        client: Arc<
-            RwLock<HashMap<Recipient<CentralWebSocketMessage>, (String, Addr<CentralWebSocket>)>>,
-        >,
+        client: CentralClient,
+        user_id: String,
+        db: Database,
     ) -> Self {
-        Self { processor, client }
+        Self {
+            processor,
+            client,
+            user_id,
+            db,
+        }
     }
 }
 
@@ -26,3 +26,83 @@ pub async fn connect() -> Option<Database> {
         _ => None,
     }
 }
+
+// Tests that exercise the aggregation pipelines run against a local MongoDB, every test gets a
+// database of its own. They are ignored by default, run them with `cargo test -- --ignored`
+// and point TEST_DATABASE_URI at the server when it is not on localhost.
+#[cfg(test)]
+pub mod test {
+    use mongodb::{Client, Database};
+    use uuid::Uuid;
+
+    use crate::models::{
+        camera::{Camera, CameraAddress},
+        cluster::Cluster,
+        evidence::{Evidence, EvidenceStatus},
+        processor::{Processor, ProcessorAddress},
+    };
+
+    pub async fn connect() -> Database {
+        let uri =
+            std::env::var("TEST_DATABASE_URI").unwrap_or(String::from("mongodb://localhost:27017"));
+        let client = Client::with_uri_str(uri)
+            .await
+            .expect("Failed to connect to database");
+
+        client.database(&format!("scm_test_{}", Uuid::new_v4().simple()))
+    }
+
+    // A cluster with one processor, camera and evidence, all named after the cluster id
+    pub async fn seed(cluster_id: &str, db: &Database) {
+        let cluster = Cluster {
+            id: cluster_id.to_string(),
+            name: cluster_id.to_string(),
+            retention: None,
+            report: Vec::new(),
+            escalation: Vec::new(),
+        };
+        let processor = Processor {
+            id: format!("{}_processor", cluster_id),
+            cluster_id: cluster_id.to_string(),
+            name: format!("{}_processor", cluster_id),
+            model: String::from("yolov8n.hef"),
+            address: ProcessorAddress {
+                host: [127, 0, 0, 1],
+                port: 8000,
+            },
+            version: 0,
+            secret: None,
+        };
+        let camera = Camera {
+            id: format!("{}_camera", cluster_id),
+            cluster_id: cluster_id.to_string(),
+            processor_id: processor.id.clone(),
+            address: CameraAddress {
+                host: [127, 0, 0, 1],
+                port: 554,
+                path: None,
+                authentication: None,
+            },
+            name: format!("{}_camera", cluster_id),
+        };
+        let evidence = Evidence {
+            id: format!("{}_evidence", cluster_id),
+            cluster_id: cluster_id.to_string(),
+            processor_id: processor.id.clone(),
+            camera_id: camera.id.clone(),
+            frame_id: String::from("000001"),
+            timestamp: 0,
+            person: Vec::new(),
+            clip: None,
+            status: EvidenceStatus::Open,
+            review: Vec::new(),
+            correction: Vec::new(),
+            escalation: Vec::new(),
+        };
+
+        cluster.save(db).await.unwrap();
+        processor.save(db).await.unwrap();
+        camera.save(db).await.unwrap();
+        evidence.save(db).await.unwrap();
+    }
+}
@@ -1,10 +1,20 @@
-use actix_web::HttpResponse;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
 
-use crate::models::event::EventKind;
+use crate::models::{event::EventKind, user::UserAuthentication};
 
 pub fn error_handler(e: EventKind) -> HttpResponse {
     match e {
         EventKind::NotFound => HttpResponse::NotFound().body(e.to_string()),
+        EventKind::Unauthorized => HttpResponse::Unauthorized().body(e.to_string()),
         _ => HttpResponse::BadRequest().body(e.to_string()),
     }
 }
+
+// Rejects requests for clusters the user does not belong to
+pub fn cluster_forbidden(req: &HttpRequest, cluster_id: &String) -> Option<HttpResponse> {
+    match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) if issuer.allows(cluster_id) => None,
+        Some(_) => Some(HttpResponse::Forbidden().body("FORBIDDEN")),
+        None => Some(HttpResponse::Unauthorized().body("UNAUTHORIZED")),
+    }
+}
@@ -1,17 +1,13 @@
 use std::{
     collections::{HashMap, VecDeque},
-    fs::{File, read_to_string},
-    io::{self, Seek, SeekFrom},
+    fs::read_to_string,
+    io,
     sync::Arc,
     time::Duration,
 };
 
-use a2::{
-    Client, ClientConfig, DefaultNotificationBuilder, NotificationBuilder, NotificationOptions,
-};
 use actix::{Addr, Recipient};
 use actix_cors::Cors;
-use actix_files::Files;
 use actix_web::{
     App, HttpServer,
     web::{self, scope},
@@ -20,20 +16,31 @@ use chrono::Utc;
 use tokio::{sync::RwLock, time::sleep};
 
 use central::{CentralWebSocket, CentralWebSocketMessage};
-use models::user::{User, UserAuthenticationMiddlewareFactory, UserRole, load_keys};
+use models::user::{
+    User, UserAuthenticationMiddlewareFactory, UserAuthorization, UserRole, load_keys,
+};
+use notifier::{NotifierDispatcher, apple::AppleNotifier, telegram::TelegramNotifier};
+use telegram::TelegramClient;
 use uuid::Uuid;
 
 use crate::models::{
+    batch::Batch,
+    cluster::Cluster,
     evidence::Evidence,
-    processor::Processor,
-    subscriber::{Subscriber, SubscriberKind},
+    export::Export,
+    preference::{Preference, PreferenceMode},
+    queue::QueuedEvidence,
+    report::Report,
+    signature::Signature,
 };
 
 mod central;
 mod database;
 mod helper;
 mod models;
+mod notifier;
 mod routes;
+mod telegram;
 mod views;
 
 fn load_env() {
@@ -85,6 +92,12 @@ fn load_env() {
         if std::env::var("BASE_PATH").is_err() {
             std::env::set_var("BASE_PATH", "");
         }
+        if std::env::var("TELEGRAM_URL").is_err() {
+            std::env::set_var("TELEGRAM_URL", "https://api.telegram.org");
+        }
+        if std::env::var("NOTIFICATION_WINDOW").is_err() {
+            std::env::set_var("NOTIFICATION_WINDOW", "300");
+        }
         if std::env::var("HOST").is_err() {
             std::env::set_var("HOST", "127.0.0.1");
         }
@@ -108,13 +121,24 @@ async fn main() -> io::Result<()> {
     let database = database::connect()
         .await
         .expect("Failed to connect to database");
+    if let Err(e) = Evidence::create_indexes(&database).await {
+        println!("INDEXING FAILED: {:?}", e);
+    }
 
     let processor = Arc::new(RwLock::new(HashMap::<String, i64>::new()));
-    let evidence = Arc::new(RwLock::new(VecDeque::<Evidence>::new()));
+    // Evidence queued for notification before a restart is dispatched first
+    let evidence = Arc::new(RwLock::new(
+        QueuedEvidence::load(&database).await.unwrap_or_else(|e| {
+            println!("QUEUE LOADING FAILED: {:?}", e);
+            VecDeque::<Evidence>::new()
+        }),
+    ));
     let client = Arc::new(RwLock::new(HashMap::<
         Recipient<CentralWebSocketMessage>,
         (String, Addr<CentralWebSocket>),
     >::new()));
+    let signature = Arc::new(RwLock::new(Signature::default()));
+    let telegram = TelegramClient::from_env();
 
     match User::find_all(&database).await {
         Ok(users) => {
@@ -146,136 +170,162 @@ async fn main() -> io::Result<()> {
     // STATE MANAGER THREAD
     let processor_clone = processor.clone();
     let client_clone = client.clone();
+    let database_clone = database.clone();
     let _ = tokio::spawn(async move {
         loop {
             let timestamp = Utc::now().timestamp_millis();
 
-            let payload = {
+            let processor = {
                 let mut processor = processor_clone.write().await;
                 processor.retain(|_, exp| timestamp <= *exp);
-                central::CentralWebSocketResponse::Processor((*processor).clone())
+                (*processor).clone()
             };
 
-            let client = client_clone.read().await;
-            for (_, (_, client)) in (*client).iter() {
-                client.do_send(central::CentralWebSocketMessage(
-                    serde_json::to_string(&payload).unwrap(),
-                ));
-            }
+            central::broadcast_processor(&client_clone, &processor, &database_clone).await;
 
             sleep(Duration::from_millis(30000)).await;
         }
     });
 
+    // RETENTION THREAD
     let database_clone = database.clone();
-    let evidence_clone = evidence.clone();
-    let _ = tokio::spawn(async move {
-        let mut file = File::open("keys/apns.p8").expect("APNS_NOT_FOUND");
-
-        let key_id = std::env::var("APNS_KEY").expect("APNS_KEY_NOT_FOUND");
-        let team_id = std::env::var("APNS_TEAM").expect("APNS_TEAM_NOT_FOUND");
-
-        let mut apns = Client::token(
-            &mut file,
-            key_id.clone(),
-            team_id.clone(),
-            ClientConfig::new(a2::Endpoint::Sandbox),
-        )
-        .expect("TOKEN_CREATION_FAILED");
-
-        let mut counter = 0;
-
+    tokio::spawn(async move {
         loop {
-            let evidence = {
-                let mut evidence = evidence_clone.write().await;
-                match (*evidence).pop_front() {
-                    Some(v) => v,
-                    None => {
-                        sleep(Duration::from_secs(1)).await;
+            if let Ok(mut clusters) = Cluster::find_all(&database_clone).await {
+                for cluster in clusters.drain(..) {
+                    if cluster.retention.is_none() {
                         continue;
                     }
+                    match cluster.expire(false, &database_clone).await {
+                        Ok(retention) if !retention.evidence_id.is_empty() => println!(
+                            "RETENTION: {} evidence(s) deleted from cluster {}",
+                            retention.evidence_id.len(),
+                            cluster.id
+                        ),
+                        Err(e) => println!("RETENTION FAILED: {:?}", e),
+                        _ => (),
+                    }
                 }
-            };
-
-            let mut users =
-                match User::find_many_by_cluster_id(&evidence.cluster_id, &database_clone).await {
-                    Ok(v) => v,
-                    _ => continue,
-                };
+            }
 
-            for user in users.drain(..) {
-                let mut subscribers =
-                    match Subscriber::find_many_by_user_id(&user.id, &database_clone).await {
-                        Ok(v) => v,
-                        _ => continue,
-                    };
+            sleep(Duration::from_secs(3600)).await;
+        }
+    });
 
-                for subscriber in subscribers.drain(..) {
-                    match &subscriber.kind {
-                        SubscriberKind::Apple(token) => {
-                            let options = NotificationOptions {
-                                apns_topic: Some("com.gidence.scm"),
-                                ..Default::default()
-                            };
+    // REPORT THREAD
+    let database_clone = database.clone();
+    tokio::spawn(async move {
+        loop {
+            if let Ok(mut clusters) = Cluster::find_all(&database_clone).await {
+                for cluster in clusters.drain(..) {
+                    for schedule in cluster.report.iter() {
+                        match Report::generate(&cluster, *schedule, &database_clone).await {
+                            Ok(Some(report)) => println!(
+                                "REPORT: {:?} report {} generated for cluster {}",
+                                schedule, report.id, cluster.id
+                            ),
+                            Err(e) => println!("REPORT FAILED: {:?}", e),
+                            _ => (),
+                        }
+                    }
+                }
+            }
 
-                            let violation_count = evidence
-                                .person
-                                .iter()
-                                .map(|p| p.violation.len())
-                                .sum::<usize>();
+            sleep(Duration::from_secs(3600)).await;
+        }
+    });
 
-                            let title = format!("Terjadi {} Pelanggaran Baru!", violation_count);
-                            let subtitle = match Processor::find_by_id(
-                                &evidence.processor_id,
-                                &database_clone,
-                            )
-                            .await
-                            {
-                                Ok(v) => format!("Tertangkap kamera {}", v.name),
-                                _ => String::from("Cek sekarang!"),
-                            };
+    // EXPORT THREAD
+    let database_clone = database.clone();
+    tokio::spawn(async move {
+        if let Err(e) = Export::reset(&database_clone).await {
+            println!("EXPORT RESET FAILED: {:?}", e);
+        }
 
-                            let builder = DefaultNotificationBuilder::new()
-                                .set_title(&title)
-                                .set_subtitle(&subtitle)
-                                .set_content_available()
-                                .set_sound("ping.flac");
+        let mut expired = 0;
+        loop {
+            match Export::claim(&database_clone).await {
+                Ok(Some(mut export)) => {
+                    match export.run(&database_clone).await {
+                        Ok(()) => println!(
+                            "EXPORT: {} evidence(s) archived in export {}",
+                            export.evidence_count, export.id
+                        ),
+                        Err(e) => println!("EXPORT FAILED: {:?}", e),
+                    }
+                    continue;
+                }
+                Err(e) => println!("EXPORT FAILED: {:?}", e),
+                _ => (),
+            }
 
-                            let mut payload = builder.build(token, options);
-                            payload
-                                .add_custom_data("evidence_id", &evidence.id)
-                                .unwrap();
+            let timestamp = Utc::now().timestamp_millis();
+            if timestamp - expired >= 60000 {
+                let _ = Export::expire(&database_clone).await;
+                expired = timestamp;
+            }
 
-                            if let Err(err) = apns.send(payload).await {
-                                println!("SENDING FAILED: {:#?}", err);
-                                let response = match err {
-                                    a2::Error::ResponseError(v) => v,
-                                    _ => continue,
-                                };
+            sleep(Duration::from_secs(1)).await;
+        }
+    });
 
-                                if response.code == 403 || response.code == 410 {
-                                    let _ = subscriber.delete(&database_clone).await;
-                                }
-                            }
-                        }
+    // NOTIFIER THREAD
+    let database_clone = database.clone();
+    let evidence_clone = evidence.clone();
+    let window = std::env::var("NOTIFICATION_WINDOW")
+        .unwrap()
+        .parse::<i64>()
+        .expect("INVALID_NOTIFICATION_WINDOW");
+    if let Err(e) = Batch::create_indexes(&database).await {
+        println!("INDEXING FAILED: {:?}", e);
+    }
+    if let Err(e) = Batch::reset(&database).await {
+        println!("BATCH RESET FAILED: {:?}", e);
+    }
+    let mut dispatcher = NotifierDispatcher::new(window * 1000);
+    dispatcher.register(AppleNotifier::from_env());
+    if let Some(telegram) = telegram.clone() {
+        dispatcher.register(TelegramNotifier::new(telegram));
+    }
+    tokio::spawn(async move {
+        let mut scheduled = 0; // Digests and escalations are checked once a minute
+        loop {
+            let timestamp = Utc::now().timestamp_millis();
+            if timestamp - scheduled >= 60000 {
+                if let Ok(mut preferences) =
+                    Preference::find_many_by_mode(PreferenceMode::Digest, &database_clone).await
+                {
+                    for preference in preferences.iter_mut() {
+                        dispatcher
+                            .digest(preference, timestamp, &database_clone)
+                            .await;
+                    }
+                }
+                if let Ok(clusters) = Cluster::find_all(&database_clone).await {
+                    for cluster in clusters.iter().filter(|c| !c.escalation.is_empty()) {
+                        dispatcher
+                            .escalate(cluster, timestamp, &database_clone)
+                            .await;
                     }
                 }
+                scheduled = timestamp;
             }
+            dispatcher.flush(timestamp, &database_clone).await;
+            dispatcher.resend(&database_clone).await;
 
-            counter += 1;
-            sleep(Duration::from_secs(1)).await;
+            let evidence = {
+                let mut evidence = evidence_clone.write().await;
+                match (*evidence).pop_front() {
+                    Some(v) => v,
+                    None => {
+                        sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                }
+            };
 
-            if counter >= 240 {
-                file.seek(SeekFrom::Start(0)).expect("APNS_KEY_SEEK_FAILED");
-                apns = Client::token(
-                    &mut file,
-                    key_id.clone(),
-                    team_id.clone(),
-                    ClientConfig::new(a2::Endpoint::Sandbox),
-                )
-                .expect("TOKEN_CREATION_FAILED");
-                counter = 0;
-            }
+            dispatcher.dispatch(&evidence, &database_clone).await;
+            let _ = QueuedEvidence::remove(&evidence.id, &database_clone).await;
         }
     });
 
@@ -291,11 +341,17 @@ async fn main() -> io::Result<()> {
             .app_data(web::Data::new(processor.clone()))
             .app_data(web::Data::new(evidence.clone()))
             .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(signature.clone()))
+            .app_data(web::Data::new(telegram.clone()))
             .service(
                 web::scope(&std::env::var("BASE_PATH").unwrap())
-                    .service(web::resource("/ws").to(central::ws_index))
+                    .service(
+                        web::resource("/ws")
+                            .wrap(UserAuthorization::any())
+                            .to(central::ws_index),
+                    )
                     .service(routes::ping)
-                    .service(Files::new("/static", "./evidence").show_files_listing())
+                    .service(scope("/static").service(routes::evidence::get_evidence_file))
                     .service(
                         scope("/users")
                             .service(routes::user::create_user)
@@ -303,13 +359,24 @@ async fn main() -> io::Result<()> {
                             .service(routes::user::delete_user)
                             .service(routes::user::login)
                             .service(routes::user::refresh)
+                            .service(routes::user::get_user_preference)
+                            .service(routes::user::update_user_preference)
+                            .service(routes::user::delete_user_preference)
                             .service(routes::user::get_users)
                             .service(routes::user::get_user),
                     )
                     .service(
                         scope("/clusters")
                             .service(routes::cluster::create_cluster)
+                            .service(routes::cluster::update_cluster)
                             .service(routes::cluster::delete_cluster)
+                            .service(routes::cluster::get_cluster_retention)
+                            .service(routes::cluster::get_cluster_stored_reports)
+                            .service(routes::cluster::get_cluster_stored_report)
+                            .service(routes::cluster::get_cluster_report)
+                            .service(routes::cluster::create_cluster_enrollment)
+                            .service(routes::cluster::get_cluster_enrollments)
+                            .service(routes::cluster::delete_cluster_enrollment)
                             .service(routes::cluster::get_clusters)
                             .service(routes::cluster::get_cluster),
                     )
@@ -319,17 +386,39 @@ async fn main() -> io::Result<()> {
                             .service(routes::processor::delete_processor)
                             .service(routes::processor::get_processors)
                             .service(routes::processor::get_processor)
+                            .service(routes::processor::rotate_processor_secret)
                             .service(routes::processor::sync_processor),
                     )
+                    .service(scope("/enrollments").service(routes::enrollment::redeem_enrollment))
                     .service(
                         scope("/evidences")
+                            .service(routes::evidence::export_evidences)
                             .service(routes::evidence::create_evidence)
+                            .service(routes::evidence::correct_evidence)
+                            .service(routes::evidence::review_evidence)
+                            .service(routes::evidence::get_evidence_review)
                             .service(routes::evidence::get_evidence)
                             .service(routes::evidence::get_evidences),
                     )
-                    .service(scope("/cameras").service(routes::camera::get_cameras))
+                    .service(
+                        scope("/exports")
+                            .service(routes::export::get_exports)
+                            .service(routes::export::download_export)
+                            .service(routes::export::get_export),
+                    )
+                    .service(scope("/datasets").service(routes::dataset::export_dataset))
+                    .service(
+                        scope("/analytics").service(routes::analytics::get_violation_analytics),
+                    )
+                    .service(
+                        scope("/cameras")
+                            .wrap(UserAuthorization::any())
+                            .service(routes::camera::get_cameras),
+                    )
                     .service(
                         scope("/subscribers")
+                            .service(routes::subscriber::link_telegram)
+                            .service(routes::subscriber::telegram_webhook)
                             .service(routes::subscriber::refresh)
                             .service(routes::subscriber::subscribe)
                             .service(routes::subscriber::unsubscribe),
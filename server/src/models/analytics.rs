@@ -0,0 +1,104 @@
+use chrono::Utc;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::models::evidence::EvidenceQuery;
+
+use super::event::EventKind;
+
+// Period covered when the query does not say
+const ANALYTICS_PERIOD: i64 = 7 * 24 * 60 * 60 * 1000;
+// Points a single series may have, so an hourly bucket over a year is rejected
+const ANALYTICS_BUCKET_MAXIMUM: i64 = 1000;
+const ANALYTICS_TOP: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyticsBucket {
+    Hour,
+    Day,
+    Week,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyticsGroup {
+    Cluster,
+    Processor,
+    Camera,
+    Violation,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQuery {
+    pub cluster_id: Option<String>,
+    pub processor_id: Option<String>,
+    pub camera_id: Option<String>,
+    pub date_minimum: Option<i64>,
+    pub date_maximum: Option<i64>,
+    pub bucket: Option<AnalyticsBucket>, // Day when not set
+    pub group: Option<AnalyticsGroup>,   // A single series when not set
+    pub timezone: Option<String>,        // Bucket boundaries, e.g. Asia/Jakarta, UTC when not set
+    pub top: Option<usize>,              // Cameras in the top offenders
+    #[serde(skip)]
+    pub scope: Option<Vec<String>>, // Clusters of the caller, set for everyone but super admins
+}
+
+impl AnalyticsBucket {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            AnalyticsBucket::Hour => "hour",
+            AnalyticsBucket::Day => "day",
+            AnalyticsBucket::Week => "week",
+        }
+    }
+    pub fn duration(&self) -> i64 {
+        match self {
+            AnalyticsBucket::Hour => 60 * 60 * 1000,
+            AnalyticsBucket::Day => 24 * 60 * 60 * 1000,
+            AnalyticsBucket::Week => 7 * 24 * 60 * 60 * 1000,
+        }
+    }
+}
+
+impl AnalyticsQuery {
+    pub fn bucket(&self) -> AnalyticsBucket {
+        self.bucket.unwrap_or(AnalyticsBucket::Day)
+    }
+    pub fn top(&self) -> usize {
+        self.top.unwrap_or(ANALYTICS_TOP).clamp(1, 100)
+    }
+    // Period the analytics cover, the last week when not set. The timezone is checked here too,
+    // so the database is never asked to bucket by one it does not know.
+    pub fn period(&self) -> Result<(i64, i64), EventKind> {
+        if let Some(timezone) = &self.timezone
+            && timezone.parse::<Tz>().is_err()
+        {
+            return Err(EventKind::InvalidTimezone);
+        }
+
+        let date_maximum = self
+            .date_maximum
+            .unwrap_or_else(|| Utc::now().timestamp_millis());
+        let date_minimum = self.date_minimum.unwrap_or(date_maximum - ANALYTICS_PERIOD);
+
+        if date_minimum >= date_maximum
+            || (date_maximum - date_minimum) / self.bucket().duration() > ANALYTICS_BUCKET_MAXIMUM
+        {
+            return Err(EventKind::InvalidRange);
+        }
+
+        Ok((date_minimum, date_maximum))
+    }
+    // Evidence the analytics are computed from within the given period
+    pub fn evidence_query(&self, date_minimum: i64, date_maximum: i64) -> EvidenceQuery {
+        EvidenceQuery {
+            cluster_id: self.cluster_id.clone(),
+            processor_id: self.processor_id.clone(),
+            camera_id: self.camera_id.clone(),
+            date_minimum: Some(date_minimum),
+            date_maximum: Some(date_maximum),
+            scope: self.scope.clone(),
+            ..Default::default()
+        }
+    }
+}
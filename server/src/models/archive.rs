@@ -0,0 +1,90 @@
+use std::{
+    fs::{File, create_dir_all},
+    io::Write,
+};
+
+use tokio::{
+    sync::mpsc::{self, Sender},
+    task::{JoinHandle, spawn_blocking},
+};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use super::event::EventKind;
+
+// Items read ahead of the archive writer
+const ARCHIVE_BUFFER: usize = 64;
+
+// The files of an archive, written one item at a time
+pub trait ArchiveContent: Send + 'static {
+    type Item: Send + 'static;
+
+    // Files before the first item
+    fn start(&mut self, _archive: &mut ZipWriter<File>) -> Result<(), EventKind> {
+        Ok(())
+    }
+    fn entry(&mut self, archive: &mut ZipWriter<File>, item: Self::Item) -> Result<(), EventKind>;
+    // Files after the last item
+    fn finish(&mut self, _archive: &mut ZipWriter<File>) -> Result<(), EventKind> {
+        Ok(())
+    }
+}
+
+// Archive written on a blocking thread from items handed to it as they are read, so memory use
+// does not grow with the archive and it is not written on the async workers
+pub struct ArchiveWriter<C: ArchiveContent> {
+    sender: Sender<C::Item>,
+    writer: JoinHandle<Result<(C, u64), EventKind>>,
+}
+
+impl<C: ArchiveContent> ArchiveWriter<C> {
+    pub fn new(file: &str, content: C) -> Self {
+        let (sender, mut receiver) = mpsc::channel(ARCHIVE_BUFFER);
+        let file = file.to_string();
+
+        let writer = spawn_blocking(move || {
+            let mut content = content;
+
+            let _ = create_dir_all("./export");
+            let mut archive = ZipWriter::new(File::create(file).map_err(|e| failed(&e))?);
+            content.start(&mut archive)?;
+            while let Some(item) = receiver.blocking_recv() {
+                content.entry(&mut archive, item)?;
+            }
+            content.finish(&mut archive)?;
+
+            let mut file = archive.finish().map_err(|e| failed(&e))?;
+            file.flush().map_err(|e| failed(&e))?;
+            let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+            Ok((content, size))
+        });
+
+        Self { sender, writer }
+    }
+    // False once the writer stopped, its error is returned by finish
+    pub async fn send(&self, item: C::Item) -> bool {
+        self.sender.send(item).await.is_ok()
+    }
+    // Waits for the archive to be written, returns its content and size in bytes
+    pub async fn finish(self) -> Result<(C, u64), EventKind> {
+        drop(self.sender);
+        self.writer.await.unwrap_or(Err(EventKind::SavingFailed))
+    }
+}
+
+// JPEGs are already compressed, they are stored as is
+pub fn stored() -> SimpleFileOptions {
+    SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true)
+}
+pub fn deflated() -> SimpleFileOptions {
+    SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true)
+}
+
+pub fn failed(e: &dyn std::fmt::Debug) -> EventKind {
+    println!("ERROR: {:?}", e);
+    EventKind::SavingFailed
+}
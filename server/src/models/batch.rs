@@ -0,0 +1,268 @@
+use mongodb::{
+    Database, IndexModel,
+    bson::doc,
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{evidence::Evidence, preference::Preference};
+
+use super::event::EventKind;
+
+const COLLECTION: &str = "batches";
+
+// Evidence of a camera held back from a user while a notification window is open, kept in the
+// database so a restart does not lose it. The first evidence of a window is sent right away and
+// opens the batch, the rest is sent as a single summary once the window closes.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Batch {
+    pub id: String,
+    pub user_id: String,
+    pub cluster_id: String,
+    pub camera_id: String,
+    pub evidence_id: Vec<String>, // Held back, oldest first
+    pub violation_count: u64,
+    pub timestamp: i64, // When the window opened
+    pub expiry: i64,    // When the window closes
+    #[serde(default)]
+    pub claimed: Option<i64>, // When the summary started being sent, the batch is deleted after
+}
+
+impl Batch {
+    // Adds the evidence to the user's open batch of the camera, counting the violations the
+    // user's preference cares about. Returns whether there was none, a batch is then opened and
+    // the evidence should be sent right away.
+    pub async fn append(
+        preference: &Preference,
+        evidence: &Evidence,
+        window: i64,
+        timestamp: i64,
+        db: &Database,
+    ) -> Result<bool, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if window <= 0 {
+            return Ok(true);
+        }
+        let user_id = &preference.user_id;
+        let violation_count = preference.violation_count(evidence) as i64;
+
+        match collection
+            .update_one(
+                doc! {
+                    "user_id": user_id,
+                    "cluster_id": &evidence.cluster_id,
+                    "camera_id": &evidence.camera_id,
+                    "expiry": { "$gt": timestamp },
+                },
+                doc! {
+                    "$push": { "evidence_id": &evidence.id },
+                    "$inc": { "violation_count": violation_count },
+                },
+                None,
+            )
+            .await
+        {
+            Ok(v) if v.matched_count > 0 => return Ok(false),
+            Ok(_) => (),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                return Err(EventKind::UpdatingFailed);
+            }
+        }
+
+        let batch = Self {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.clone(),
+            cluster_id: evidence.cluster_id.clone(),
+            camera_id: evidence.camera_id.clone(),
+            evidence_id: Vec::new(),
+            violation_count: 0,
+            timestamp,
+            expiry: timestamp + window,
+            claimed: None,
+        };
+        if collection.insert_one(&batch, None).await.is_ok() {
+            Ok(true)
+        } else {
+            Err(EventKind::SavingFailed)
+        }
+    }
+    // Takes the batch whose window closed first, in a single update so it is only sent once. It
+    // is kept until its deliveries are recorded.
+    pub async fn claim(timestamp: i64, db: &Database) -> Result<Option<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .find_one_and_update(
+                doc! { "expiry": { "$lte": timestamp }, "claimed": null },
+                doc! { "$set": { "claimed": timestamp } },
+                FindOneAndUpdateOptions::builder()
+                    .sort(doc! { "expiry": 1 })
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+        {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::UpdatingFailed)
+            }
+        }
+    }
+    // Batches whose summary was interrupted by a restart are sent again
+    pub async fn reset(db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection
+            .update_many(
+                doc! { "claimed": { "$ne": null } },
+                doc! { "$set": { "claimed": null } },
+                None,
+            )
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::UpdatingFailed)
+        }
+    }
+    pub async fn delete(&self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection
+            .delete_one(doc! { "id": &self.id }, None)
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::DeletingFailed)
+        }
+    }
+
+    pub async fn create_indexes(db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let indexes = [
+            doc! { "expiry": 1 },
+            doc! { "user_id": 1, "cluster_id": 1, "camera_id": 1, "expiry": -1 },
+        ]
+        .into_iter()
+        .map(|keys| IndexModel::builder().keys(keys).build());
+
+        match collection.create_indexes(indexes, None).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::SavingFailed)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database,
+        models::{
+            evidence::{Evidence, EvidencePerson, EvidencePersonViolation, EvidenceStatus},
+            preference::{Preference, PreferenceRequest},
+        },
+    };
+
+    use super::Batch;
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn append_holds_back_evidence_until_the_window_closes() {
+        let db = database::test::connect().await;
+
+        let evidence = Evidence {
+            id: String::from("evidence"),
+            cluster_id: String::from("cluster"),
+            processor_id: String::from("processor"),
+            camera_id: String::from("camera"),
+            frame_id: String::from("frame"),
+            timestamp: 0,
+            person: vec![EvidencePerson {
+                id: String::from("person"),
+                bbox: [0.0, 0.0, 1.0, 1.0],
+                confidence: 0.9,
+                part: Vec::new(),
+                equipment: Vec::new(),
+                violation: vec![
+                    EvidencePersonViolation::MissingHardhat,
+                    EvidencePersonViolation::MissingGloves,
+                ],
+                confirmation: Vec::new(),
+            }],
+            clip: None,
+            status: EvidenceStatus::Open,
+            review: Vec::new(),
+            correction: Vec::new(),
+            escalation: Vec::new(),
+        };
+        // Only the hardhats the user cares about are counted
+        let preference = Preference::new(
+            String::from("user"),
+            PreferenceRequest {
+                violation: Some(vec![EvidencePersonViolation::MissingHardhat]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // The first evidence opens the window, the next ones are held back
+        assert!(
+            Batch::append(&preference, &evidence, 1000, 0, &db)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !Batch::append(&preference, &evidence, 1000, 10, &db)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !Batch::append(&preference, &evidence, 1000, 20, &db)
+                .await
+                .unwrap()
+        );
+        assert!(Batch::claim(999, &db).await.unwrap().is_none());
+
+        // Evidence of another camera opens its own window
+        let mut other = evidence.clone();
+        other.camera_id = String::from("other");
+        assert!(
+            Batch::append(&preference, &other, 1000, 30, &db)
+                .await
+                .unwrap()
+        );
+
+        let batch = Batch::claim(1000, &db).await.unwrap().unwrap();
+        assert_eq!(batch.evidence_id.len(), 2);
+        assert_eq!(batch.camera_id, "camera");
+        assert_eq!(batch.violation_count, 2);
+        assert!(Batch::claim(1000, &db).await.unwrap().is_none());
+
+        // A claim interrupted by a restart is taken again, until the batch is deleted
+        Batch::reset(&db).await.unwrap();
+        let batch = Batch::claim(1000, &db).await.unwrap().unwrap();
+        batch.delete(&db).await.unwrap();
+        Batch::reset(&db).await.unwrap();
+        assert!(Batch::claim(1000, &db).await.unwrap().is_none());
+
+        // Once closed, the next evidence opens a new window
+        assert!(
+            Batch::append(&preference, &evidence, 1000, 1000, &db)
+                .await
+                .unwrap()
+        );
+
+        db.drop(None).await.unwrap();
+    }
+}
@@ -7,6 +7,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::models::evidence::{Evidence, EvidenceQuery};
 
+use crate::views::page::PageOrder;
+
 use super::event::EventKind;
 
 const COLLECTION: &str = "cameras";
@@ -41,7 +43,10 @@ pub struct CameraQuery {
     pub date_maximum: Option<i64>,
     pub text: Option<String>,
     pub limit: Option<usize>,
-    pub skip: Option<usize>,
+    pub cursor: Option<String>,
+    pub order: Option<PageOrder>, // By name, alphabetical when not set
+    #[serde(skip)]
+    pub scope: Option<Vec<String>>, // Clusters of the caller, set for everyone but super admins
 }
 
 impl Camera {
@@ -80,11 +85,8 @@ impl Camera {
 
         let _ = Evidence::delete_many(
             &&EvidenceQuery {
-                cluster_id: None,
-                processor_id: None,
                 camera_id: Some(self.id.clone()),
-                date_minimum: None,
-                date_maximum: None,
+                ..Default::default()
             },
             db,
         )
@@ -146,11 +148,6 @@ impl Camera {
             }
         });
 
-        if let Some(skip) = query.skip {
-            pipeline.push(doc! {
-                "$skip": to_bson::<usize>(&skip).unwrap()
-            });
-        }
         if let Some(limit) = query.limit {
             pipeline.push(doc! {
                 "$limit": to_bson::<usize>(&limit).unwrap()
@@ -1,11 +1,21 @@
+use std::fs::metadata;
+
+use chrono::Utc;
 use futures::StreamExt;
 use mongodb::{
     Database,
     bson::{doc, to_bson},
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
 
+use crate::models::{
+    evidence::{Evidence, EvidenceQuery},
+    report::{Report, ReportSchedule},
+    subscriber::SubscriberChannel,
+    user::UserRole,
+};
+
 use super::event::EventKind;
 
 const COLLECTION: &str = "clusters";
@@ -13,11 +23,39 @@ const COLLECTION: &str = "clusters";
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ClusterRequest {
     pub name: String,
+    // Kept as is when not set, removed when null
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub retention: Option<Option<i64>>,
+    pub report: Option<Vec<ReportSchedule>>, // Kept as is when not set
+    pub escalation: Option<Vec<ClusterEscalation>>, // Kept as is when not set
 }
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Cluster {
     pub id: String,
     pub name: String,
+    pub retention: Option<i64>, // Maximum evidence age in milliseconds, kept forever if None
+    #[serde(default)]
+    pub report: Vec<ReportSchedule>, // Reports generated in the background
+    #[serde(default)]
+    pub escalation: Vec<ClusterEscalation>, // Tiers alerted in order, earliest first
+}
+
+// A tier of users alerted when an evidence is still open some time after it was caught
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClusterEscalation {
+    pub delay: i64, // Milliseconds after the evidence
+    pub role: UserRole,
+    pub channel: Option<SubscriberChannel>, // Every channel of the users when not set
+}
+
+// Evidence that is (or would be) removed by a cluster's retention policy
+#[derive(Debug, Serialize)]
+pub struct ClusterRetention {
+    pub cluster_id: String,
+    pub retention: Option<i64>,
+    pub timestamp: i64, // Evidence older than this is expired
+    pub evidence_id: Vec<String>,
+    pub size: u64, // Bytes of evidence images
 }
 
 #[derive(Clone, Deserialize)]
@@ -33,7 +71,47 @@ impl From<ClusterRequest> for Cluster {
         Self {
             id: Uuid::new_v4().to_string(),
             name: a.name,
+            retention: a.retention.flatten(),
+            report: a.report.unwrap_or_default(),
+            escalation: a.escalation.unwrap_or_default(),
+        }
+    }
+}
+
+// Tells a field set to null apart from one left out, which stays None
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl ClusterRequest {
+    pub fn check(&self) -> Result<(), EventKind> {
+        if let Some(Some(retention)) = self.retention
+            && retention <= 0
+        {
+            return Err(EventKind::InvalidRange);
         }
+        if let Some(escalation) = &self.escalation {
+            ClusterEscalation::check(escalation)?;
+        }
+        Ok(())
+    }
+}
+
+impl ClusterEscalation {
+    // Each tier has to come strictly after the one before it
+    pub fn check(escalation: &[Self]) -> Result<(), EventKind> {
+        let mut delay = 0;
+        for tier in escalation.iter() {
+            if tier.delay <= delay {
+                return Err(EventKind::InvalidRange);
+            }
+            delay = tier.delay;
+        }
+        Ok(())
     }
 }
 
@@ -55,6 +133,15 @@ impl Cluster {
         let collection = db.collection::<Self>(COLLECTION);
 
         self.name = request.name;
+        if let Some(retention) = request.retention {
+            self.retention = retention;
+        }
+        if let Some(report) = request.report {
+            self.report = report;
+        }
+        if let Some(escalation) = request.escalation {
+            self.escalation = escalation;
+        }
 
         if collection
             .update_one(
@@ -73,6 +160,8 @@ impl Cluster {
     pub async fn delete(&self, db: &Database) -> Result<(), EventKind> {
         let collection = db.collection::<Self>(COLLECTION);
 
+        let _ = Report::delete_many(&self.id, db).await;
+
         if collection
             .delete_one(doc! { "id": &self.id }, None)
             .await
@@ -117,4 +206,79 @@ impl Cluster {
             }
         }
     }
+    // Deletes expired evidence, or only reports it when dry_run is set
+    pub async fn expire(
+        &self,
+        dry_run: bool,
+        db: &Database,
+    ) -> Result<ClusterRetention, EventKind> {
+        let timestamp = self
+            .retention
+            .and_then(|v| Utc::now().timestamp_millis().checked_sub(v))
+            .unwrap_or(i64::MIN);
+
+        let mut retention = ClusterRetention {
+            cluster_id: self.id.clone(),
+            retention: self.retention,
+            timestamp,
+            evidence_id: Vec::new(),
+            size: 0,
+        };
+
+        // A retention saved before it was validated is ignored rather than wiping the cluster
+        if self.retention.is_none_or(|v| v <= 0) {
+            return Ok(retention);
+        }
+
+        let query = EvidenceQuery {
+            cluster_id: Some(self.id.clone()),
+            date_maximum: Some(timestamp),
+            ..Default::default()
+        };
+
+        let evidence_id = match Evidence::find_many(&query, db).await {
+            Ok(v) => v.into_iter().map(|e| e.id).collect::<Vec<String>>(),
+            Err(EventKind::NotFound) => return Ok(retention),
+            Err(e) => return Err(e),
+        };
+        retention.size = evidence_id
+            .iter()
+            .flat_map(|id| Evidence::file(id))
+            .filter_map(|file| metadata(file).ok())
+            .map(|m| m.len())
+            .sum();
+
+        retention.evidence_id = if dry_run {
+            evidence_id
+        } else {
+            Evidence::delete_many(&query, db).await?
+        };
+
+        Ok(retention)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClusterRequest;
+
+    fn request(value: serde_json::Value) -> ClusterRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn request_tells_cleared_retention_from_omitted() {
+        let omitted = request(serde_json::json!({ "name": "north" }));
+        assert_eq!(omitted.retention, None);
+        assert!(omitted.check().is_ok());
+
+        let cleared = request(serde_json::json!({ "name": "north", "retention": null }));
+        assert_eq!(cleared.retention, Some(None));
+        assert!(cleared.check().is_ok());
+
+        for retention in [0, -1, i64::MIN] {
+            let invalid = request(serde_json::json!({ "name": "north", "retention": retention }));
+            assert!(invalid.check().is_err());
+        }
+    }
 }
@@ -0,0 +1,382 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+};
+
+use futures::StreamExt;
+use mongodb::{
+    Database,
+    bson::{Document, doc, to_bson},
+    options::FindOptions,
+};
+use serde::Serialize;
+use zip::ZipWriter;
+
+use crate::models::{
+    archive::{self, ArchiveContent, ArchiveWriter, failed},
+    evidence::{
+        Evidence, EvidencePersonEquipmentLabel, EvidencePersonPartLabel, EvidencePersonViolation,
+        EvidenceStatus,
+    },
+};
+
+use super::event::EventKind;
+
+const COLLECTION: &str = "evidences";
+// Class ids of the labels, in the order of data.yaml
+pub const DATASET_CLASS: [&str; 15] = [
+    "person",
+    "head",
+    "hand",
+    "face",
+    "foot",
+    "ear",
+    "hardhat",
+    "gloves",
+    "shoes",
+    "safetyvest",
+    "safetysuit",
+    "facemask",
+    "faceguard",
+    "earmuffs",
+    "glasses",
+];
+// Evidence held out for validation out of 256, picked by id so a later export keeps the split
+const DATASET_VALIDATION: u8 = 51;
+// Missing equipment violations, the equipment and the part it is worn on
+const DATASET_EQUIPMENT: [(
+    EvidencePersonViolation,
+    EvidencePersonEquipmentLabel,
+    Option<EvidencePersonPartLabel>,
+); 6] = [
+    (
+        EvidencePersonViolation::MissingHardhat,
+        EvidencePersonEquipmentLabel::Hardhat,
+        Some(EvidencePersonPartLabel::Head),
+    ),
+    (
+        EvidencePersonViolation::MissingGloves,
+        EvidencePersonEquipmentLabel::Gloves,
+        Some(EvidencePersonPartLabel::Hand),
+    ),
+    (
+        EvidencePersonViolation::MissingShoes,
+        EvidencePersonEquipmentLabel::Shoes,
+        Some(EvidencePersonPartLabel::Foot),
+    ),
+    (
+        EvidencePersonViolation::MissingFacemask,
+        EvidencePersonEquipmentLabel::Facemask,
+        Some(EvidencePersonPartLabel::Face),
+    ),
+    (
+        EvidencePersonViolation::MissingEarmuffs,
+        EvidencePersonEquipmentLabel::Earmuffs,
+        Some(EvidencePersonPartLabel::Ear),
+    ),
+    (
+        EvidencePersonViolation::MissingSafetyvest,
+        EvidencePersonEquipmentLabel::Safetyvest,
+        None,
+    ),
+];
+
+#[derive(Debug, Default, Serialize)]
+pub struct DatasetSummary {
+    pub evidence_count: u64,
+    pub skipped_count: u64, // Without an image, or with a correction that has no box to apply to
+    pub size: u64,          // Bytes of the archive
+}
+
+// Evidence a reviewer has looked at, the only evidence whose labels can be trusted
+pub fn create_reviewed_filter() -> Document {
+    doc! {
+        "$or": [
+            {
+                "status": {
+                    "$in": to_bson(&[
+                        EvidenceStatus::Acknowledged,
+                        EvidenceStatus::FalsePositive,
+                        EvidenceStatus::Resolved,
+                    ])
+                    .unwrap()
+                }
+            },
+            { "correction.0": { "$exists": true } },
+        ]
+    }
+}
+
+// YOLO label lines of the evidence with its review applied. Equipment a reviewer says was worn
+// takes the box of the part it is worn on, equipment a reviewer says is missing was a false
+// detection and is left out. None when such equipment has no part to take a box from.
+pub fn create_labels(evidence: &Evidence) -> Option<Vec<String>> {
+    let mut labels = Vec::new();
+
+    for person in evidence.person.iter() {
+        let violation = evidence.reviewed_violation(person);
+
+        labels.push(create_label("person", &person.bbox));
+        for part in person.part.iter() {
+            labels.push(create_label(&label_name(&part.label), &part.bbox));
+        }
+
+        for equipment in person.equipment.iter() {
+            let false_detection = DATASET_EQUIPMENT.iter().any(|(missing, label, _)| {
+                *label == equipment.label
+                    && violation.contains(missing)
+                    && !person.violation.contains(missing)
+            });
+            if !false_detection {
+                labels.push(create_label(&label_name(&equipment.label), &equipment.bbox));
+            }
+        }
+
+        for (missing, equipment, part) in DATASET_EQUIPMENT {
+            if !person.violation.contains(&missing) || violation.contains(&missing) {
+                continue;
+            }
+            let part = part?;
+            let bbox = person
+                .part
+                .iter()
+                .filter(|v| v.label == part)
+                .map(|v| v.bbox)
+                .collect::<Vec<_>>();
+            if bbox.is_empty() {
+                return None;
+            }
+            for bbox in bbox {
+                labels.push(create_label(&label_name(&equipment), &bbox));
+            }
+        }
+    }
+
+    Some(labels)
+}
+
+// Archive of the reviewed evidence matching the filter, laid out like the Roboflow YOLOv8
+// exports train.ipynb downloads. Evidence is read from a cursor and handed to the archive
+// writer, as for evidence exports.
+pub async fn write(
+    filter: Document,
+    file: &str,
+    db: &Database,
+) -> Result<DatasetSummary, EventKind> {
+    let collection = db.collection::<Evidence>(COLLECTION);
+
+    let mut cursor = match collection
+        .find(
+            doc! { "$and": [filter, create_reviewed_filter()] },
+            FindOptions::builder()
+                .sort(doc! { "timestamp": 1, "id": 1 })
+                .build(),
+        )
+        .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            println!("ERROR: {:?}", e);
+            return Err(EventKind::FindingFailed);
+        }
+    };
+
+    let writer = ArchiveWriter::new(file, DatasetArchive::default());
+
+    let mut read = Ok(());
+    while let Some(evidence) = cursor.next().await {
+        match evidence {
+            Ok(v) => {
+                // The writer stopped, its error is returned below
+                if !writer.send(v).await {
+                    break;
+                }
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                read = Err(EventKind::SavingFailed);
+                break;
+            }
+        }
+    }
+
+    let written = writer.finish().await;
+    read.and(written.map(|(v, size)| DatasetSummary { size, ..v.summary }))
+}
+
+// Images and labels split into training and validation, after a data.yaml naming the classes
+#[derive(Default)]
+struct DatasetArchive {
+    summary: DatasetSummary,
+}
+
+impl ArchiveContent for DatasetArchive {
+    type Item = Evidence;
+
+    fn start(&mut self, archive: &mut ZipWriter<File>) -> Result<(), EventKind> {
+        archive
+            .start_file("data.yaml", archive::deflated())
+            .map_err(|e| failed(&e))?;
+        write!(
+            archive,
+            "train: ../train/images\nval: ../valid/images\n\nnc: {}\nnames: {:?}\n",
+            DATASET_CLASS.len(),
+            DATASET_CLASS
+        )
+        .map_err(|e| failed(&e))
+    }
+    fn entry(
+        &mut self,
+        archive: &mut ZipWriter<File>,
+        evidence: Evidence,
+    ) -> Result<(), EventKind> {
+        let (labels, mut image) = match (
+            create_labels(&evidence),
+            File::open(format!("./evidence/{}.jpg", evidence.id)),
+        ) {
+            (Some(labels), Ok(image)) => (labels, image),
+            _ => {
+                self.summary.skipped_count += 1;
+                return Ok(());
+            }
+        };
+        let split = match evidence
+            .id
+            .get(..2)
+            .and_then(|v| u8::from_str_radix(v, 16).ok())
+        {
+            Some(v) if v < DATASET_VALIDATION => "valid",
+            _ => "train",
+        };
+
+        archive
+            .start_file(
+                format!("{}/images/{}.jpg", split, evidence.id),
+                archive::stored(),
+            )
+            .map_err(|e| failed(&e))?;
+        io::copy(&mut image, archive).map_err(|e| failed(&e))?;
+        archive
+            .start_file(
+                format!("{}/labels/{}.txt", split, evidence.id),
+                archive::deflated(),
+            )
+            .map_err(|e| failed(&e))?;
+        archive
+            .write_all(labels.join("\n").as_bytes())
+            .map_err(|e| failed(&e))?;
+
+        self.summary.evidence_count += 1;
+        Ok(())
+    }
+}
+
+// Class id and box in YOLO format: center, width and height relative to the image
+fn create_label(name: &str, bbox: &[f32; 4]) -> String {
+    let class = DATASET_CLASS.iter().position(|v| *v == name).unwrap();
+    let [x1, y1, x2, y2] = bbox.map(|v| v.clamp(0.0, 1.0));
+
+    format!(
+        "{} {:.6} {:.6} {:.6} {:.6}",
+        class,
+        (x1 + x2) / 2.0,
+        (y1 + y2) / 2.0,
+        x2 - x1,
+        y2 - y1
+    )
+}
+
+fn label_name<T: Serialize>(label: &T) -> String {
+    serde_json::to_value(label)
+        .ok()
+        .and_then(|v| v.as_str().map(|v| v.to_string()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        models::evidence::{
+            Evidence, EvidenceCorrection, EvidencePerson, EvidencePersonEquipment,
+            EvidencePersonEquipmentLabel, EvidencePersonPart, EvidencePersonPartLabel,
+            EvidencePersonViolation, EvidenceStatus,
+        },
+        views::user::UserRef,
+    };
+
+    use super::create_labels;
+
+    fn evidence(violation: Vec<EvidencePersonViolation>) -> Evidence {
+        Evidence {
+            id: String::from("evidence"),
+            cluster_id: String::from("cluster"),
+            processor_id: String::from("processor"),
+            camera_id: String::from("camera"),
+            frame_id: String::from("frame"),
+            timestamp: 0,
+            person: vec![EvidencePerson {
+                id: String::from("person"),
+                bbox: [0.2, 0.2, 0.6, 1.2],
+                confidence: 0.9,
+                part: vec![EvidencePersonPart {
+                    label: EvidencePersonPartLabel::Head,
+                    bbox: [0.3, 0.2, 0.5, 0.4],
+                    confidence: 0.9,
+                }],
+                equipment: vec![EvidencePersonEquipment {
+                    label: EvidencePersonEquipmentLabel::Gloves,
+                    bbox: [0.2, 0.6, 0.3, 0.7],
+                    confidence: 0.9,
+                }],
+                violation,
+                confirmation: Vec::new(),
+            }],
+            clip: None,
+            status: EvidenceStatus::Acknowledged,
+            review: Vec::new(),
+            correction: Vec::new(),
+            escalation: Vec::new(),
+        }
+    }
+    fn correct(evidence: &mut Evidence, violation: Vec<EvidencePersonViolation>) {
+        evidence.correction.push(EvidenceCorrection {
+            person_id: String::from("person"),
+            violation,
+            user: UserRef {
+                id: String::from("user"),
+                name: String::from("user"),
+            },
+            timestamp: 0,
+        });
+    }
+
+    #[test]
+    fn labels_apply_the_review() {
+        // Boxes are clamped to the image
+        let labels = create_labels(&evidence(Vec::new())).unwrap();
+        assert_eq!(
+            labels,
+            [
+                "0 0.400000 0.600000 0.400000 0.800000",
+                "1 0.400000 0.300000 0.200000 0.200000",
+                "7 0.250000 0.650000 0.100000 0.100000",
+            ]
+        );
+
+        // A hardhat the detector missed takes the box of the head
+        let mut corrected = evidence(vec![EvidencePersonViolation::MissingHardhat]);
+        correct(&mut corrected, Vec::new());
+        let labels = create_labels(&corrected).unwrap();
+        assert_eq!(labels[3], "6 0.400000 0.300000 0.200000 0.200000");
+
+        // Gloves the reviewer says are missing were a false detection
+        let mut corrected = evidence(Vec::new());
+        correct(&mut corrected, vec![EvidencePersonViolation::MissingGloves]);
+        assert_eq!(create_labels(&corrected).unwrap().len(), 2);
+
+        // A false positive clears the violations, but shoes have no foot to take a box from
+        let mut dismissed = evidence(vec![EvidencePersonViolation::MissingShoes]);
+        dismissed.status = EvidenceStatus::FalsePositive;
+        assert_eq!(create_labels(&dismissed), None);
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::Utc;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::subscriber::{Subscriber, SubscriberChannel};
+
+use super::event::EventKind;
+
+const COLLECTION: &str = "deliveries";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Sent,
+    Failed,   // Every attempt failed
+    Rejected, // The recipient is gone, its subscriber was removed
+}
+
+// Outcome of sending the alert of an evidence to a subscriber
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Delivery {
+    pub id: String,
+    pub evidence_id: String,
+    pub subscriber_id: String,
+    pub user_id: String,
+    pub channel: SubscriberChannel,
+    pub status: DeliveryStatus,
+    pub attempt: u32,
+    pub error: Option<String>, // Of the last attempt
+    pub timestamp: i64,
+}
+
+impl Delivery {
+    pub fn new(
+        evidence_id: &str,
+        subscriber: &Subscriber,
+        status: DeliveryStatus,
+        attempt: u32,
+        error: Option<String>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            evidence_id: evidence_id.to_string(),
+            subscriber_id: subscriber.id.clone(),
+            user_id: subscriber.user_id.clone(),
+            channel: subscriber.kind.channel(),
+            status,
+            attempt,
+            error,
+            timestamp: Utc::now().timestamp_millis(),
+        }
+    }
+
+    pub async fn save(&self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection.insert_one(self, None).await.is_ok() {
+            Ok(())
+        } else {
+            Err(EventKind::SavingFailed)
+        }
+    }
+}
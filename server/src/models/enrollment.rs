@@ -0,0 +1,293 @@
+use chrono::Utc;
+use futures::StreamExt;
+use mongodb::{
+    Database,
+    bson::doc,
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{
+    processor::{Processor, ProcessorRequest},
+    signature::Signature,
+};
+
+use super::event::EventKind;
+
+const COLLECTION: &str = "enrollments";
+// How long a code stays valid when the request does not say
+const ENROLLMENT_DURATION: i64 = 15 * 60 * 1000;
+const ENROLLMENT_DURATION_MAXIMUM: i64 = 24 * 60 * 60 * 1000;
+// Codes are typed by hand on the processor, so characters that look alike are left out
+const ENROLLMENT_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const ENROLLMENT_CODE_LENGTH: usize = 8;
+
+#[derive(Debug, Deserialize)]
+pub struct EnrollmentRequest {
+    pub duration: Option<i64>, // Milliseconds the code stays valid
+}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Enrollment {
+    pub id: String,
+    pub cluster_id: String,
+    pub code: String,
+    pub user_id: String, // User who issued the code
+    pub timestamp: i64,
+    pub expiry: i64,
+    pub processor_id: Option<String>, // Processor that redeemed the code
+    pub enrolled_timestamp: Option<i64>,
+}
+// Sent by a processor to exchange a code for its webhook configuration
+#[derive(Debug, Deserialize)]
+pub struct EnrollmentRedemption {
+    pub code: String,
+    pub processor: ProcessorRequest,
+}
+#[derive(Debug, Serialize)]
+pub struct EnrollmentCredential {
+    pub cluster_id: String,
+    pub processor_id: String,
+    pub secret: String,
+    pub path: EnrollmentCredentialPath,
+}
+#[derive(Debug, Serialize)]
+pub struct EnrollmentCredentialPath {
+    pub evidence: String,
+    pub update: String,
+}
+
+impl Enrollment {
+    pub fn new(cluster_id: String, user_id: String, request: EnrollmentRequest) -> Self {
+        let timestamp = Utc::now().timestamp_millis();
+        let duration = request
+            .duration
+            .unwrap_or(ENROLLMENT_DURATION)
+            .clamp(1000, ENROLLMENT_DURATION_MAXIMUM);
+
+        let mut rng = rand::thread_rng();
+        let code = (0..ENROLLMENT_CODE_LENGTH)
+            .map(|_| ENROLLMENT_ALPHABET[rng.gen_range(0..ENROLLMENT_ALPHABET.len())] as char)
+            .collect::<String>();
+
+        Self {
+            id: Uuid::new_v4().to_string(),
+            cluster_id,
+            code,
+            user_id,
+            timestamp,
+            expiry: timestamp + duration,
+            processor_id: None,
+            enrolled_timestamp: None,
+        }
+    }
+
+    pub async fn save(&self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection.insert_one(self, None).await.is_ok() {
+            Ok(())
+        } else {
+            Err(EventKind::SavingFailed)
+        }
+    }
+    pub async fn delete(&self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection
+            .delete_one(doc! { "id": &self.id }, None)
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::DeletingFailed)
+        }
+    }
+    pub async fn find_by_id(id: &String, db: &Database) -> Result<Self, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection.find_one(doc! { "id": id }, None).await {
+            Ok(Some(v)) => Ok(v),
+            Ok(_) => Err(EventKind::NotFound),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    // An unused, unexpired code
+    pub async fn find_by_code(code: &str, db: &Database) -> Result<Self, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .find_one(
+                doc! {
+                    "code": code.to_uppercase(),
+                    "processor_id": null,
+                    "expiry": { "$gt": Utc::now().timestamp_millis() },
+                },
+                None,
+            )
+            .await
+        {
+            Ok(Some(v)) => Ok(v),
+            Ok(_) => Err(EventKind::Unauthorized),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    pub async fn find_many(cluster_id: &String, db: &Database) -> Result<Vec<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .find(doc! { "cluster_id": cluster_id }, None)
+            .await
+        {
+            Ok(mut cursor) => {
+                let mut enrollments = Vec::new();
+                while let Some(Ok(enrollment)) = cursor.next().await {
+                    enrollments.push(enrollment);
+                }
+                enrollments.sort_by_key(|e| -e.timestamp);
+                Ok(enrollments)
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    // Mark an unused, unexpired code as redeemed by the processor, in a single update so a
+    // code can never be used twice
+    pub async fn redeem(
+        code: &str,
+        processor_id: &String,
+        db: &Database,
+    ) -> Result<Self, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+        let timestamp = Utc::now().timestamp_millis();
+
+        match collection
+            .find_one_and_update(
+                doc! {
+                    "code": code.to_uppercase(),
+                    "processor_id": null,
+                    "expiry": { "$gt": timestamp },
+                },
+                doc! {
+                    "$set": {
+                        "processor_id": processor_id,
+                        "enrolled_timestamp": timestamp,
+                    }
+                },
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+        {
+            Ok(Some(v)) => Ok(v),
+            Ok(_) => Err(EventKind::Unauthorized),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::UpdatingFailed)
+            }
+        }
+    }
+    // Redeems the code and saves the processor in the code's cluster with a new secret. A
+    // processor enrolled in another cluster is never moved, one already paired in this cluster
+    // only pairs again when the request was signed with its current secret.
+    pub async fn enroll(
+        code: &str,
+        request: ProcessorRequest,
+        proven: bool,
+        db: &Database,
+    ) -> Result<(Processor, String), EventKind> {
+        let enrollment = Self::find_by_code(code, db).await?;
+        match Processor::find_by_id(&request.id, db).await {
+            Ok(v) if v.cluster_id != enrollment.cluster_id => {
+                return Err(EventKind::AlreadyEnrolled);
+            }
+            Ok(v) if v.secret.is_some() && !proven => return Err(EventKind::Unauthorized),
+            Ok(_) | Err(EventKind::NotFound) => (),
+            Err(e) => return Err(e),
+        }
+
+        let enrollment = Self::redeem(code, &request.id, db).await?;
+        let secret = Signature::secret();
+        let processor = Processor {
+            id: request.id,
+            cluster_id: enrollment.cluster_id,
+            name: request.name,
+            model: request.model,
+            address: request.address,
+            version: 0, // Older than anything the processor sends, so its first sync is applied
+            secret: Some(secret.clone()),
+        };
+        processor.save(db).await?;
+
+        Ok((processor, secret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database,
+        models::{
+            enrollment::EnrollmentRequest,
+            event::EventKind,
+            processor::{Processor, ProcessorAddress, ProcessorRequest},
+        },
+    };
+
+    use super::Enrollment;
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn enroll_refuses_processors_of_other_clusters() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+        database::test::seed("south", &db).await;
+
+        let enrollment = Enrollment::new(
+            String::from("north"),
+            String::from("user"),
+            EnrollmentRequest { duration: None },
+        );
+        enrollment.save(&db).await.unwrap();
+        let request = |id: &str| ProcessorRequest {
+            id: id.to_string(),
+            name: id.to_string(),
+            model: String::from("yolov8n.hef"),
+            address: ProcessorAddress {
+                host: [127, 0, 0, 1],
+                port: 8000,
+            },
+            version: 0,
+        };
+
+        // Even a valid signature does not move the processor of another cluster
+        let result =
+            Enrollment::enroll(&enrollment.code, request("south_processor"), true, &db).await;
+        assert!(matches!(result, Err(EventKind::AlreadyEnrolled)));
+        let processor = Processor::find_by_id(&String::from("south_processor"), &db)
+            .await
+            .unwrap();
+        assert_eq!(processor.cluster_id, "south");
+        assert_eq!(processor.secret, None);
+
+        // The code was not used up by the refused attempt
+        let (processor, secret) = Enrollment::enroll(&enrollment.code, request("new"), false, &db)
+            .await
+            .unwrap();
+        assert_eq!(processor.cluster_id, "north");
+        assert_eq!(processor.secret, Some(secret));
+
+        db.drop(None).await.unwrap();
+    }
+}
@@ -31,6 +31,14 @@ pub enum EventKind {
     InvalidCombination,
     InvalidToken,
     InvalidId,
+    Unauthorized,
+    InvalidCursor,
+    InvalidRange,
+    RenderingFailed,
+    InvalidTransition,
+    MissingComment,
+    InvalidTimezone,
+    AlreadyEnrolled,
 }
 
 impl EventKind {
@@ -47,6 +55,14 @@ impl EventKind {
             EventKind::InvalidCombination => String::from("InvalidCombination"),
             EventKind::InvalidToken => String::from("InvalidToken"),
             EventKind::InvalidId => String::from("InvalidId"),
+            EventKind::Unauthorized => String::from("Unauthorized"),
+            EventKind::InvalidCursor => String::from("InvalidCursor"),
+            EventKind::InvalidRange => String::from("InvalidRange"),
+            EventKind::RenderingFailed => String::from("RenderingFailed"),
+            EventKind::InvalidTransition => String::from("InvalidTransition"),
+            EventKind::MissingComment => String::from("MissingComment"),
+            EventKind::InvalidTimezone => String::from("InvalidTimezone"),
+            EventKind::AlreadyEnrolled => String::from("AlreadyEnrolled"),
         }
     }
 }
@@ -1,8 +1,19 @@
+use std::fs::remove_file;
+
+use futures::StreamExt;
 use mongodb::{
-    Database,
-    bson::{doc, to_bson},
+    Database, IndexModel,
+    bson::{Bson, Document, doc, to_bson},
+};
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{DeserializeOwned, Error, IntoDeserializer, value},
+};
+
+use crate::{
+    models::{subscriber::SubscriberChannel, user::UserRole},
+    views::{page::PageOrder, user::UserRef},
 };
-use serde::{Deserialize, Serialize};
 
 use super::event::EventKind;
 
@@ -25,6 +36,16 @@ pub struct Evidence {
     pub frame_id: String,
     pub timestamp: i64,
     pub person: Vec<EvidencePerson>,
+    #[serde(default)]
+    pub clip: Option<String>, // File name of the video clip under /static
+    #[serde(default)]
+    pub status: EvidenceStatus,
+    #[serde(default)]
+    pub review: Vec<EvidenceReview>, // Status transitions, oldest first
+    #[serde(default)]
+    pub correction: Vec<EvidenceCorrection>, // At most one per person, the latest
+    #[serde(default)]
+    pub escalation: Vec<EvidenceEscalation>, // Tiers alerted while open, in the cluster's order
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EvidencePerson {
@@ -34,6 +55,8 @@ pub struct EvidencePerson {
     pub part: Vec<EvidencePersonPart>,
     pub equipment: Vec<EvidencePersonEquipment>,
     pub violation: Vec<EvidencePersonViolation>,
+    #[serde(default)]
+    pub confirmation: Vec<EvidencePersonConfirmation>,
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EvidencePersonPart {
@@ -48,6 +71,13 @@ pub struct EvidencePersonEquipment {
     pub confidence: f32,
 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvidencePersonConfirmation {
+    pub violation: EvidencePersonViolation,
+    pub frame: u32,     // Frames the violation was observed in
+    pub timestamp: i64, // When the violation was first observed
+    pub duration: i64,  // Milliseconds the violation persisted before it was confirmed
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum EvidencePersonPartLabel {
     Head,
@@ -56,7 +86,7 @@ pub enum EvidencePersonPartLabel {
     Foot,
     Ear,
 }
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum EvidencePersonEquipmentLabel {
     Hardhat,
@@ -69,7 +99,7 @@ pub enum EvidencePersonEquipmentLabel {
     Earmuffs,
     Glasses,
 }
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum EvidencePersonViolation {
     MissingHardhat,
@@ -85,16 +115,153 @@ pub enum EvidencePersonViolation {
     ImproperlyWornEarmuffs,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvidenceSeverity {
+    #[default]
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvidenceStatus {
+    #[default]
+    Open, // Not reviewed yet
+    Acknowledged,
+    FalsePositive,
+    Resolved, // Corrective action was taken, described in the comment
+}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvidenceReview {
+    pub status: EvidenceStatus,
+    pub previous: EvidenceStatus,
+    pub user: UserRef, // User who made the transition
+    pub comment: Option<String>,
+    pub timestamp: i64,
+}
+#[derive(Debug, Deserialize)]
+pub struct EvidenceReviewRequest {
+    pub status: EvidenceStatus,
+    pub comment: Option<String>,
+}
+// Violations a reviewer says a person actually has, replacing what the processor reported
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvidenceCorrection {
+    pub person_id: String,
+    pub violation: Vec<EvidencePersonViolation>,
+    pub user: UserRef,
+    pub timestamp: i64,
+}
+// A tier of the cluster's escalation policy alerted because nobody acknowledged the evidence
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvidenceEscalation {
+    pub role: UserRole,
+    pub channel: Option<SubscriberChannel>,
+    pub user: Vec<UserRef>, // Users of the tier at the time, none when the tier was empty
+    pub timestamp: i64,
+}
 #[derive(Debug, Deserialize)]
+pub struct EvidenceCorrectionRequest {
+    pub person_id: String,
+    pub violation: Vec<EvidencePersonViolation>,
+}
+
+#[derive(Debug, Default, Deserialize)]
 pub struct EvidenceQuery {
     pub cluster_id: Option<String>,
     pub processor_id: Option<String>,
     pub camera_id: Option<String>,
     pub date_minimum: Option<i64>,
     pub date_maximum: Option<i64>,
+    // Lists are comma separated, e.g. violation=missing_hardhat,missing_gloves
+    #[serde(default, deserialize_with = "deserialize_list")]
+    pub violation: Option<Vec<EvidencePersonViolation>>,
+    pub violation_match: Option<EvidenceQueryMatch>, // Any of the violations when not set
+    #[serde(default, deserialize_with = "deserialize_list")]
+    pub equipment: Option<Vec<EvidencePersonEquipmentLabel>>, // Worn by a person
+    #[serde(default, deserialize_with = "deserialize_list")]
+    pub equipment_missing: Option<Vec<EvidencePersonEquipmentLabel>>, // Not worn by a person
+    pub person_minimum: Option<usize>,
+    pub confidence_minimum: Option<f32>, // Of a person in the evidence
+    #[serde(default, deserialize_with = "deserialize_list")]
+    pub status: Option<Vec<EvidenceStatus>>,
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub order: Option<PageOrder>, // By timestamp, newest first when not set
+    #[serde(skip)]
+    pub scope: Option<Vec<String>>, // Clusters of the caller, set for everyone but super admins
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvidenceQueryMatch {
+    Any, // Evidence with at least one of the violations
+    All, // Evidence with every violation, across its persons
+}
+
+fn deserialize_list<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(list) => list
+            .split(',')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| T::deserialize(IntoDeserializer::<value::Error>::into_deserializer(v)))
+            .collect::<Result<Vec<T>, _>>()
+            .map(Some)
+            .map_err(D::Error::custom),
+        None => Ok(None),
+    }
+}
+
+impl EvidencePersonViolation {
+    // Missing head protection and visibility are the most dangerous, equipment that is worn but
+    // worn wrongly the least
+    pub fn severity(&self) -> EvidenceSeverity {
+        match self {
+            EvidencePersonViolation::MissingHardhat
+            | EvidencePersonViolation::MissingSafetyvest => EvidenceSeverity::High,
+            EvidencePersonViolation::MissingGloves
+            | EvidencePersonViolation::MissingShoes
+            | EvidencePersonViolation::MissingFacemask
+            | EvidencePersonViolation::MissingEarmuffs => EvidenceSeverity::Medium,
+            EvidencePersonViolation::ImproperlyWornHardhat
+            | EvidencePersonViolation::ImproperlyWornGloves
+            | EvidencePersonViolation::ImproperlyWornShoes
+            | EvidencePersonViolation::ImproperlyWornFacemask
+            | EvidencePersonViolation::ImproperlyWornEarmuffs => EvidenceSeverity::Low,
+        }
+    }
+}
+
+impl EvidenceStatus {
+    // Open evidence can be moved to any review status, reviewed evidence can be reopened and an
+    // acknowledgement can still be settled
+    pub fn allows(&self, status: EvidenceStatus) -> bool {
+        use EvidenceStatus::*;
+
+        matches!(
+            (self, status),
+            (Open, Acknowledged | FalsePositive | Resolved)
+                | (Acknowledged, FalsePositive | Resolved | Open)
+                | (FalsePositive | Resolved, Open)
+        )
+    }
 }
 
 impl Evidence {
+    // Files kept in ./evidence for an evidence, only the image is always present
+    pub fn file(id: &str) -> [String; 3] {
+        [
+            format!("./evidence/{}.jpg", id),
+            format!("./evidence/{}.annotated.jpg", id),
+            format!("./evidence/{}.avi", id),
+        ]
+    }
     pub async fn save(&self, db: &Database) -> Result<(), EventKind> {
         let collection = db.collection::<Self>(COLLECTION);
 
@@ -121,35 +288,250 @@ impl Evidence {
             Err(EventKind::UpdatingFailed)
         }
     }
-    pub async fn delete_many(query: &EvidenceQuery, db: &Database) -> Result<(), EventKind> {
+    // Moves the evidence to the requested status and records the transition. The update only
+    // applies while the status is still the one the transition was checked against, so two
+    // reviewers cannot both act on the same state.
+    pub async fn review(
+        &mut self,
+        request: EvidenceReviewRequest,
+        user: UserRef,
+        timestamp: i64,
+        db: &Database,
+    ) -> Result<(), EventKind> {
         let collection = db.collection::<Self>(COLLECTION);
 
-        let mut queries = Vec::new();
-
-        if let Some(cluster_id) = &query.cluster_id {
-            queries.push(doc! {
-                "cluster_id": cluster_id
-            });
+        if !self.status.allows(request.status) {
+            return Err(EventKind::InvalidTransition);
         }
-        if let Some(processor_id) = &query.processor_id {
-            queries.push(doc! {
-                "processor_id": processor_id
-            });
+        let comment = request
+            .comment
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        if request.status == EvidenceStatus::Resolved && comment.is_none() {
+            return Err(EventKind::MissingComment);
         }
 
-        if collection
-            .delete_many(
+        let review = EvidenceReview {
+            status: request.status,
+            previous: self.status,
+            user,
+            comment,
+            timestamp,
+        };
+
+        let mut filter = doc! { "id": &self.id };
+        filter.insert(
+            "status",
+            match self.status {
+                // Evidence saved before reviews existed has no status
+                EvidenceStatus::Open => doc! { "$in": [to_bson(&self.status).unwrap(), null] },
+                status => doc! { "$eq": to_bson(&status).unwrap() },
+            },
+        );
+
+        match collection
+            .update_one(
+                filter,
                 doc! {
-                    "$and": queries
+                    "$set": { "status": to_bson(&review.status).unwrap() },
+                    "$push": { "review": to_bson(&review).unwrap() },
                 },
                 None,
             )
             .await
+        {
+            Ok(v) if v.matched_count == 1 => {
+                self.status = review.status;
+                self.review.push(review);
+                Ok(())
+            }
+            Ok(_) => Err(EventKind::InvalidTransition),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::UpdatingFailed)
+            }
+        }
+    }
+    // Records that the next tier of the escalation policy was alerted. The update only applies
+    // while the evidence is still open and no other tier was recorded in the meantime, it returns
+    // whether it did.
+    pub async fn escalate(
+        &mut self,
+        escalation: EvidenceEscalation,
+        db: &Database,
+    ) -> Result<bool, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let filter = doc! {
+            "id": &self.id,
+            "status": { "$in": [to_bson(&EvidenceStatus::Open).unwrap(), null] },
+            "$expr": {
+                "$eq": [{ "$size": { "$ifNull": ["$escalation", []] } }, self.escalation.len() as i64]
+            },
+        };
+
+        match collection
+            .update_one(
+                filter,
+                doc! { "$push": { "escalation": to_bson(&escalation).unwrap() } },
+                None,
+            )
+            .await
+        {
+            Ok(v) if v.matched_count == 1 => {
+                self.escalation.push(escalation);
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::UpdatingFailed)
+            }
+        }
+    }
+    // Open evidence of the cluster caught within the period that has been escalated to exactly
+    // the given number of tiers
+    pub async fn find_many_by_escalation(
+        cluster_id: &String,
+        escalation_count: usize,
+        date_minimum: i64,
+        date_maximum: i64,
+        db: &Database,
+    ) -> Result<Vec<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let filter = doc! {
+            "cluster_id": cluster_id,
+            "timestamp": { "$gte": date_minimum, "$lte": date_maximum },
+            "status": { "$in": [to_bson(&EvidenceStatus::Open).unwrap(), null] },
+            "$expr": {
+                "$eq": [{ "$size": { "$ifNull": ["$escalation", []] } }, escalation_count as i64]
+            },
+        };
+
+        match collection.find(filter, None).await {
+            Ok(mut cursor) => {
+                let mut evidences = Vec::new();
+                while let Some(Ok(evidence)) = cursor.next().await {
+                    evidences.push(evidence);
+                }
+                Ok(evidences)
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    // Records the violations a person actually has, replacing an earlier correction of the person
+    pub async fn correct(
+        &mut self,
+        request: EvidenceCorrectionRequest,
+        user: UserRef,
+        timestamp: i64,
+        db: &Database,
+    ) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if !self.person.iter().any(|p| p.id == request.person_id) {
+            return Err(EventKind::NotFound);
+        }
+
+        let mut violation = Vec::new();
+        for v in request.violation {
+            if !violation.contains(&v) {
+                violation.push(v);
+            }
+        }
+        self.correction.retain(|c| c.person_id != request.person_id);
+        self.correction.push(EvidenceCorrection {
+            person_id: request.person_id,
+            violation,
+            user,
+            timestamp,
+        });
+
+        if collection
+            .update_one(
+                doc! { "id": &self.id },
+                doc! { "$set": { "correction": to_bson(&self.correction).unwrap() } },
+                None,
+            )
+            .await
             .is_ok()
         {
             Ok(())
         } else {
-            Err(EventKind::DeletingFailed)
+            Err(EventKind::UpdatingFailed)
+        }
+    }
+    // Violations of a person after review: the reviewer's correction, none when the whole
+    // evidence was disputed, otherwise what the processor reported
+    pub fn reviewed_violation(&self, person: &EvidencePerson) -> Vec<EvidencePersonViolation> {
+        if let Some(correction) = self.correction.iter().find(|c| c.person_id == person.id) {
+            return correction.violation.clone();
+        }
+        if self.status == EvidenceStatus::FalsePositive {
+            return Vec::new();
+        }
+        person.violation.clone()
+    }
+    // Deletes the matching evidence documents together with their images
+    pub async fn delete_many(
+        query: &EvidenceQuery,
+        db: &Database,
+    ) -> Result<Vec<String>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let queries = Self::create_queries(query);
+        if queries.is_empty() {
+            // Refuse to wipe the whole collection by accident
+            return Err(EventKind::InvalidCombination);
+        }
+
+        let evidences = match Self::find_many(query, db).await {
+            Ok(v) => v,
+            Err(EventKind::NotFound) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let id = evidences.into_iter().map(|e| e.id).collect::<Vec<String>>();
+
+        if collection
+            .delete_many(doc! { "id": { "$in": &id } }, None)
+            .await
+            .is_err()
+        {
+            return Err(EventKind::DeletingFailed);
+        }
+
+        for id in id.iter() {
+            for file in Self::file(id) {
+                let _ = remove_file(file);
+            }
+        }
+
+        Ok(id)
+    }
+    pub async fn find_many(query: &EvidenceQuery, db: &Database) -> Result<Vec<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection.find(Self::create_filter(query), None).await {
+            Ok(mut cursor) => {
+                let mut evidences = Vec::new();
+                while let Some(Ok(evidence)) = cursor.next().await {
+                    evidences.push(evidence);
+                }
+
+                if evidences.is_empty() {
+                    Err(EventKind::NotFound)
+                } else {
+                    Ok(evidences)
+                }
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
         }
     }
     pub async fn find_by_id(id: &String, db: &Database) -> Result<Self, EventKind> {
@@ -164,4 +546,323 @@ impl Evidence {
             }
         }
     }
+
+    // Indexes for the listing, its pagination and the evidence filters
+    pub async fn create_indexes(db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let indexes = [
+            doc! { "id": 1 },
+            doc! { "timestamp": -1, "id": -1 },
+            doc! { "cluster_id": 1, "timestamp": -1, "id": -1 },
+            doc! { "processor_id": 1, "timestamp": -1 },
+            doc! { "camera_id": 1, "timestamp": -1 },
+            doc! { "person.violation": 1, "timestamp": -1 },
+            doc! { "person.equipment.label": 1 },
+            doc! { "person.confidence": 1 },
+            doc! { "status": 1, "timestamp": -1 },
+        ]
+        .into_iter()
+        .map(|keys| IndexModel::builder().keys(keys).build());
+
+        match collection.create_indexes(indexes, None).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::SavingFailed)
+            }
+        }
+    }
+
+    // Collection filter for the query, plain query operators so the indexes above apply
+    pub fn create_filter(query: &EvidenceQuery) -> Document {
+        let queries = Self::create_queries(query);
+        if queries.is_empty() {
+            doc! {}
+        } else {
+            doc! { "$and": queries }
+        }
+    }
+
+    // Helper function to translate the query into collection filters
+    fn create_queries(query: &EvidenceQuery) -> Vec<Document> {
+        let mut queries = Vec::new();
+
+        if let Some(cluster_id) = &query.cluster_id {
+            queries.push(doc! {
+                "cluster_id": cluster_id
+            });
+        }
+        if let Some(scope) = &query.scope {
+            queries.push(doc! {
+                "cluster_id": { "$in": scope }
+            });
+        }
+        if let Some(processor_id) = &query.processor_id {
+            queries.push(doc! {
+                "processor_id": processor_id
+            });
+        }
+        if let Some(camera_id) = &query.camera_id {
+            queries.push(doc! {
+                "camera_id": camera_id
+            });
+        }
+        if let Some(date) = &query.date_minimum {
+            queries.push(doc! {
+                "timestamp": { "$gte": date }
+            });
+        }
+        if let Some(date) = &query.date_maximum {
+            queries.push(doc! {
+                "timestamp": { "$lte": date }
+            });
+        }
+        if let Some(violation) = &query.violation
+            && !violation.is_empty()
+        {
+            let violation = to_bson(violation).unwrap();
+            queries.push(match query.violation_match {
+                Some(EvidenceQueryMatch::All) => doc! {
+                    "person.violation": { "$all": violation }
+                },
+                _ => doc! {
+                    "person.violation": { "$in": violation }
+                },
+            });
+        }
+        if let Some(equipment) = &query.equipment {
+            for label in equipment {
+                queries.push(doc! {
+                    "person.equipment.label": to_bson(label).unwrap()
+                });
+            }
+        }
+        if let Some(equipment) = &query.equipment_missing
+            && !equipment.is_empty()
+        {
+            queries.push(doc! {
+                "person": {
+                    "$elemMatch": {
+                        "equipment.label": { "$nin": to_bson(equipment).unwrap() }
+                    }
+                }
+            });
+        }
+        if let Some(count) = query.person_minimum
+            && count > 0
+        {
+            // The person at index count - 1 exists only when there are at least count persons
+            queries.push(doc! {
+                format!("person.{}", count - 1): { "$exists": true }
+            });
+        }
+        if let Some(confidence) = query.confidence_minimum {
+            queries.push(doc! {
+                "person.confidence": { "$gte": confidence }
+            });
+        }
+        if let Some(status) = &query.status
+            && !status.is_empty()
+        {
+            let mut status = status
+                .iter()
+                .map(|v| to_bson(v).unwrap())
+                .collect::<Vec<_>>();
+            // Evidence saved before reviews existed has no status and is open
+            if status.contains(&to_bson(&EvidenceStatus::Open).unwrap()) {
+                status.push(Bson::Null);
+            }
+            queries.push(doc! {
+                "status": { "$in": status }
+            });
+        }
+
+        queries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{database, models::user::UserRole, views::user::UserRef};
+
+    use super::{
+        Evidence, EvidenceEscalation, EvidenceQuery, EvidenceReviewRequest,
+        EvidenceStatus::{self, *},
+    };
+
+    fn request(status: EvidenceStatus, comment: Option<&str>) -> EvidenceReviewRequest {
+        EvidenceReviewRequest {
+            status,
+            comment: comment.map(String::from),
+        }
+    }
+
+    #[test]
+    fn status_allows_review_transitions() {
+        assert!(Open.allows(Acknowledged));
+        assert!(Open.allows(Resolved));
+        assert!(Acknowledged.allows(FalsePositive));
+        assert!(Resolved.allows(Open));
+
+        assert!(!Open.allows(Open));
+        assert!(!Resolved.allows(FalsePositive));
+        assert!(!FalsePositive.allows(Acknowledged));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn review_records_transitions() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+        let user = UserRef {
+            id: String::from("manager"),
+            name: String::from("Manager"),
+        };
+
+        let mut evidence = Evidence::find_by_id(&String::from("north_evidence"), &db)
+            .await
+            .unwrap();
+        // A stale copy still believes the evidence is open
+        let mut stale = evidence.clone();
+
+        evidence
+            .review(request(Acknowledged, None), user.clone(), 1, &db)
+            .await
+            .unwrap();
+        assert!(
+            evidence
+                .review(request(Resolved, Some(" ")), user.clone(), 2, &db)
+                .await
+                .is_err()
+        );
+        evidence
+            .review(
+                request(Resolved, Some("Hardhats issued")),
+                user.clone(),
+                3,
+                &db,
+            )
+            .await
+            .unwrap();
+        assert!(
+            stale
+                .review(request(FalsePositive, None), user, 4, &db)
+                .await
+                .is_err()
+        );
+
+        let evidence = Evidence::find_by_id(&evidence.id, &db).await.unwrap();
+        assert_eq!(evidence.status, Resolved);
+        assert_eq!(
+            evidence
+                .review
+                .iter()
+                .map(|v| (v.previous, v.status))
+                .collect::<Vec<_>>(),
+            [(Open, Acknowledged), (Acknowledged, Resolved)]
+        );
+        assert_eq!(
+            evidence.review[1].comment.as_deref(),
+            Some("Hardhats issued")
+        );
+
+        let query = |status: &str| {
+            serde_json::from_value::<EvidenceQuery>(serde_json::json!({ "status": status }))
+                .unwrap()
+        };
+        assert_eq!(
+            Evidence::find_many(&query("resolved"), &db)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            Evidence::find_many(&query("open,acknowledged"), &db)
+                .await
+                .is_err()
+        );
+
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn escalate_stops_once_acknowledged() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+        let user = UserRef {
+            id: String::from("manager"),
+            name: String::from("Manager"),
+        };
+        let escalation = |role: UserRole, timestamp: i64| EvidenceEscalation {
+            role,
+            channel: None,
+            user: vec![user.clone()],
+            timestamp,
+        };
+        let cluster_id = String::from("north");
+
+        let mut evidence = Evidence::find_by_id(&String::from("north_evidence"), &db)
+            .await
+            .unwrap();
+        // A stale copy has not seen the first tier yet
+        let mut stale = evidence.clone();
+
+        assert_eq!(
+            Evidence::find_many_by_escalation(&cluster_id, 0, 0, 0, &db)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            evidence
+                .escalate(escalation(UserRole::Officer, 1), &db)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !stale
+                .escalate(escalation(UserRole::Manager, 2), &db)
+                .await
+                .unwrap()
+        );
+        assert!(
+            Evidence::find_many_by_escalation(&cluster_id, 0, 0, 0, &db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        evidence
+            .review(request(Acknowledged, None), user.clone(), 3, &db)
+            .await
+            .unwrap();
+        assert!(
+            !evidence
+                .escalate(escalation(UserRole::Manager, 4), &db)
+                .await
+                .unwrap()
+        );
+        assert!(
+            Evidence::find_many_by_escalation(&cluster_id, 1, 0, 0, &db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let evidence = Evidence::find_by_id(&evidence.id, &db).await.unwrap();
+        assert_eq!(
+            evidence
+                .escalation
+                .iter()
+                .map(|v| v.role)
+                .collect::<Vec<_>>(),
+            [UserRole::Officer]
+        );
+
+        db.drop(None).await.unwrap();
+    }
 }
@@ -0,0 +1,432 @@
+use std::{
+    fs::{File, remove_file},
+    io,
+};
+
+use chrono::Utc;
+use futures::StreamExt;
+use mongodb::{
+    Database,
+    bson::{Document, doc, from_document, to_bson},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zip::ZipWriter;
+
+use crate::{
+    models::{
+        archive::{self, ArchiveContent, ArchiveWriter, failed},
+        dataset,
+        evidence::{Evidence, EvidenceQuery},
+    },
+    views::evidence::ViewEvidence,
+};
+
+use super::event::EventKind;
+
+const COLLECTION: &str = "exports";
+// How long a finished archive stays available for download
+const EXPORT_DURATION: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportKind {
+    #[default]
+    Evidence, // Evidence data and images with a manifest
+    Dataset, // Reviewed evidence as a YOLO dataset
+}
+
+// Evidence archive built in the background, kept under ./export
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Export {
+    pub id: String,
+    pub user_id: String, // User who requested the export
+    #[serde(default)]
+    pub kind: ExportKind,
+    pub filter: Document, // Evidence filter, already limited to the clusters of the user
+    pub status: ExportStatus,
+    pub evidence_count: u64,
+    #[serde(default)]
+    pub skipped_count: u64, // Evidence left out of a dataset
+    pub size: u64, // Bytes of the archive
+    pub timestamp: i64,
+    pub completed_timestamp: Option<i64>,
+    pub expiry: Option<i64>, // When the export and its archive are deleted, set once finished
+}
+
+impl Export {
+    pub fn new(user_id: String, kind: ExportKind, query: &EvidenceQuery) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            kind,
+            filter: Evidence::create_filter(query),
+            status: ExportStatus::Pending,
+            evidence_count: 0,
+            skipped_count: 0,
+            size: 0,
+            timestamp: Utc::now().timestamp_millis(),
+            completed_timestamp: None,
+            expiry: None,
+        }
+    }
+    pub fn file(&self) -> String {
+        format!("./export/{}.zip", self.id)
+    }
+    // Name of the archive when downloaded
+    pub fn file_name(&self) -> String {
+        match self.kind {
+            ExportKind::Evidence => format!("evidence_{}.zip", self.id),
+            ExportKind::Dataset => format!("dataset_{}.zip", self.id),
+        }
+    }
+
+    pub async fn save(&self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection.insert_one(self, None).await.is_ok() {
+            Ok(())
+        } else {
+            Err(EventKind::SavingFailed)
+        }
+    }
+    pub async fn update(&self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection
+            .update_one(
+                doc! { "id": &self.id },
+                doc! { "$set": to_bson::<Self>(self).unwrap() },
+                None,
+            )
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::UpdatingFailed)
+        }
+    }
+    pub async fn find_by_id(id: &String, db: &Database) -> Result<Self, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection.find_one(doc! { "id": id }, None).await {
+            Ok(Some(v)) => Ok(v),
+            Ok(_) => Err(EventKind::NotFound),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    // Exports requested by the user, newest first
+    pub async fn find_many(user_id: &String, db: &Database) -> Result<Vec<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .find(
+                doc! { "user_id": user_id },
+                FindOptions::builder()
+                    .sort(doc! { "timestamp": -1 })
+                    .build(),
+            )
+            .await
+        {
+            Ok(mut cursor) => {
+                let mut exports = Vec::new();
+                while let Some(Ok(export)) = cursor.next().await {
+                    exports.push(export);
+                }
+                Ok(exports)
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    // Take the oldest pending export, in a single update so it is only built once
+    pub async fn claim(db: &Database) -> Result<Option<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .find_one_and_update(
+                doc! { "status": to_bson(&ExportStatus::Pending).unwrap() },
+                doc! { "$set": { "status": to_bson(&ExportStatus::Running).unwrap() } },
+                FindOneAndUpdateOptions::builder()
+                    .sort(doc! { "timestamp": 1 })
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+        {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::UpdatingFailed)
+            }
+        }
+    }
+    // Exports interrupted by a restart are built again from the start
+    pub async fn reset(db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection
+            .update_many(
+                doc! { "status": to_bson(&ExportStatus::Running).unwrap() },
+                doc! { "$set": { "status": to_bson(&ExportStatus::Pending).unwrap() } },
+                None,
+            )
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::UpdatingFailed)
+        }
+    }
+    // Deletes exports whose archive is past its expiry, returns how many were removed
+    pub async fn expire(db: &Database) -> Result<usize, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+        let timestamp = Utc::now().timestamp_millis();
+
+        let exports = match collection
+            .find(doc! { "expiry": { "$lt": timestamp } }, None)
+            .await
+        {
+            Ok(mut cursor) => {
+                let mut exports = Vec::new();
+                while let Some(Ok(export)) = cursor.next().await {
+                    exports.push(export);
+                }
+                exports
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                return Err(EventKind::FindingFailed);
+            }
+        };
+
+        for export in exports.iter() {
+            let _ = remove_file(export.file());
+            if collection
+                .delete_one(doc! { "id": &export.id }, None)
+                .await
+                .is_err()
+            {
+                return Err(EventKind::DeletingFailed);
+            }
+        }
+
+        Ok(exports.len())
+    }
+
+    // Builds the archive and records the outcome, a failed export keeps no partial archive
+    pub async fn run(&mut self, db: &Database) -> Result<(), EventKind> {
+        let result = match self.kind {
+            ExportKind::Evidence => self.write(db).await.map(|(v, size)| (v, 0, size)),
+            ExportKind::Dataset => dataset::write(self.filter.clone(), &self.file(), db)
+                .await
+                .map(|v| (v.evidence_count, v.skipped_count, v.size)),
+        };
+        let timestamp = Utc::now().timestamp_millis();
+
+        match result {
+            Ok((evidence_count, skipped_count, size)) => {
+                self.status = ExportStatus::Completed;
+                self.evidence_count = evidence_count;
+                self.skipped_count = skipped_count;
+                self.size = size;
+            }
+            Err(_) => {
+                self.status = ExportStatus::Failed;
+                let _ = remove_file(self.file());
+            }
+        }
+        self.completed_timestamp = Some(timestamp);
+        self.expiry = Some(timestamp + EXPORT_DURATION);
+        self.update(db).await?;
+
+        result.map(|_| ())
+    }
+
+    // Evidence is read from a cursor and handed to the archive writer one entry at a time
+    async fn write(&self, db: &Database) -> Result<(u64, u64), EventKind> {
+        let mut cursor = ViewEvidence::find_cursor(self.filter.clone(), db).await?;
+        let writer = ArchiveWriter::new(&self.file(), ExportArchive::new(&self.id));
+
+        let mut read = Ok(());
+        while let Some(doc) = cursor.next().await {
+            match doc.map(from_document::<ViewEvidence>) {
+                Ok(Ok(v)) => {
+                    // The writer stopped, its error is returned below
+                    if !writer.send(v).await {
+                        break;
+                    }
+                }
+                Ok(Err(e)) => println!("ERROR: {:?}", e),
+                Err(e) => {
+                    println!("ERROR: {:?}", e);
+                    read = Err(EventKind::SavingFailed);
+                    break;
+                }
+            }
+        }
+
+        let written = writer.finish().await;
+        read.and(written.map(|(v, size)| (v.evidence_count, size)))
+    }
+}
+
+// Evidence data and image, with a manifest spooled to its own file and appended last
+struct ExportArchive {
+    manifest_file: String,
+    manifest: Option<csv::Writer<File>>, // Created by start
+    evidence_count: u64,
+}
+
+impl ExportArchive {
+    fn new(id: &str) -> Self {
+        Self {
+            manifest_file: format!("./export/{}.csv", id),
+            manifest: None,
+            evidence_count: 0,
+        }
+    }
+    fn manifest(&mut self) -> Result<&mut csv::Writer<File>, EventKind> {
+        self.manifest.as_mut().ok_or(EventKind::SavingFailed)
+    }
+}
+
+impl ArchiveContent for ExportArchive {
+    type Item = ViewEvidence;
+
+    fn start(&mut self, _archive: &mut ZipWriter<File>) -> Result<(), EventKind> {
+        let mut manifest = csv::Writer::from_path(&self.manifest_file).map_err(|e| failed(&e))?;
+        manifest
+            .write_record([
+                "id",
+                "timestamp",
+                "cluster",
+                "processor",
+                "camera",
+                "person_count",
+                "violation_count",
+                "data",
+                "image",
+            ])
+            .map_err(|e| failed(&e))?;
+        self.manifest = Some(manifest);
+
+        Ok(())
+    }
+    fn entry(
+        &mut self,
+        archive: &mut ZipWriter<File>,
+        evidence: ViewEvidence,
+    ) -> Result<(), EventKind> {
+        let data = format!("evidence/{}.json", evidence.id);
+        archive
+            .start_file(data.as_str(), archive::deflated())
+            .map_err(|e| failed(&e))?;
+        serde_json::to_writer_pretty(&mut *archive, &evidence).map_err(|e| failed(&e))?;
+
+        let image = match File::open(format!("./evidence/{}.jpg", evidence.id)) {
+            Ok(mut file) => {
+                let image = format!("evidence/{}.jpg", evidence.id);
+                archive
+                    .start_file(image.as_str(), archive::stored())
+                    .map_err(|e| failed(&e))?;
+                io::copy(&mut file, archive).map_err(|e| failed(&e))?;
+                image
+            }
+            Err(_) => String::new(),
+        };
+
+        self.manifest()?
+            .write_record([
+                evidence.id.clone(),
+                evidence.timestamp.to_string(),
+                evidence.cluster.name.clone(),
+                evidence.processor.name.clone(),
+                evidence.camera.name.clone(),
+                evidence.person.len().to_string(),
+                evidence
+                    .person
+                    .iter()
+                    .map(|p| p.violation.len())
+                    .sum::<usize>()
+                    .to_string(),
+                data,
+                image,
+            ])
+            .map_err(|e| failed(&e))?;
+        self.evidence_count += 1;
+
+        Ok(())
+    }
+    fn finish(&mut self, archive: &mut ZipWriter<File>) -> Result<(), EventKind> {
+        self.manifest()?.flush().map_err(|e| failed(&e))?;
+        self.manifest = None;
+
+        archive
+            .start_file("manifest.csv", archive::deflated())
+            .map_err(|e| failed(&e))?;
+        let copied =
+            File::open(&self.manifest_file).and_then(|mut file| io::copy(&mut file, archive));
+        let _ = remove_file(&self.manifest_file);
+        copied.map_err(|e| failed(&e))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{File, remove_file};
+
+    use zip::ZipArchive;
+
+    use crate::{database, models::evidence::EvidenceQuery};
+
+    use super::{Export, ExportKind, ExportStatus};
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn run_archives_evidence_in_scope() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+        database::test::seed("south", &db).await;
+
+        let query = EvidenceQuery {
+            scope: Some(vec![String::from("north")]),
+            ..Default::default()
+        };
+        let mut export = Export::new(String::from("user"), ExportKind::Evidence, &query);
+        export.save(&db).await.unwrap();
+        export.run(&db).await.unwrap();
+
+        let export = Export::find_by_id(&export.id, &db).await.unwrap();
+        assert_eq!(export.status, ExportStatus::Completed);
+        assert_eq!(export.evidence_count, 1);
+
+        let mut archive = ZipArchive::new(File::open(export.file()).unwrap()).unwrap();
+        let mut name = archive.file_names().collect::<Vec<_>>();
+        name.sort();
+        assert_eq!(name, ["evidence/north_evidence.json", "manifest.csv"]);
+        assert!(archive.by_name("manifest.csv").unwrap().size() > 0);
+
+        remove_file(export.file()).unwrap();
+        db.drop(None).await.unwrap();
+    }
+}
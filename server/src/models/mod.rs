@@ -1,7 +1,18 @@
+pub mod analytics;
+pub mod archive;
+pub mod batch;
 pub mod camera;
 pub mod cluster;
+pub mod dataset;
+pub mod delivery;
+pub mod enrollment;
 pub mod event;
 pub mod evidence;
+pub mod export;
+pub mod preference;
 pub mod processor;
+pub mod queue;
+pub mod report;
+pub mod signature;
 pub mod subscriber;
 pub mod user;
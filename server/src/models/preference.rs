@@ -0,0 +1,410 @@
+use chrono::{DateTime, Duration, TimeZone, Timelike};
+use chrono_tz::Tz;
+use futures::StreamExt;
+use mongodb::{
+    Database,
+    bson::{doc, to_bson},
+    options::ReplaceOptions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::models::evidence::{Evidence, EvidencePersonViolation, EvidenceSeverity};
+
+use super::event::EventKind;
+
+const COLLECTION: &str = "preferences";
+const DAY_MINUTE: u32 = 24 * 60;
+// Local hour the daily digest is sent at when the request does not say
+const PREFERENCE_DIGEST_HOUR: u32 = 7;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreferenceMode {
+    #[default]
+    Immediate, // A notification for every evidence
+    Digest, // A single summary a day
+}
+
+// Minutes after local midnight, a start after the end spans midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PreferenceQuiet {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PreferenceRequest {
+    pub camera_id: Option<Vec<String>>,    // Every camera when not set
+    pub processor_id: Option<Vec<String>>, // Every processor when not set
+    pub violation: Option<Vec<EvidencePersonViolation>>, // Every violation when not set
+    pub severity_minimum: Option<EvidenceSeverity>,
+    pub quiet: Option<PreferenceQuiet>,
+    pub timezone: Option<String>, // Of the quiet hours and the digest, e.g. Asia/Jakarta, UTC when not set
+    pub mode: Option<PreferenceMode>,
+    pub digest_hour: Option<u32>,
+}
+
+// What a user is notified about, users without one are notified about everything at once
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Preference {
+    pub user_id: String,
+    pub camera_id: Option<Vec<String>>,
+    pub processor_id: Option<Vec<String>>,
+    pub violation: Option<Vec<EvidencePersonViolation>>,
+    pub severity_minimum: EvidenceSeverity,
+    pub quiet: Option<PreferenceQuiet>,
+    pub timezone: String,
+    pub mode: PreferenceMode,
+    pub digest_hour: u32,
+    pub digest_timestamp: Option<i64>, // When the last digest was sent
+}
+
+impl Preference {
+    pub fn new(user_id: String, request: PreferenceRequest) -> Result<Self, EventKind> {
+        let timezone = request.timezone.unwrap_or(String::from("UTC"));
+        if timezone.parse::<Tz>().is_err() {
+            return Err(EventKind::InvalidTimezone);
+        }
+
+        let digest_hour = request.digest_hour.unwrap_or(PREFERENCE_DIGEST_HOUR);
+        if digest_hour >= 24
+            || request
+                .quiet
+                .is_some_and(|v| v.start >= DAY_MINUTE || v.end >= DAY_MINUTE)
+        {
+            return Err(EventKind::InvalidRange);
+        }
+
+        Ok(Self {
+            user_id,
+            camera_id: request.camera_id,
+            processor_id: request.processor_id,
+            violation: request.violation,
+            severity_minimum: request.severity_minimum.unwrap_or_default(),
+            quiet: request.quiet,
+            timezone,
+            mode: request.mode.unwrap_or_default(),
+            digest_hour,
+            digest_timestamp: None,
+        })
+    }
+
+    // Replaces the preference of the user, the digest already sent is kept
+    pub async fn save(&mut self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if let Ok(v) = Self::find_by_user_id(&self.user_id, db).await {
+            self.digest_timestamp = v.digest_timestamp;
+        }
+
+        if collection
+            .replace_one(
+                doc! { "user_id": &self.user_id },
+                &*self,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::SavingFailed)
+        }
+    }
+    pub async fn delete(user_id: &String, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .delete_one(doc! { "user_id": user_id }, None)
+            .await
+        {
+            Ok(v) if v.deleted_count > 0 => Ok(()),
+            Ok(_) => Err(EventKind::NotFound),
+            Err(_) => Err(EventKind::DeletingFailed),
+        }
+    }
+    pub async fn find_by_user_id(user_id: &String, db: &Database) -> Result<Self, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection.find_one(doc! { "user_id": user_id }, None).await {
+            Ok(Some(v)) => Ok(v),
+            Ok(_) => Err(EventKind::NotFound),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    // The preference of the user, everything at once when there is none
+    pub async fn find_or_default(user_id: &String, db: &Database) -> Self {
+        match Self::find_by_user_id(user_id, db).await {
+            Ok(v) => v,
+            Err(_) => Self::new(user_id.clone(), PreferenceRequest::default()).unwrap(),
+        }
+    }
+    pub async fn find_many_by_mode(
+        mode: PreferenceMode,
+        db: &Database,
+    ) -> Result<Vec<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .find(doc! { "mode": to_bson(&mode).unwrap() }, None)
+            .await
+        {
+            Ok(mut cursor) => {
+                let mut preferences = Vec::new();
+                while let Some(Ok(preference)) = cursor.next().await {
+                    preferences.push(preference);
+                }
+                Ok(preferences)
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    pub async fn update_digest(&mut self, timestamp: i64, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        self.digest_timestamp = Some(timestamp);
+        if collection
+            .update_one(
+                doc! { "user_id": &self.user_id },
+                doc! { "$set": { "digest_timestamp": timestamp } },
+                None,
+            )
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::UpdatingFailed)
+        }
+    }
+
+    // Whether the evidence should be sent to the user right away
+    pub fn allows(&self, evidence: &Evidence, timestamp: i64) -> bool {
+        self.mode == PreferenceMode::Immediate
+            && !self.quiet_at(timestamp)
+            && self.matches(evidence)
+    }
+    // Whether the evidence is on the user's cameras and has a violation the user cares about
+    pub fn matches(&self, evidence: &Evidence) -> bool {
+        if self
+            .camera_id
+            .as_ref()
+            .is_some_and(|v| !v.contains(&evidence.camera_id))
+            || self
+                .processor_id
+                .as_ref()
+                .is_some_and(|v| !v.contains(&evidence.processor_id))
+        {
+            return false;
+        }
+
+        self.violation_count(evidence) > 0
+    }
+    // Reviewed violations of the evidence of the kinds and severity the user cares about
+    pub fn violation_count(&self, evidence: &Evidence) -> usize {
+        evidence
+            .person
+            .iter()
+            .flat_map(|person| evidence.reviewed_violation(person))
+            .filter(|v| {
+                v.severity() >= self.severity_minimum
+                    && self.violation.as_ref().is_none_or(|w| w.contains(v))
+            })
+            .count()
+    }
+    pub fn quiet_at(&self, timestamp: i64) -> bool {
+        let quiet = match self.quiet {
+            Some(v) if v.start != v.end => v,
+            _ => return false,
+        };
+        let time = match self.local(timestamp) {
+            Some(v) => v,
+            None => return false,
+        };
+        let minute = time.hour() * 60 + time.minute();
+
+        if quiet.start < quiet.end {
+            quiet.start <= minute && minute < quiet.end
+        } else {
+            minute >= quiet.start || minute < quiet.end
+        }
+    }
+    // Period of the digest that is due at the timestamp: the day before the last digest hour,
+    // None when that digest was already sent
+    pub fn digest_due(&self, timestamp: i64) -> Option<(i64, i64)> {
+        if self.mode != PreferenceMode::Digest {
+            return None;
+        }
+
+        let time = self.local(timestamp)?;
+        let mut end = time
+            .date_naive()
+            .and_hms_opt(self.digest_hour, 0, 0)?
+            .and_local_timezone(time.timezone())
+            .earliest()?;
+        if end > time {
+            end -= Duration::days(1);
+        }
+        let end = end.timestamp_millis();
+
+        match self.digest_timestamp {
+            Some(v) if v >= end => None,
+            _ => Some((end - Duration::days(1).num_milliseconds(), end)),
+        }
+    }
+
+    fn local(&self, timestamp: i64) -> Option<DateTime<Tz>> {
+        let timezone = self.timezone.parse::<Tz>().ok()?;
+        timezone.timestamp_millis_opt(timestamp).single()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::evidence::{
+        Evidence, EvidencePerson, EvidencePersonViolation, EvidenceSeverity, EvidenceStatus,
+    };
+
+    use super::{Preference, PreferenceMode, PreferenceQuiet, PreferenceRequest};
+
+    // 2024-01-10 00:00 UTC
+    const TIMESTAMP: i64 = 19732 * 24 * 60 * 60 * 1000;
+    const HOUR: i64 = 60 * 60 * 1000;
+
+    fn evidence(violation: Vec<EvidencePersonViolation>) -> Evidence {
+        Evidence {
+            id: String::from("evidence"),
+            cluster_id: String::from("cluster"),
+            processor_id: String::from("processor"),
+            camera_id: String::from("camera"),
+            frame_id: String::from("frame"),
+            timestamp: TIMESTAMP,
+            person: vec![EvidencePerson {
+                id: String::from("person"),
+                bbox: [0.0, 0.0, 1.0, 1.0],
+                confidence: 0.9,
+                part: Vec::new(),
+                equipment: Vec::new(),
+                violation,
+                confirmation: Vec::new(),
+            }],
+            clip: None,
+            status: EvidenceStatus::Open,
+            review: Vec::new(),
+            correction: Vec::new(),
+            escalation: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn matches_cameras_violations_and_severity() {
+        let preference = Preference::new(
+            String::from("user"),
+            PreferenceRequest {
+                camera_id: Some(vec![String::from("camera")]),
+                severity_minimum: Some(EvidenceSeverity::Medium),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(preference.matches(&evidence(vec![EvidencePersonViolation::MissingGloves])));
+        assert!(!preference.matches(&evidence(vec![
+            EvidencePersonViolation::ImproperlyWornGloves
+        ])));
+
+        let mut other = evidence(vec![EvidencePersonViolation::MissingHardhat]);
+        other.camera_id = String::from("other");
+        assert!(!preference.matches(&other));
+
+        let preference = Preference::new(
+            String::from("user"),
+            PreferenceRequest {
+                violation: Some(vec![EvidencePersonViolation::MissingHardhat]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(!preference.matches(&evidence(vec![EvidencePersonViolation::MissingGloves])));
+        assert!(preference.matches(&evidence(vec![
+            EvidencePersonViolation::MissingGloves,
+            EvidencePersonViolation::MissingHardhat
+        ])));
+
+        // Only the violations the user cares about are counted, after the review
+        let mut dismissed = evidence(vec![
+            EvidencePersonViolation::MissingGloves,
+            EvidencePersonViolation::MissingHardhat,
+        ]);
+        assert_eq!(preference.violation_count(&dismissed), 1);
+        dismissed.status = EvidenceStatus::FalsePositive;
+        assert_eq!(preference.violation_count(&dismissed), 0);
+    }
+
+    #[test]
+    fn quiet_hours_follow_the_timezone() {
+        // 22:00 to 06:00 in Jakarta, UTC+7
+        let preference = Preference::new(
+            String::from("user"),
+            PreferenceRequest {
+                quiet: Some(PreferenceQuiet {
+                    start: 22 * 60,
+                    end: 6 * 60,
+                }),
+                timezone: Some(String::from("Asia/Jakarta")),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let hardhat = evidence(vec![EvidencePersonViolation::MissingHardhat]);
+
+        // 07:00 and 22:00 in Jakarta
+        assert!(preference.allows(&hardhat, TIMESTAMP));
+        assert!(!preference.allows(&hardhat, TIMESTAMP + 15 * HOUR));
+        // 05:59 in Jakarta
+        assert!(preference.quiet_at(TIMESTAMP - 61 * 60 * 1000));
+
+        assert!(
+            Preference::new(
+                String::from("user"),
+                PreferenceRequest {
+                    timezone: Some(String::from("Mars/Olympus")),
+                    ..Default::default()
+                },
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn digest_is_due_once_a_day() {
+        let mut preference = Preference::new(
+            String::from("user"),
+            PreferenceRequest {
+                mode: Some(PreferenceMode::Digest),
+                digest_hour: Some(7),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(!preference.allows(&evidence(vec![EvidencePersonViolation::MissingHardhat]), 0));
+
+        // 10:00 UTC, the digest of the day before 07:00
+        let timestamp = TIMESTAMP + 10 * HOUR;
+        let period = (TIMESTAMP - 17 * HOUR, TIMESTAMP + 7 * HOUR);
+        assert_eq!(preference.digest_due(timestamp), Some(period));
+
+        preference.digest_timestamp = Some(timestamp);
+        assert_eq!(preference.digest_due(timestamp + HOUR), None);
+        assert_eq!(
+            preference.digest_due(timestamp + 24 * HOUR),
+            Some((period.1, period.1 + 24 * HOUR))
+        );
+    }
+}
@@ -6,6 +6,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::models::evidence::{Evidence, EvidenceQuery};
 
+use crate::views::page::PageOrder;
+
 use super::event::EventKind;
 
 const COLLECTION: &str = "processors";
@@ -26,6 +28,14 @@ pub struct Processor {
     pub model: String,
     pub address: ProcessorAddress,
     pub version: i64, // Comparable version
+    #[serde(default)]
+    pub secret: Option<String>, // Shared secret the processor signs its requests with
+}
+// Returned once when a secret is provisioned, the server never sends it again
+#[derive(Debug, Serialize)]
+pub struct ProcessorCredential {
+    pub id: String,
+    pub secret: String,
 }
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ProcessorAddress {
@@ -40,7 +50,10 @@ pub struct ProcessorQuery {
     pub date_maximum: Option<i64>,
     pub text: Option<String>,
     pub limit: Option<usize>,
-    pub skip: Option<usize>,
+    pub cursor: Option<String>,
+    pub order: Option<PageOrder>, // By name, alphabetical when not set
+    #[serde(skip)]
+    pub scope: Option<Vec<String>>, // Clusters of the caller, set for everyone but super admins
 }
 
 impl Processor {
@@ -79,11 +92,8 @@ impl Processor {
 
         let _ = Evidence::delete_many(
             &EvidenceQuery {
-                cluster_id: None,
                 processor_id: Some(self.id.clone()),
-                camera_id: None,
-                date_minimum: None,
-                date_maximum: None,
+                ..Default::default()
             },
             db,
         )
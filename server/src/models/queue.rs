@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+
+use futures::StreamExt;
+use mongodb::{Database, bson::doc, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::models::evidence::Evidence;
+
+use super::event::EventKind;
+
+const COLLECTION: &str = "queue";
+
+// Evidence waiting in the in-memory notification queue, kept in the database until it was
+// dispatched so a restart does not lose it
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QueuedEvidence {
+    pub evidence_id: String,
+    pub timestamp: i64, // Of the evidence, the queue is loaded back in this order
+}
+
+impl QueuedEvidence {
+    pub async fn push(evidence: &Evidence, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let queued = Self {
+            evidence_id: evidence.id.clone(),
+            timestamp: evidence.timestamp,
+        };
+        if collection.insert_one(&queued, None).await.is_ok() {
+            Ok(())
+        } else {
+            Err(EventKind::SavingFailed)
+        }
+    }
+    pub async fn remove(evidence_id: &String, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection
+            .delete_many(doc! { "evidence_id": evidence_id }, None)
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::DeletingFailed)
+        }
+    }
+    // The evidence still to be dispatched, oldest first. Entries whose evidence was deleted in
+    // the meantime are dropped.
+    pub async fn load(db: &Database) -> Result<VecDeque<Evidence>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let mut queued = Vec::new();
+        match collection
+            .find(
+                doc! {},
+                FindOptions::builder().sort(doc! { "timestamp": 1 }).build(),
+            )
+            .await
+        {
+            Ok(mut cursor) => {
+                while let Some(Ok(v)) = cursor.next().await {
+                    queued.push(v);
+                }
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                return Err(EventKind::FindingFailed);
+            }
+        }
+
+        let mut evidences = VecDeque::new();
+        for v in queued {
+            match Evidence::find_by_id(&v.evidence_id, db).await {
+                Ok(evidence) => evidences.push_back(evidence),
+                Err(EventKind::NotFound) => {
+                    let _ = Self::remove(&v.evidence_id, db).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(evidences)
+    }
+}
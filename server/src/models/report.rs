@@ -0,0 +1,250 @@
+use std::fs::{create_dir_all, remove_file, write};
+
+use chrono::Utc;
+use futures::StreamExt;
+use mongodb::{
+    Database,
+    bson::{doc, to_bson},
+    options::{FindOneOptions, FindOptions},
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use uuid::Uuid;
+
+use crate::{models::cluster::Cluster, views::report::ViewReport};
+
+use super::event::EventKind;
+
+const COLLECTION: &str = "reports";
+const DAY: i64 = 24 * 60 * 60 * 1000;
+// 1970-01-01 was a thursday, weeks start on the monday after it
+const WEEK_OFFSET: i64 = 4 * DAY;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportSchedule {
+    Daily,
+    Weekly,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Csv,
+    Pdf,
+}
+
+// A report generated by the schedule, its files are kept under ./report
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Report {
+    pub id: String,
+    pub cluster_id: String,
+    pub schedule: ReportSchedule,
+    pub date_minimum: i64,
+    pub date_maximum: i64,
+    pub timestamp: i64, // When the report was generated
+}
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    pub date_minimum: Option<i64>,
+    pub date_maximum: Option<i64>,
+    pub format: Option<ReportFormat>, // PDF when not set
+}
+
+impl ReportSchedule {
+    // Last complete day or week (monday to sunday) before the timestamp, in UTC
+    pub fn period(&self, timestamp: i64) -> (i64, i64) {
+        let (length, offset) = match self {
+            ReportSchedule::Daily => (DAY, 0),
+            ReportSchedule::Weekly => (7 * DAY, WEEK_OFFSET),
+        };
+        let end = (timestamp - offset).div_euclid(length) * length + offset;
+
+        (end - length, end - 1)
+    }
+}
+
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Pdf => "pdf",
+        }
+    }
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "text/csv",
+            ReportFormat::Pdf => "application/pdf",
+        }
+    }
+}
+
+impl Report {
+    pub fn file(&self, format: ReportFormat) -> String {
+        format!("./report/{}.{}", self.id, format.extension())
+    }
+
+    pub async fn save(&self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        if collection.insert_one(self, None).await.is_ok() {
+            Ok(())
+        } else {
+            Err(EventKind::SavingFailed)
+        }
+    }
+    // Removes the records and files of every report of the cluster
+    pub async fn delete_many(cluster_id: &String, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        for report in Self::find_many(cluster_id, db).await? {
+            for format in [ReportFormat::Csv, ReportFormat::Pdf] {
+                let _ = remove_file(report.file(format));
+            }
+        }
+
+        if collection
+            .delete_many(doc! { "cluster_id": cluster_id }, None)
+            .await
+            .is_ok()
+        {
+            Ok(())
+        } else {
+            Err(EventKind::DeletingFailed)
+        }
+    }
+    pub async fn find_by_id(id: &String, db: &Database) -> Result<Self, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection.find_one(doc! { "id": id }, None).await {
+            Ok(Some(v)) => Ok(v),
+            Ok(_) => Err(EventKind::NotFound),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    // Reports of a cluster, newest period first
+    pub async fn find_many(cluster_id: &String, db: &Database) -> Result<Vec<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .find(
+                doc! { "cluster_id": cluster_id },
+                FindOptions::builder()
+                    .sort(doc! { "date_maximum": -1, "schedule": 1 })
+                    .build(),
+            )
+            .await
+        {
+            Ok(mut cursor) => {
+                let mut reports = Vec::new();
+                while let Some(Ok(report)) = cursor.next().await {
+                    reports.push(report);
+                }
+                Ok(reports)
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    pub async fn find_latest(
+        cluster_id: &String,
+        schedule: ReportSchedule,
+        db: &Database,
+    ) -> Result<Self, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        match collection
+            .find_one(
+                doc! { "cluster_id": cluster_id, "schedule": to_bson(&schedule).unwrap() },
+                FindOneOptions::builder()
+                    .sort(doc! { "date_maximum": -1 })
+                    .build(),
+            )
+            .await
+        {
+            Ok(Some(v)) => Ok(v),
+            Ok(_) => Err(EventKind::NotFound),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+    // Renders and stores the report of the last complete period of the schedule, None when it
+    // was already generated
+    pub async fn generate(
+        cluster: &Cluster,
+        schedule: ReportSchedule,
+        db: &Database,
+    ) -> Result<Option<Self>, EventKind> {
+        let timestamp = Utc::now().timestamp_millis();
+        let (date_minimum, date_maximum) = schedule.period(timestamp);
+
+        match Self::find_latest(&cluster.id, schedule, db).await {
+            Ok(v) if v.date_maximum >= date_maximum => return Ok(None),
+            Ok(_) | Err(EventKind::NotFound) => (),
+            Err(e) => return Err(e),
+        }
+
+        let report = Self {
+            id: Uuid::new_v4().to_string(),
+            cluster_id: cluster.id.clone(),
+            schedule,
+            date_minimum,
+            date_maximum,
+            timestamp,
+        };
+
+        let view = ViewReport::find(cluster, date_minimum, date_maximum, db).await?;
+        let file = [ReportFormat::Csv, ReportFormat::Pdf].map(|f| (f, report.file(f)));
+        // Thumbnails are decoded from disk, off the async workers
+        let written = spawn_blocking(move || {
+            let _ = create_dir_all("./report");
+            for (format, file) in file {
+                if write(file, view.render(format)?).is_err() {
+                    return Err(EventKind::SavingFailed);
+                }
+            }
+            Ok(())
+        })
+        .await;
+        match written {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(EventKind::RenderingFailed),
+        }
+
+        report.save(db).await?;
+        Ok(Some(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DAY, ReportSchedule};
+
+    #[test]
+    fn period_ends_before_the_timestamp() {
+        // 2024-01-10 is a wednesday
+        let timestamp = 19732 * DAY + 15 * 60 * 60 * 1000;
+
+        assert_eq!(
+            ReportSchedule::Daily.period(timestamp),
+            (19731 * DAY, 19732 * DAY - 1)
+        );
+        // Monday 2024-01-01 to sunday 2024-01-07
+        assert_eq!(
+            ReportSchedule::Weekly.period(timestamp),
+            (19723 * DAY, 19730 * DAY - 1)
+        );
+        // A period that just ended is the last complete one
+        assert_eq!(
+            ReportSchedule::Weekly.period(19730 * DAY),
+            (19723 * DAY, 19730 * DAY - 1)
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use actix_web::HttpRequest;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use super::event::EventKind;
+
+// How far a request timestamp may drift from the server clock
+const SIGNATURE_TOLERANCE: i64 = 5 * 60 * 1000;
+
+// Requests from processors are signed with their secret:
+//
+// X-Gidence-Timestamp: milliseconds since epoch
+// X-Gidence-Signature: hex(HMAC-SHA256(secret, "{timestamp}.{digest}"))
+//
+// The digest is the hex SHA-256 of a JSON body, or for multipart bodies the hex SHA-256 of
+// "{name}:{hex SHA-256 of the part}\n" for every part in the order it was sent.
+#[derive(Debug, Default)]
+pub struct Signature {
+    pub seen: HashMap<String, i64>, // signature -> when it falls out of the tolerance window
+}
+#[derive(Debug, Default)]
+pub struct SignatureDigest {
+    part: String,
+}
+
+impl Signature {
+    // New random secret for a processor
+    pub fn secret() -> String {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        hex::encode(secret)
+    }
+
+    pub fn verify(
+        &mut self,
+        secret: &str,
+        req: &HttpRequest,
+        digest: &str,
+    ) -> Result<(), EventKind> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let timestamp = header("X-Gidence-Timestamp")
+            .and_then(|v| v.parse::<i64>().ok())
+            .ok_or(EventKind::Unauthorized)?;
+        let signature = header("X-Gidence-Signature").ok_or(EventKind::Unauthorized)?;
+
+        let now = Utc::now().timestamp_millis();
+        if (now - timestamp).abs() > SIGNATURE_TOLERANCE {
+            return Err(EventKind::Unauthorized);
+        }
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .map_err(|_| EventKind::Unauthorized)?;
+        mac.update(format!("{}.{}", timestamp, digest).as_bytes());
+        let expected = hex::decode(&signature).map_err(|_| EventKind::Unauthorized)?;
+        if mac.verify_slice(&expected).is_err() {
+            return Err(EventKind::Unauthorized);
+        }
+
+        // Replay protection, a signature is only accepted once within the tolerance window
+        self.seen.retain(|_, expiry| *expiry > now);
+        if self.seen.contains_key(&signature) {
+            return Err(EventKind::Unauthorized);
+        }
+        self.seen.insert(signature, timestamp + SIGNATURE_TOLERANCE);
+
+        Ok(())
+    }
+}
+
+impl SignatureDigest {
+    pub fn body(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    pub fn push(&mut self, name: &str, data: &[u8]) {
+        self.part
+            .push_str(&format!("{}:{}\n", name, hex::encode(Sha256::digest(data))));
+    }
+    pub fn finish(&self) -> String {
+        Self::body(self.part.as_bytes())
+    }
+}
@@ -1,30 +1,47 @@
+use chrono::Utc;
 use futures::StreamExt;
 use mongodb::{
     Database,
     bson::{doc, to_bson},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
 };
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::event::EventKind;
 
 const COLLECTION: &str = "subscribers";
+const LINK_COLLECTION: &str = "subscriber_links";
+// How long a linking code stays valid
+const LINK_DURATION: i64 = 15 * 60 * 1000;
+// Codes may be typed by hand into the chat, so characters that look alike are left out
+const LINK_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const LINK_CODE_LENGTH: usize = 8;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SubscriberRequest {
     pub user_id: String,
     pub kind: SubscriberKind,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Subscriber {
     pub id: String,
     pub user_id: String,
     pub kind: SubscriberKind,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SubscriberKind {
     Apple(String),
+    Telegram(String), // Chat id, bound through the bot with a linking code
+}
+// Service a subscriber is reached through, each has its own notifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriberChannel {
+    Apple,
+    Telegram,
 }
 
 #[derive(Deserialize)]
@@ -36,6 +53,19 @@ pub struct SubscriberQuery {
 #[serde(rename_all = "snake_case")]
 pub enum SubscriberQueryKind {
     Apple,
+    Telegram,
+}
+
+// Single use code a user sends to the Telegram bot as /start <code> to bind the chat
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SubscriberLink {
+    pub id: String,
+    pub user_id: String,
+    pub code: String,
+    pub timestamp: i64,
+    pub expiry: i64,
+    pub chat_id: Option<String>, // Chat that redeemed the code
+    pub linked_timestamp: Option<i64>,
 }
 
 impl From<SubscriberRequest> for Subscriber {
@@ -48,6 +78,15 @@ impl From<SubscriberRequest> for Subscriber {
     }
 }
 
+impl SubscriberKind {
+    pub fn channel(&self) -> SubscriberChannel {
+        match self {
+            SubscriberKind::Apple(_) => SubscriberChannel::Apple,
+            SubscriberKind::Telegram(_) => SubscriberChannel::Telegram,
+        }
+    }
+}
+
 impl Subscriber {
     pub async fn save(&mut self, db: &Database) -> Result<(), EventKind> {
         let collection = db.collection::<Self>(COLLECTION);
@@ -109,6 +148,7 @@ impl Subscriber {
 
         let query = match kind {
             SubscriberKind::Apple(token) => doc! { "kind.apple": token },
+            SubscriberKind::Telegram(chat_id) => doc! { "kind.telegram": chat_id },
         };
 
         match collection.find_one(query, None).await {
@@ -147,3 +187,67 @@ impl Subscriber {
         }
     }
 }
+
+impl SubscriberLink {
+    pub fn new(user_id: String) -> Self {
+        let timestamp = Utc::now().timestamp_millis();
+
+        let mut rng = rand::thread_rng();
+        let code = (0..LINK_CODE_LENGTH)
+            .map(|_| LINK_ALPHABET[rng.gen_range(0..LINK_ALPHABET.len())] as char)
+            .collect::<String>();
+
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            code,
+            timestamp,
+            expiry: timestamp + LINK_DURATION,
+            chat_id: None,
+            linked_timestamp: None,
+        }
+    }
+
+    pub async fn save(&self, db: &Database) -> Result<(), EventKind> {
+        let collection = db.collection::<Self>(LINK_COLLECTION);
+
+        if collection.insert_one(self, None).await.is_ok() {
+            Ok(())
+        } else {
+            Err(EventKind::SavingFailed)
+        }
+    }
+    // Mark an unused, unexpired code as redeemed by the chat, in a single update so a code can
+    // never be used twice
+    pub async fn redeem(code: &str, chat_id: &String, db: &Database) -> Result<Self, EventKind> {
+        let collection = db.collection::<Self>(LINK_COLLECTION);
+        let timestamp = Utc::now().timestamp_millis();
+
+        match collection
+            .find_one_and_update(
+                doc! {
+                    "code": code.to_uppercase(),
+                    "chat_id": null,
+                    "expiry": { "$gt": timestamp },
+                },
+                doc! {
+                    "$set": {
+                        "chat_id": chat_id,
+                        "linked_timestamp": timestamp,
+                    }
+                },
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+        {
+            Ok(Some(v)) => Ok(v),
+            Ok(_) => Err(EventKind::Unauthorized),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::UpdatingFailed)
+            }
+        }
+    }
+}
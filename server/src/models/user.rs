@@ -2,7 +2,8 @@ use std::{fs::read_to_string, rc::Rc, str::FromStr};
 
 use actix_service::{Service, Transform};
 use actix_web::{
-    Error, HttpMessage,
+    Error, HttpMessage, HttpResponse,
+    body::EitherBody,
     dev::{ServiceRequest, ServiceResponse},
     web,
 };
@@ -19,7 +20,7 @@ use mongodb::{
 use pwhash::bcrypt;
 use serde::{Deserialize, Serialize};
 
-use crate::views::user::ViewUser;
+use crate::views::{page::PageOrder, user::ViewUser};
 
 use super::event::EventKind;
 
@@ -47,7 +48,7 @@ pub struct User {
     pub role: UserRole,
 }
 
-#[derive(PartialEq, Eq, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum UserRole {
     SuperAdmin,
@@ -61,7 +62,10 @@ pub struct UserQuery {
     pub cluster_eid: Option<String>,
     pub text: Option<String>,
     pub limit: Option<usize>,
-    pub skip: Option<usize>,
+    pub cursor: Option<String>,
+    pub order: Option<PageOrder>, // By name, alphabetical when not set
+    #[serde(skip)]
+    pub scope: Option<Vec<String>>, // Clusters of the caller, set for everyone but super admins
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -74,6 +78,7 @@ struct UserClaim {
 #[derive(Debug)]
 pub struct UserAuthenticationData {
     pub id: String,
+    pub cluster_id: Vec<String>,
     pub role: UserRole,
     pub token: String,
 }
@@ -86,6 +91,15 @@ pub struct UserAuthenticationMiddleware<S> {
     service: Rc<S>,
 }
 pub struct UserAuthenticationMiddlewareFactory;
+// Guard for a scope or route, answers 401 when the request carries no valid token and 403
+// when the role of the user is not one of the allowed roles
+pub struct UserAuthorization {
+    role: Rc<Vec<UserRole>>,
+}
+pub struct UserAuthorizationMiddleware<S> {
+    service: Rc<S>,
+    role: Rc<Vec<UserRole>>,
+}
 
 pub type UserAuthentication = Rc<UserAuthenticationData>;
 
@@ -407,6 +421,7 @@ where
                             if let Ok(user) = User::find_by_id(&id, db).await {
                                 let auth_data = UserAuthenticationData {
                                     id,
+                                    cluster_id: user.cluster_id,
                                     role: user.role,
                                     token,
                                 };
@@ -442,6 +457,96 @@ where
     }
 }
 
+impl UserAuthenticationData {
+    // Super admins act on every cluster, everyone else only on the clusters they belong to
+    pub fn allows(&self, cluster_id: &String) -> bool {
+        self.role == UserRole::SuperAdmin || self.cluster_id.contains(cluster_id)
+    }
+    // Clusters listings are limited to, None when the user sees every cluster
+    pub fn scope(&self) -> Option<Vec<String>> {
+        match self.role {
+            UserRole::SuperAdmin => None,
+            _ => Some(self.cluster_id.clone()),
+        }
+    }
+    // Managers act on users of their own clusters, only super admins act on super admins
+    pub fn allows_user(&self, user: &User) -> bool {
+        match self.role {
+            UserRole::SuperAdmin => true,
+            UserRole::Manager => {
+                user.role != UserRole::SuperAdmin
+                    && user.cluster_id.iter().all(|v| self.cluster_id.contains(v))
+            }
+            UserRole::Officer => self.id == user.id,
+        }
+    }
+}
+
+impl UserAuthorization {
+    pub fn any() -> Self {
+        Self::role(&[UserRole::SuperAdmin, UserRole::Manager, UserRole::Officer])
+    }
+    pub fn role(role: &[UserRole]) -> Self {
+        Self {
+            role: Rc::new(role.to_vec()),
+        }
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for UserAuthorizationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    actix_service::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let role = req
+            .extensions()
+            .get::<UserAuthentication>()
+            .map(|issuer| issuer.role);
+
+        let response = match role {
+            None => HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+            Some(role) if !self.role.contains(&role) => HttpResponse::Forbidden().body("FORBIDDEN"),
+            Some(_) => {
+                let srv = self.service.clone();
+                return async move {
+                    let res = srv.call(req).await?;
+                    Ok(res.map_into_left_body())
+                }
+                .boxed_local();
+            }
+        };
+
+        let res = req.into_response(response).map_into_right_body();
+        async move { Ok(res) }.boxed_local()
+    }
+}
+impl<S, B> Transform<S, ServiceRequest> for UserAuthorization
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = UserAuthorizationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(UserAuthorizationMiddleware {
+            service: Rc::new(service),
+            role: self.role.clone(),
+        }))
+    }
+}
+
 pub fn load_keys() {
     let private_access_file =
         read_to_string("./keys/private_access.key").expect("LOAD_FAILED_PRIVATE_ACCESS");
@@ -0,0 +1,105 @@
+use std::{fs::read, io::Cursor, time::Duration};
+
+use a2::{
+    Client, ClientConfig, DefaultNotificationBuilder, NotificationBuilder, NotificationOptions,
+};
+use async_trait::async_trait;
+use tokio::{sync::RwLock, time::Instant};
+
+use crate::models::subscriber::{SubscriberChannel, SubscriberKind};
+
+use super::{Notification, Notifier, NotifierError};
+
+// The client is signed again before its provider token can go stale
+const APPLE_REFRESH: Duration = Duration::from_secs(240);
+
+// Push notifications through APNS, keyed by keys/apns.p8
+pub struct AppleNotifier {
+    key: Vec<u8>,
+    key_id: String,
+    team_id: String,
+    client: RwLock<(Client, Instant)>,
+}
+
+impl AppleNotifier {
+    pub fn new(key: Vec<u8>, key_id: String, team_id: String) -> Result<Self, a2::Error> {
+        let client = Self::create_client(&key, &key_id, &team_id)?;
+
+        Ok(Self {
+            key,
+            key_id,
+            team_id,
+            client: RwLock::new((client, Instant::now())),
+        })
+    }
+    pub fn from_env() -> Self {
+        let key = read("keys/apns.p8").expect("APNS_NOT_FOUND");
+        let key_id = std::env::var("APNS_KEY").expect("APNS_KEY_NOT_FOUND");
+        let team_id = std::env::var("APNS_TEAM").expect("APNS_TEAM_NOT_FOUND");
+
+        Self::new(key, key_id, team_id).expect("TOKEN_CREATION_FAILED")
+    }
+
+    fn create_client(key: &[u8], key_id: &str, team_id: &str) -> Result<Client, a2::Error> {
+        Client::token(
+            &mut Cursor::new(key),
+            key_id,
+            team_id,
+            ClientConfig::new(a2::Endpoint::Sandbox),
+        )
+    }
+    async fn refresh(&self) -> Result<(), NotifierError> {
+        let mut client = self.client.write().await;
+        if client.1.elapsed() >= APPLE_REFRESH {
+            let refreshed = Self::create_client(&self.key, &self.key_id, &self.team_id)
+                .map_err(|e| NotifierError::Failed(e.to_string()))?;
+            *client = (refreshed, Instant::now());
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for AppleNotifier {
+    fn channel(&self) -> SubscriberChannel {
+        SubscriberChannel::Apple
+    }
+    async fn send(
+        &self,
+        kind: &SubscriberKind,
+        notification: &Notification,
+    ) -> Result<(), NotifierError> {
+        let token = match kind {
+            SubscriberKind::Apple(v) => v,
+            _ => return Err(NotifierError::Rejected(String::from("UNSUPPORTED_KIND"))),
+        };
+        self.refresh().await?;
+
+        let options = NotificationOptions {
+            apns_topic: Some("com.gidence.scm"),
+            ..Default::default()
+        };
+
+        let builder = DefaultNotificationBuilder::new()
+            .set_title(&notification.title)
+            .set_subtitle(&notification.subtitle)
+            .set_content_available()
+            .set_sound("ping.flac");
+
+        let mut payload = builder.build(token, options);
+        payload
+            .add_custom_data("evidence_id", &notification.evidence_id)
+            .unwrap();
+
+        let client = self.client.read().await;
+        match client.0.send(payload).await {
+            Ok(_) => Ok(()),
+            // The device token is no longer valid
+            Err(a2::Error::ResponseError(v)) if v.code == 403 || v.code == 410 => {
+                Err(NotifierError::Rejected(format!("{:?}", v)))
+            }
+            Err(e) => Err(NotifierError::Failed(e.to_string())),
+        }
+    }
+}
@@ -0,0 +1,704 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::Database;
+use tokio::time::{Instant, sleep_until};
+
+use crate::{
+    models::{
+        batch::Batch,
+        camera::Camera,
+        cluster::Cluster,
+        delivery::{Delivery, DeliveryStatus},
+        evidence::{Evidence, EvidenceEscalation, EvidenceQuery},
+        preference::Preference,
+        processor::Processor,
+        subscriber::{Subscriber, SubscriberChannel, SubscriberKind},
+        user::{User, UserRole},
+    },
+    views::user::UserRef,
+};
+
+// How long after a tier became due an evidence is still escalated, so a policy added to a
+// cluster does not alert on its whole backlog
+const ESCALATION_HORIZON: i64 = 24 * 60 * 60 * 1000;
+
+pub mod apple;
+pub mod telegram;
+
+// Alert of a new evidence, each notifier renders it for its channel
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub evidence_id: String,
+    pub title: String,
+    pub subtitle: String,
+    pub photo: Option<Arc<Vec<u8>>>, // The annotated image when the processor sent it
+}
+
+#[derive(Debug, Clone)]
+pub enum NotifierError {
+    Rejected(String), // The recipient is gone for good, it is not retried
+    Failed(String),
+}
+
+// How a channel is retried and throttled
+#[derive(Debug, Clone, Copy)]
+pub struct NotifierPolicy {
+    pub attempt: u32,       // Attempts per subscriber, the first included
+    pub backoff: Duration,  // Wait after the first failure, doubled after each one
+    pub interval: Duration, // Minimum time between two sends on the channel
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn channel(&self) -> SubscriberChannel;
+    fn policy(&self) -> NotifierPolicy {
+        NotifierPolicy::default()
+    }
+    async fn send(
+        &self,
+        kind: &SubscriberKind,
+        notification: &Notification,
+    ) -> Result<(), NotifierError>;
+}
+
+// Sends alerts through the notifier registered for each channel, subscribers of a channel
+// without one are left alone
+#[derive(Default)]
+pub struct NotifierDispatcher {
+    channel: HashMap<SubscriberChannel, NotifierChannel>,
+    window: i64, // Milliseconds evidence of a cluster is batched per user, none when zero
+    retry: Vec<NotifierRetry>, // Failed sends waiting out their backoff, lost on restart
+}
+
+struct NotifierChannel {
+    notifier: Box<dyn Notifier>,
+    next: Instant, // Earliest time of the next send
+}
+
+struct NotifierRetry {
+    subscriber: Subscriber,
+    notification: Notification,
+    attempt: u32,      // Attempts made so far
+    backoff: Duration, // Wait before this attempt
+    due: Instant,
+}
+
+impl Default for NotifierPolicy {
+    fn default() -> Self {
+        Self {
+            attempt: 3,
+            backoff: Duration::from_secs(1),
+            interval: Duration::ZERO,
+        }
+    }
+}
+
+impl Notification {
+    pub async fn new(evidence: &Evidence, db: &Database) -> Self {
+        let violation_count = evidence
+            .person
+            .iter()
+            .map(|p| p.violation.len())
+            .sum::<usize>();
+
+        let subtitle = match Processor::find_by_id(&evidence.processor_id, db).await {
+            Ok(v) => format!("Tertangkap kamera {}", v.name),
+            _ => String::from("Cek sekarang!"),
+        };
+        let photo = match tokio::fs::read(format!("./evidence/{}.annotated.jpg", evidence.id)).await
+        {
+            Ok(v) => Some(v),
+            Err(_) => tokio::fs::read(format!("./evidence/{}.jpg", evidence.id))
+                .await
+                .ok(),
+        };
+
+        Self {
+            evidence_id: evidence.id.clone(),
+            title: format!("Terjadi {} Pelanggaran Baru!", violation_count),
+            subtitle,
+            photo: photo.map(Arc::new),
+        }
+    }
+    // Summary of the evidence of a digest period, None when there was none. Only the reviewed
+    // violations the preference allows are counted.
+    pub fn digest(evidences: &[Evidence], preference: &Preference) -> Option<Self> {
+        let latest = evidences.iter().max_by_key(|e| e.timestamp)?;
+
+        let violation_count = evidences
+            .iter()
+            .map(|e| preference.violation_count(e))
+            .sum::<usize>();
+        let mut camera_id = evidences.iter().map(|e| &e.camera_id).collect::<Vec<_>>();
+        camera_id.sort();
+        camera_id.dedup();
+
+        Some(Self {
+            evidence_id: latest.id.clone(),
+            title: format!("Ringkasan Harian: {} Pelanggaran", violation_count),
+            subtitle: format!("Dari {} kamera dalam 24 jam terakhir", camera_id.len()),
+            photo: None,
+        })
+    }
+    // Alert of an evidence nobody acknowledged within the delay of an escalation tier
+    pub async fn escalation(evidence: &Evidence, delay: i64, db: &Database) -> Self {
+        let notification = Self::new(evidence, db).await;
+
+        Self {
+            title: format!(
+                "Pelanggaran Belum Ditanggapi Selama {} Menit!",
+                (delay / 60000).max(1)
+            ),
+            ..notification
+        }
+    }
+    // Summary of the evidence held back while the batch's window was open, None when there was
+    // none
+    pub fn batch(batch: &Batch, camera: &str) -> Option<Self> {
+        let evidence_id = batch.evidence_id.last()?;
+
+        Some(Self {
+            evidence_id: evidence_id.clone(),
+            title: format!(
+                "{} Pelanggaran Baru di Kamera {}",
+                batch.violation_count, camera
+            ),
+            subtitle: format!(
+                "Dalam {} menit terakhir",
+                ((batch.expiry - batch.timestamp) / 60000).max(1)
+            ),
+            photo: None,
+        })
+    }
+}
+
+impl NotifierDispatcher {
+    pub fn new(window: i64) -> Self {
+        Self {
+            channel: HashMap::new(),
+            window,
+            retry: Vec::new(),
+        }
+    }
+    pub fn register<T: Notifier + 'static>(&mut self, notifier: T) {
+        self.channel.insert(
+            notifier.channel(),
+            NotifierChannel {
+                notifier: Box::new(notifier),
+                next: Instant::now(),
+            },
+        );
+    }
+
+    // Alerts every subscriber of the users of the evidence's cluster whose preference allows it,
+    // unless the user already has a window open for the cluster and the evidence is batched
+    pub async fn dispatch(&mut self, evidence: &Evidence, db: &Database) -> Vec<Delivery> {
+        let users = match User::find_many_by_cluster_id(&evidence.cluster_id, db).await {
+            Ok(v) => v,
+            _ => return Vec::new(),
+        };
+        let timestamp = Utc::now().timestamp_millis();
+
+        let mut subscribers = Vec::new();
+        for user in users.iter() {
+            let preference = Preference::find_or_default(&user.id, db).await;
+            if !preference.allows(evidence, timestamp) {
+                continue;
+            }
+            // The evidence is sent right away when batching fails, rather than lost
+            if let Ok(false) =
+                Batch::append(&preference, evidence, self.window, timestamp, db).await
+            {
+                continue;
+            }
+            if let Ok(mut v) = Subscriber::find_many_by_user_id(&user.id, db).await {
+                subscribers.append(&mut v);
+            }
+        }
+        if subscribers.is_empty() {
+            return Vec::new();
+        }
+
+        let notification = Notification::new(evidence, db).await;
+        let deliveries = self.deliver(&subscribers, &notification).await;
+        Self::record(&subscribers, &deliveries, db).await;
+
+        deliveries
+    }
+    // Sends the daily summary of a digest user when it is due, quiet hours do not apply since
+    // the user picked the hour
+    pub async fn digest(
+        &mut self,
+        preference: &mut Preference,
+        timestamp: i64,
+        db: &Database,
+    ) -> Vec<Delivery> {
+        let (date_minimum, date_maximum) = match preference.digest_due(timestamp) {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        let user = match User::find_by_id(&preference.user_id, db).await {
+            Ok(v) => v,
+            Err(_) => return Vec::new(),
+        };
+
+        let query = EvidenceQuery {
+            date_minimum: Some(date_minimum),
+            date_maximum: Some(date_maximum - 1),
+            scope: match user.role {
+                UserRole::SuperAdmin => None,
+                _ => Some(user.cluster_id.clone()),
+            },
+            ..Default::default()
+        };
+        let evidences = Evidence::find_many(&query, db)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|e| preference.matches(e))
+            .collect::<Vec<_>>();
+
+        let mut deliveries = Vec::new();
+        if let Some(notification) = Notification::digest(&evidences, preference) {
+            let subscribers = Subscriber::find_many_by_user_id(&user.id, db)
+                .await
+                .unwrap_or_default();
+            deliveries = self.deliver(&subscribers, &notification).await;
+            Self::record(&subscribers, &deliveries, db).await;
+        }
+
+        if let Err(e) = preference.update_digest(timestamp, db).await {
+            println!("DIGEST FAILED: {:?}", e);
+        }
+        deliveries
+    }
+    // Alerts the next tier of the cluster's escalation policy about each open evidence that is
+    // due. Escalations skip preferences and batching, they exist because earlier alerts went
+    // unanswered.
+    pub async fn escalate(
+        &mut self,
+        cluster: &Cluster,
+        timestamp: i64,
+        db: &Database,
+    ) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+
+        for (escalation_count, tier) in cluster.escalation.iter().enumerate() {
+            let date_maximum = timestamp - tier.delay;
+            let mut evidences = match Evidence::find_many_by_escalation(
+                &cluster.id,
+                escalation_count,
+                date_maximum - ESCALATION_HORIZON,
+                date_maximum,
+                db,
+            )
+            .await
+            {
+                Ok(v) if !v.is_empty() => v,
+                _ => continue,
+            };
+
+            let users = User::find_many_by_cluster_id(&cluster.id, db)
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|u| u.role == tier.role)
+                .collect::<Vec<_>>();
+            let mut subscribers = Vec::new();
+            for user in users.iter() {
+                if let Ok(mut v) = Subscriber::find_many_by_user_id(&user.id, db).await {
+                    subscribers.append(&mut v);
+                }
+            }
+            subscribers.retain(|s| tier.channel.is_none_or(|c| s.kind.channel() == c));
+
+            for evidence in evidences.iter_mut() {
+                let escalation = EvidenceEscalation {
+                    role: tier.role,
+                    channel: tier.channel,
+                    user: users
+                        .iter()
+                        .map(|u| UserRef {
+                            id: u.id.clone(),
+                            name: u.name.clone(),
+                        })
+                        .collect(),
+                    timestamp,
+                };
+                match evidence.escalate(escalation, db).await {
+                    Ok(true) => (),
+                    Ok(false) => continue,
+                    Err(e) => {
+                        println!("ESCALATION FAILED: {:?}", e);
+                        continue;
+                    }
+                }
+
+                let notification = Notification::escalation(evidence, tier.delay, db).await;
+                let mut v = self.deliver(&subscribers, &notification).await;
+                Self::record(&subscribers, &v, db).await;
+
+                deliveries.append(&mut v);
+            }
+        }
+
+        deliveries
+    }
+    // Sends the summary of every batch whose window closed, a batch is deleted once its
+    // deliveries are recorded. A summary due in the user's quiet hours is dropped, like the
+    // evidence that arrives in them.
+    pub async fn flush(&mut self, timestamp: i64, db: &Database) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+
+        while let Ok(Some(batch)) = Batch::claim(timestamp, db).await {
+            let quiet = Preference::find_or_default(&batch.user_id, db)
+                .await
+                .quiet_at(timestamp);
+            let camera = match Camera::find_by_id(&batch.camera_id, db).await {
+                Ok(v) => v.name,
+                Err(_) => batch.camera_id.clone(),
+            };
+            if !quiet && let Some(notification) = Notification::batch(&batch, &camera) {
+                let subscribers = Subscriber::find_many_by_user_id(&batch.user_id, db)
+                    .await
+                    .unwrap_or_default();
+                let mut v = self.deliver(&subscribers, &notification).await;
+                Self::record(&subscribers, &v, db).await;
+
+                deliveries.append(&mut v);
+            }
+
+            if let Err(e) = batch.delete(db).await {
+                println!("BATCH DELETING FAILED: {:?}", e);
+            }
+        }
+
+        deliveries
+    }
+    // Records the deliveries and removes the subscribers whose recipient is gone
+    async fn record(subscribers: &[Subscriber], deliveries: &[Delivery], db: &Database) {
+        for delivery in deliveries.iter() {
+            if delivery.status == DeliveryStatus::Rejected
+                && let Some(subscriber) =
+                    subscribers.iter().find(|s| s.id == delivery.subscriber_id)
+            {
+                let _ = subscriber.delete(db).await;
+            }
+            if let Err(e) = delivery.save(db).await {
+                println!("DELIVERY RECORDING FAILED: {:?}", e);
+            }
+        }
+    }
+
+    // Sends the notification to each subscriber in order, keeping to the interval of the
+    // channel. Failed sends are queued for retry rather than waited on, their deliveries come
+    // from resend once they are final.
+    pub async fn deliver(
+        &mut self,
+        subscribers: &[Subscriber],
+        notification: &Notification,
+    ) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+
+        for subscriber in subscribers.iter() {
+            let retry = NotifierRetry {
+                subscriber: subscriber.clone(),
+                notification: notification.clone(),
+                attempt: 0,
+                backoff: Duration::ZERO,
+                due: Instant::now(),
+            };
+            if let Some(delivery) = self.attempt(retry).await {
+                deliveries.push(delivery);
+            }
+        }
+
+        deliveries
+    }
+    // Sends the queued retries whose backoff passed and records the ones that are final
+    pub async fn resend(&mut self, db: &Database) -> Vec<Delivery> {
+        let (subscribers, deliveries) = self.retry().await;
+        Self::record(&subscribers, &deliveries, db).await;
+
+        deliveries
+    }
+    async fn retry(&mut self) -> (Vec<Subscriber>, Vec<Delivery>) {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.retry)
+            .into_iter()
+            .partition::<Vec<_>, _>(|v| v.due <= now);
+        self.retry = pending;
+
+        let mut subscribers = Vec::new();
+        let mut deliveries = Vec::new();
+        for retry in due {
+            let subscriber = retry.subscriber.clone();
+            if let Some(delivery) = self.attempt(retry).await {
+                subscribers.push(subscriber);
+                deliveries.push(delivery);
+            }
+        }
+
+        (subscribers, deliveries)
+    }
+    // One send of the notification, None when it failed and was queued for another attempt
+    async fn attempt(&mut self, mut retry: NotifierRetry) -> Option<Delivery> {
+        let channel = self.channel.get_mut(&retry.subscriber.kind.channel())?;
+        let policy = channel.notifier.policy();
+
+        sleep_until(channel.next).await;
+        channel.next = Instant::now() + policy.interval;
+        retry.attempt += 1;
+
+        let (status, error) = match channel
+            .notifier
+            .send(&retry.subscriber.kind, &retry.notification)
+            .await
+        {
+            Ok(()) => (DeliveryStatus::Sent, None),
+            Err(NotifierError::Rejected(e)) => (DeliveryStatus::Rejected, Some(e)),
+            Err(NotifierError::Failed(e)) if retry.attempt >= policy.attempt.max(1) => {
+                (DeliveryStatus::Failed, Some(e))
+            }
+            Err(NotifierError::Failed(e)) => {
+                println!("SENDING FAILED: {}", e);
+                retry.backoff = match retry.attempt {
+                    1 => policy.backoff,
+                    _ => retry.backoff * 2,
+                };
+                retry.due = Instant::now() + retry.backoff;
+                self.retry.push(retry);
+                return None;
+            }
+        };
+
+        if let Some(e) = &error {
+            println!("SENDING FAILED: {}", e);
+        }
+        Some(Delivery::new(
+            &retry.notification.evidence_id,
+            &retry.subscriber,
+            status,
+            retry.attempt,
+            error,
+        ))
+    }
+}
+
+// Notifier that keeps what it is asked to send, for asserting on dispatched notifications
+#[cfg(test)]
+pub mod test {
+    use std::{
+        collections::{HashMap, VecDeque},
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+
+    use crate::models::subscriber::{SubscriberChannel, SubscriberKind};
+
+    use super::{Notification, Notifier, NotifierError, NotifierPolicy};
+
+    #[derive(Clone)]
+    pub struct MemoryNotifier {
+        pub channel: SubscriberChannel,
+        pub policy: NotifierPolicy,
+        pub sent: Arc<Mutex<Vec<(SubscriberKind, Notification)>>>, // Successful sends only
+        // Returned by the next sends to the recipient, in order
+        pub failure: Arc<Mutex<HashMap<SubscriberKind, VecDeque<NotifierError>>>>,
+    }
+
+    impl MemoryNotifier {
+        pub fn new(channel: SubscriberChannel, policy: NotifierPolicy) -> Self {
+            Self {
+                channel,
+                policy,
+                sent: Arc::new(Mutex::new(Vec::new())),
+                failure: Arc::new(Mutex::new(HashMap::new())),
+            }
+        }
+        pub fn fail(&self, kind: SubscriberKind, error: NotifierError) {
+            self.failure
+                .lock()
+                .unwrap()
+                .entry(kind)
+                .or_default()
+                .push_back(error);
+        }
+        pub fn sent(&self) -> Vec<(SubscriberKind, Notification)> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl Notifier for MemoryNotifier {
+        fn channel(&self) -> SubscriberChannel {
+            self.channel
+        }
+        fn policy(&self) -> NotifierPolicy {
+            self.policy
+        }
+        async fn send(
+            &self,
+            kind: &SubscriberKind,
+            notification: &Notification,
+        ) -> Result<(), NotifierError> {
+            if let Some(error) = self
+                .failure
+                .lock()
+                .unwrap()
+                .get_mut(kind)
+                .and_then(|v| v.pop_front())
+            {
+                return Err(error);
+            }
+
+            self.sent
+                .lock()
+                .unwrap()
+                .push((kind.clone(), notification.clone()));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::models::{
+        batch::Batch,
+        delivery::DeliveryStatus,
+        subscriber::{Subscriber, SubscriberChannel, SubscriberKind},
+    };
+
+    use super::{
+        Notification, NotifierDispatcher, NotifierError, NotifierPolicy, test::MemoryNotifier,
+    };
+
+    fn subscriber(id: &str, kind: SubscriberKind) -> Subscriber {
+        Subscriber {
+            id: id.to_string(),
+            user_id: String::from("user"),
+            kind,
+        }
+    }
+    fn notification() -> Notification {
+        Notification {
+            evidence_id: String::from("evidence"),
+            title: String::from("title"),
+            subtitle: String::from("subtitle"),
+            photo: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn deliver_retries_until_sent_or_rejected() {
+        let apple = MemoryNotifier::new(
+            SubscriberChannel::Apple,
+            NotifierPolicy {
+                attempt: 2,
+                backoff: Duration::ZERO,
+                interval: Duration::ZERO,
+            },
+        );
+        let mut dispatcher = NotifierDispatcher::default();
+        dispatcher.register(apple.clone());
+
+        let timeout = || NotifierError::Failed(String::from("timeout"));
+        apple.fail(SubscriberKind::Apple(String::from("a")), timeout());
+        apple.fail(
+            SubscriberKind::Apple(String::from("b")),
+            NotifierError::Rejected(String::from("gone")),
+        );
+        apple.fail(SubscriberKind::Apple(String::from("c")), timeout());
+        apple.fail(SubscriberKind::Apple(String::from("c")), timeout());
+
+        let subscribers = [
+            subscriber("retried", SubscriberKind::Apple(String::from("a"))),
+            subscriber("rejected", SubscriberKind::Apple(String::from("b"))),
+            subscriber("failed", SubscriberKind::Apple(String::from("c"))),
+            // No notifier is registered for the channel
+            subscriber("skipped", SubscriberKind::Telegram(String::from("42"))),
+        ];
+        // Failures are queued rather than retried in place
+        let deliveries = dispatcher.deliver(&subscribers, &notification()).await;
+        let outcome = deliveries
+            .iter()
+            .map(|d| (d.subscriber_id.as_str(), d.status, d.attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(outcome, [("rejected", DeliveryStatus::Rejected, 1)]);
+        assert_eq!(dispatcher.retry.len(), 2);
+
+        let (retried, deliveries) = dispatcher.retry().await;
+        let outcome = deliveries
+            .iter()
+            .map(|d| (d.subscriber_id.as_str(), d.status, d.attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcome,
+            [
+                ("retried", DeliveryStatus::Sent, 2),
+                ("failed", DeliveryStatus::Failed, 2),
+            ]
+        );
+        assert_eq!(retried.len(), 2);
+        assert_eq!(deliveries[1].error.as_deref(), Some("timeout"));
+        assert!(dispatcher.retry.is_empty());
+
+        let sent = apple.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, SubscriberKind::Apple(String::from("a")));
+        assert_eq!(sent[0].1.evidence_id, "evidence");
+    }
+
+    #[tokio::test]
+    async fn deliver_keeps_to_the_channel_interval() {
+        let telegram = MemoryNotifier::new(
+            SubscriberChannel::Telegram,
+            NotifierPolicy {
+                attempt: 1,
+                backoff: Duration::ZERO,
+                interval: Duration::from_millis(50),
+            },
+        );
+        let mut dispatcher = NotifierDispatcher::default();
+        dispatcher.register(telegram.clone());
+
+        let subscribers = (0..3)
+            .map(|i| subscriber(&i.to_string(), SubscriberKind::Telegram(i.to_string())))
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        dispatcher.deliver(&subscribers, &notification()).await;
+
+        assert_eq!(telegram.sent().len(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn batch_summarizes_the_held_back_evidence() {
+        let mut batch = Batch {
+            id: String::from("batch"),
+            user_id: String::from("user"),
+            cluster_id: String::from("cluster"),
+            camera_id: String::from("camera"),
+            evidence_id: Vec::new(),
+            violation_count: 0,
+            timestamp: 0,
+            expiry: 300000,
+            claimed: None,
+        };
+        assert!(Notification::batch(&batch, "Gerbang").is_none());
+
+        batch.evidence_id = vec![String::from("first"), String::from("last")];
+        batch.violation_count = 12;
+
+        let notification = Notification::batch(&batch, "Gerbang").unwrap();
+        assert_eq!(notification.evidence_id, "last");
+        assert_eq!(notification.title, "12 Pelanggaran Baru di Kamera Gerbang");
+        assert_eq!(notification.subtitle, "Dalam 5 menit terakhir");
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    models::subscriber::{SubscriberChannel, SubscriberKind},
+    telegram::{TelegramClient, TelegramError},
+};
+
+use super::{Notification, Notifier, NotifierError, NotifierPolicy};
+
+// Messages to chats linked through the bot, with the evidence photo when there is one
+pub struct TelegramNotifier {
+    client: TelegramClient,
+}
+
+impl TelegramNotifier {
+    pub fn new(client: TelegramClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    fn channel(&self) -> SubscriberChannel {
+        SubscriberChannel::Telegram
+    }
+    // The Bot API takes about 30 messages a second across all chats
+    fn policy(&self) -> NotifierPolicy {
+        NotifierPolicy {
+            interval: Duration::from_millis(35),
+            ..Default::default()
+        }
+    }
+    async fn send(
+        &self,
+        kind: &SubscriberKind,
+        notification: &Notification,
+    ) -> Result<(), NotifierError> {
+        let chat_id = match kind {
+            SubscriberKind::Telegram(v) => v,
+            _ => return Err(NotifierError::Rejected(String::from("UNSUPPORTED_KIND"))),
+        };
+
+        let caption = format!("{}\n{}", notification.title, notification.subtitle);
+        let result = match &notification.photo {
+            Some(photo) => {
+                self.client
+                    .send_photo(chat_id, &caption, photo.to_vec())
+                    .await
+            }
+            None => self.client.send_message(chat_id, &caption).await,
+        };
+
+        match result {
+            Ok(()) => Ok(()),
+            // The user blocked the bot or the chat is gone
+            Err(e @ TelegramError::Response(403, _)) => Err(NotifierError::Rejected(e.to_string())),
+            Err(e) => Err(NotifierError::Failed(e.to_string())),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, get, web};
+use mongodb::Database;
+
+use crate::{
+    helper::error_handler,
+    models::{
+        analytics::AnalyticsQuery,
+        user::{UserAuthentication, UserAuthorization},
+    },
+    views::analytics::ViewAnalytics,
+};
+
+// Violation time series, compliance rate, top offending cameras and the trend against the
+// previous period of the same length
+#[get("/violations", wrap = "UserAuthorization::any()")]
+pub async fn get_violation_analytics(
+    req: HttpRequest,
+    query: web::Query<AnalyticsQuery>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    let mut query = query.into_inner();
+    if let Some(cluster_id) = &query.cluster_id
+        && !issuer.allows(cluster_id)
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    query.scope = issuer.scope();
+
+    match ViewAnalytics::find(&query, db.get_ref()).await {
+        Ok(analytics) => HttpResponse::Ok().json(analytics),
+        Err(e) => error_handler(e),
+    }
+}
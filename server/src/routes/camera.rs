@@ -1,10 +1,31 @@
-use actix_web::{HttpResponse, get, web};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, get, web};
 use mongodb::Database;
 
-use crate::{helper::error_handler, models::camera::CameraQuery, views::camera::ViewCamera};
+use crate::{
+    helper::error_handler,
+    models::{camera::CameraQuery, user::UserAuthentication},
+    views::camera::ViewCamera,
+};
 
 #[get("")]
-pub async fn get_cameras(query: web::Query<CameraQuery>, db: web::Data<Database>) -> HttpResponse {
+pub async fn get_cameras(
+    req: HttpRequest,
+    query: web::Query<CameraQuery>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    let mut query = query.into_inner();
+    if let Some(cluster_id) = &query.cluster_id
+        && !issuer.allows(cluster_id)
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    query.scope = issuer.scope();
+
     match ViewCamera::find_many(&query, db.get_ref()).await {
         Ok(cameras) => HttpResponse::Ok().json(cameras),
         Err(e) => error_handler(e),
@@ -1,33 +1,39 @@
-use actix_web::{HttpMessage, HttpRequest, HttpResponse, delete, get, post, web};
+use actix_files::NamedFile;
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse, delete, get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    post, put, web,
+};
+use chrono::{DateTime, Utc};
 use mongodb::Database;
 
 use crate::{
-    helper::error_handler,
+    helper::{cluster_forbidden, error_handler},
     models::{
         cluster::{Cluster, ClusterQuery, ClusterRequest},
-        user::{User, UserAuthentication, UserRole},
+        enrollment::{Enrollment, EnrollmentRequest},
+        event::EventKind,
+        report::{Report, ReportFormat, ReportQuery},
+        user::{UserAuthentication, UserAuthorization, UserRole},
     },
-    views::cluster::ViewCluster,
+    views::{cluster::ViewCluster, report::ViewReport},
 };
 
-#[post("")]
+#[post("", wrap = "UserAuthorization::role(&[UserRole::SuperAdmin])")]
 pub async fn create_cluster(
     payload: web::Json<ClusterRequest>,
     db: web::Data<Database>,
 ) -> HttpResponse {
     let request = payload.into_inner();
+    if let Err(e) = request.check() {
+        return error_handler(e);
+    }
 
     let cluster = Cluster::from(request);
 
+    // Members are assigned explicitly through the users, super admins see every cluster
     match cluster.save(db.get_ref()).await {
         Ok(()) => {
-            if let Ok(mut users) = User::find_all(db.get_ref()).await {
-                for mut user in users.drain(..) {
-                    user.cluster_id.push(cluster.id.clone());
-                    let _ = user.update(None, db.get_ref()).await;
-                }
-            }
-
             let query = ClusterQuery {
                 cluster_id: Some(vec![cluster.id.clone()]),
                 text: None,
@@ -41,7 +47,7 @@ pub async fn create_cluster(
     }
 }
 
-#[get("")]
+#[get("", wrap = "UserAuthorization::any()")]
 pub async fn get_clusters(
     req: HttpRequest,
     query: web::Query<ClusterQuery>,
@@ -54,12 +60,13 @@ pub async fn get_clusters(
 
     let mut query = query.into_inner();
     if issuer.role != UserRole::SuperAdmin {
-        let user = match User::find_by_id(&issuer.id, db.get_ref()).await {
-            Ok(v) => v,
-            _ => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
-        };
-
-        query.cluster_id = Some(user.cluster_id);
+        query.cluster_id = Some(match query.cluster_id {
+            Some(cluster_id) => cluster_id
+                .into_iter()
+                .filter(|v| issuer.allows(v))
+                .collect(),
+            None => issuer.cluster_id.clone(),
+        });
     }
 
     match ViewCluster::find_many(&query, db.get_ref()).await {
@@ -68,8 +75,9 @@ pub async fn get_clusters(
     }
 }
 
-#[get("/{cluster_id}")]
+#[get("/{cluster_id}", wrap = "UserAuthorization::any()")]
 pub async fn get_cluster(
+    req: HttpRequest,
     cluster_id: web::Path<String>,
     query: web::Query<ClusterQuery>,
     db: web::Data<Database>,
@@ -78,6 +86,9 @@ pub async fn get_cluster(
         Ok(v) => v,
         _ => return HttpResponse::BadRequest().body("INVALID_ID"),
     };
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
 
     let mut query = query.into_inner();
     query.cluster_id = Some(vec![cluster_id]);
@@ -88,7 +99,308 @@ pub async fn get_cluster(
     }
 }
 
-#[delete("/{cluster_id}")]
+#[put(
+    "/{cluster_id}",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn update_cluster(
+    req: HttpRequest,
+    cluster_id: web::Path<String>,
+    payload: web::Json<ClusterRequest>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let cluster_id = match cluster_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
+
+    let request = payload.into_inner();
+    if let Err(e) = request.check() {
+        return error_handler(e);
+    }
+
+    let mut cluster = match Cluster::find_by_id(&cluster_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(e) => return error_handler(e),
+    };
+
+    match cluster.update(request, db.get_ref()).await {
+        Ok(()) => {
+            let query = ClusterQuery {
+                cluster_id: Some(vec![cluster.id.clone()]),
+                text: None,
+                date_maximum: None,
+                date_minimum: None,
+            };
+
+            HttpResponse::Ok().json(ViewCluster::find_one(&query, db.get_ref()).await.unwrap())
+        }
+        Err(e) => error_handler(e),
+    }
+}
+
+// Dry run of the retention policy, reports the evidence that the sweeper would delete
+#[get(
+    "/{cluster_id}/retention",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn get_cluster_retention(
+    req: HttpRequest,
+    cluster_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let cluster_id = match cluster_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
+
+    let cluster = match Cluster::find_by_id(&cluster_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(e) => return error_handler(e),
+    };
+
+    match cluster.expire(true, db.get_ref()).await {
+        Ok(retention) => HttpResponse::Ok().json(retention),
+        Err(e) => error_handler(e),
+    }
+}
+
+// Period covered by a report downloaded on demand when the query does not say
+const REPORT_PERIOD: i64 = 7 * 24 * 60 * 60 * 1000;
+
+// Report of the cluster rendered on demand, the last week when no period is given
+#[get("/{cluster_id}/reports", wrap = "UserAuthorization::any()")]
+pub async fn get_cluster_report(
+    req: HttpRequest,
+    cluster_id: web::Path<String>,
+    query: web::Query<ReportQuery>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let cluster_id = match cluster_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
+
+    let cluster = match Cluster::find_by_id(&cluster_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(e) => return error_handler(e),
+    };
+
+    let date_maximum = query
+        .date_maximum
+        .unwrap_or_else(|| Utc::now().timestamp_millis());
+    let date_minimum = query.date_minimum.unwrap_or(date_maximum - REPORT_PERIOD);
+    let format = query.format.unwrap_or(ReportFormat::Pdf);
+
+    let report = match ViewReport::find(&cluster, date_minimum, date_maximum, db.get_ref()).await {
+        Ok(v) => v,
+        Err(e) => return error_handler(e),
+    };
+    // Thumbnails are decoded from disk, off the async workers
+    let data = match web::block(move || report.render(format)).await {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => return error_handler(e),
+        Err(_) => return error_handler(EventKind::RenderingFailed),
+    };
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(report_disposition(
+            &cluster.id,
+            date_minimum,
+            date_maximum,
+            format,
+        ))
+        .body(data)
+}
+
+// Reports generated by the cluster's schedule
+#[get("/{cluster_id}/reports/stored", wrap = "UserAuthorization::any()")]
+pub async fn get_cluster_stored_reports(
+    req: HttpRequest,
+    cluster_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let cluster_id = match cluster_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
+
+    match Report::find_many(&cluster_id, db.get_ref()).await {
+        Ok(reports) => HttpResponse::Ok().json(reports),
+        Err(e) => error_handler(e),
+    }
+}
+
+#[get(
+    "/{cluster_id}/reports/stored/{report_id}",
+    wrap = "UserAuthorization::any()"
+)]
+pub async fn get_cluster_stored_report(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<ReportQuery>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let (cluster_id, report_id) = path.into_inner();
+
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
+
+    let report = match Report::find_by_id(&report_id, db.get_ref()).await {
+        Ok(v) if v.cluster_id == cluster_id => v,
+        Ok(_) => return HttpResponse::NotFound().body("NOT_FOUND"),
+        Err(e) => return error_handler(e),
+    };
+
+    let format = query.format.unwrap_or(ReportFormat::Pdf);
+    match NamedFile::open_async(report.file(format)).await {
+        Ok(file) => {
+            let disposition = report_disposition(
+                &report.cluster_id,
+                report.date_minimum,
+                report.date_maximum,
+                format,
+            );
+            file.set_content_disposition(disposition)
+                .into_response(&req)
+        }
+        Err(_) => HttpResponse::NotFound().body("NOT_FOUND"),
+    }
+}
+
+fn report_disposition(
+    cluster_id: &String,
+    date_minimum: i64,
+    date_maximum: i64,
+    format: ReportFormat,
+) -> ContentDisposition {
+    let date = |timestamp: i64| {
+        DateTime::from_timestamp_millis(timestamp)
+            .map(|v| v.format("%Y%m%d").to_string())
+            .unwrap_or_default()
+    };
+
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(format!(
+            "report_{}_{}_{}.{}",
+            cluster_id,
+            date(date_minimum),
+            date(date_maximum),
+            format.extension()
+        ))],
+    }
+}
+
+// Issue a one-time code a processor can redeem to join the cluster
+#[post(
+    "/{cluster_id}/enrollments",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn create_cluster_enrollment(
+    req: HttpRequest,
+    cluster_id: web::Path<String>,
+    payload: web::Json<EnrollmentRequest>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let cluster_id: String = match cluster_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
+
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    if let Err(e) = Cluster::find_by_id(&cluster_id, db.get_ref()).await {
+        return error_handler(e);
+    }
+
+    let enrollment = Enrollment::new(cluster_id, issuer.id.clone(), payload.into_inner());
+    match enrollment.save(db.get_ref()).await {
+        Ok(()) => HttpResponse::Created().json(enrollment),
+        Err(e) => error_handler(e),
+    }
+}
+
+#[get(
+    "/{cluster_id}/enrollments",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn get_cluster_enrollments(
+    req: HttpRequest,
+    cluster_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let cluster_id: String = match cluster_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
+
+    match Enrollment::find_many(&cluster_id, db.get_ref()).await {
+        Ok(enrollments) => HttpResponse::Ok().json(enrollments),
+        Err(e) => error_handler(e),
+    }
+}
+
+// Revoke a code, redeemed codes are kept as the record of who enrolled the processor
+#[delete(
+    "/{cluster_id}/enrollments/{enrollment_id}",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn delete_cluster_enrollment(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let (cluster_id, enrollment_id) = path.into_inner();
+
+    if let Some(response) = cluster_forbidden(&req, &cluster_id) {
+        return response;
+    }
+
+    match Enrollment::find_by_id(&enrollment_id, db.get_ref()).await {
+        Ok(enrollment) if enrollment.cluster_id == cluster_id => {
+            if enrollment.processor_id.is_some() {
+                return HttpResponse::BadRequest().body("ENROLLMENT_ALREADY_REDEEMED");
+            }
+            match enrollment.delete(db.get_ref()).await {
+                Ok(()) => HttpResponse::NoContent().finish(),
+                Err(e) => error_handler(e),
+            }
+        }
+        Ok(_) => HttpResponse::NotFound().body("NOT_FOUND"),
+        Err(e) => error_handler(e),
+    }
+}
+
+#[delete(
+    "/{cluster_id}",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin])"
+)]
 pub async fn delete_cluster(
     cluster_id: web::Path<String>,
     db: web::Data<Database>,
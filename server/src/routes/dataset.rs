@@ -0,0 +1,43 @@
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, post, web};
+use mongodb::Database;
+
+use crate::{
+    helper::error_handler,
+    models::{
+        evidence::EvidenceQuery,
+        export::{Export, ExportKind},
+        user::{UserAuthentication, UserAuthorization, UserRole},
+    },
+    views::export::ViewExport,
+};
+
+// Reviewed evidence as a YOLO dataset, with the corrections applied to the labels. The archive
+// is built in the background like an evidence export and downloaded from /exports.
+#[post(
+    "/export",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn export_dataset(
+    req: HttpRequest,
+    payload: web::Json<EvidenceQuery>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    let mut query = payload.into_inner();
+    if let Some(cluster_id) = &query.cluster_id
+        && !issuer.allows(cluster_id)
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    query.scope = issuer.scope();
+
+    let export = Export::new(issuer.id.clone(), ExportKind::Dataset, &query);
+    match export.save(db.get_ref()).await {
+        Ok(()) => HttpResponse::Accepted().json(ViewExport::from(export)),
+        Err(e) => error_handler(e),
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use actix_web::{HttpRequest, HttpResponse, post, web};
+use mongodb::Database;
+use tokio::sync::RwLock;
+
+use crate::{
+    helper::error_handler,
+    models::{
+        enrollment::{
+            Enrollment, EnrollmentCredential, EnrollmentCredentialPath, EnrollmentRedemption,
+        },
+        event::EventKind,
+        processor::Processor,
+        signature::{Signature, SignatureDigest},
+    },
+};
+
+// Called by a processor to exchange a one-time code for its webhook configuration. A processor
+// that is already paired signs the request with its current secret to pair again.
+#[post("")]
+pub async fn redeem_enrollment(
+    body: web::Bytes,
+    req: HttpRequest,
+    signature: web::Data<Arc<RwLock<Signature>>>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    // Parsed by hand because the signature covers the raw body
+    let payload = match serde_json::from_slice::<EnrollmentRedemption>(&body) {
+        Ok(v) => v,
+        Err(_) => return HttpResponse::BadRequest().body("INVALID_PAYLOAD"),
+    };
+
+    let proven = match Processor::find_by_id(&payload.processor.id, db.get_ref()).await {
+        Ok(Processor {
+            secret: Some(secret),
+            ..
+        }) => {
+            let mut signature = signature.write().await;
+            signature
+                .verify(&secret, &req, &SignatureDigest::body(&body))
+                .is_ok()
+        }
+        _ => false,
+    };
+
+    let (processor, secret) =
+        match Enrollment::enroll(&payload.code, payload.processor, proven, db.get_ref()).await {
+            Ok(v) => v,
+            Err(EventKind::AlreadyEnrolled) => {
+                return HttpResponse::Conflict().body("PROCESSOR_ENROLLED_ELSEWHERE");
+            }
+            Err(e) => return error_handler(e),
+        };
+
+    let base_path = std::env::var("BASE_PATH").unwrap();
+    HttpResponse::Created().json(EnrollmentCredential {
+        path: EnrollmentCredentialPath {
+            evidence: format!("{}/evidences/{}", base_path, processor.id),
+            update: format!("{}/processors/{}", base_path, processor.cluster_id),
+        },
+        cluster_id: processor.cluster_id,
+        processor_id: processor.id,
+        secret,
+    })
+}
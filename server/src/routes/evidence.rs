@@ -1,41 +1,54 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::VecDeque,
     fs::{self, File},
     io::Write,
     sync::Arc,
 };
 
-use actix::{Addr, Recipient};
+use actix_files::NamedFile;
 use actix_multipart::Multipart;
-use actix_web::{HttpResponse, get, post, web};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, get, post, web};
+use chrono::Utc;
 use futures::StreamExt;
 use mongodb::Database;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::{
-    central::{CentralWebSocket, CentralWebSocketMessage, CentralWebSocketResponse},
-    helper::error_handler,
+    central::{self, CentralClient, CentralWebSocketResponse},
+    helper::{cluster_forbidden, error_handler},
     models::{
-        evidence::{Evidence, EvidenceQuery, EvidenceRequest},
+        event::EventKind,
+        evidence::{
+            Evidence, EvidenceCorrectionRequest, EvidenceQuery, EvidenceRequest,
+            EvidenceReviewRequest, EvidenceStatus,
+        },
+        export::{Export, ExportKind},
         processor::Processor,
+        queue::QueuedEvidence,
+        signature::{Signature, SignatureDigest},
+        user::{User, UserAuthentication, UserAuthorization, UserRole},
+    },
+    views::{
+        evidence::{ViewEvidence, ViewEvidenceReview},
+        export::ViewExport,
+        user::UserRef,
     },
-    views::evidence::ViewEvidence,
 };
 
 #[post("/{processor_id}")]
 pub async fn create_evidence(
     processor_id: web::Path<String>,
     mut payload: Multipart,
+    req: HttpRequest,
+    signature: web::Data<Arc<RwLock<Signature>>>,
     db: web::Data<Database>,
 
     // In-memory evidence queue for notification distribution
     queue: web::Data<Arc<RwLock<VecDeque<Evidence>>>>,
 
     // Websocket client
-    client: web::Data<
-        Arc<RwLock<HashMap<Recipient<CentralWebSocketMessage>, (String, Addr<CentralWebSocket>)>>>,
-    >,
+    client: web::Data<CentralClient>,
 ) -> HttpResponse {
     let processor_id: String = processor_id.into_inner();
 
@@ -47,8 +60,13 @@ pub async fn create_evidence(
 
     // Collect multipart fields
     let mut image_data: Option<Vec<u8>> = None;
+    let mut annotated_data: Option<Vec<u8>> = None;
+    let mut clip_data: Option<Vec<u8>> = None;
     let mut evidence_data: Option<EvidenceRequest> = None;
 
+    // Digest of every part as received, checked against the processor's signature
+    let mut digest = SignatureDigest::default();
+
     while let Some(item) = payload.next().await {
         let mut field = match item {
             Ok(v) => v,
@@ -60,26 +78,41 @@ pub async fn create_evidence(
             None => continue,
         };
 
-        if field_name == "image" {
-            let mut data = Vec::new();
-            while let Some(chunk) = field.next().await {
-                match chunk {
-                    Ok(bytes) => data.extend_from_slice(&bytes),
-                    Err(_) => break,
-                }
+        let mut data = Vec::new();
+        while let Some(chunk) = field.next().await {
+            match chunk {
+                Ok(bytes) => data.extend_from_slice(&bytes),
+                Err(_) => break,
             }
+        }
+        digest.push(&field_name, &data);
+
+        if field_name == "image" {
             if !data.is_empty() {
                 image_data = Some(data);
             }
-        } else if field_name == "data" {
-            let mut json_string = String::new();
-            while let Some(chunk) = field.next().await {
-                match chunk {
-                    Ok(bytes) => json_string.push_str(&String::from_utf8_lossy(&bytes)),
-                    Err(_) => break,
-                }
+        } else if field_name == "annotated" {
+            if !data.is_empty() {
+                annotated_data = Some(data);
             }
-            evidence_data = serde_json::from_str::<EvidenceRequest>(&json_string).ok();
+        } else if field_name == "clip" {
+            if !data.is_empty() {
+                clip_data = Some(data);
+            }
+        } else if field_name == "data" {
+            evidence_data = serde_json::from_slice::<EvidenceRequest>(&data).ok();
+        }
+    }
+
+    // Only processors holding a provisioned secret may submit evidence
+    {
+        let secret = match &processor.secret {
+            Some(v) => v,
+            None => return error_handler(EventKind::Unauthorized),
+        };
+        let mut signature = signature.write().await;
+        if let Err(e) = signature.verify(secret, &req, &digest.finish()) {
+            return error_handler(e);
         }
     }
 
@@ -111,6 +144,36 @@ pub async fn create_evidence(
         return HttpResponse::InternalServerError().body("FAILED_TO_SAVE_IMAGE");
     }
 
+    // Save the processor-rendered image to ./evidences/{EVIDENCE_ID}.annotated.jpg, it is optional
+    // so a failure only loses the overlay
+    let annotated_path = format!("./evidence/{}.annotated.jpg", evidence_id);
+    if let Some(annotated_data) = annotated_data {
+        let annotated_path = annotated_path.clone();
+        let _ = web::block(move || {
+            let mut file = File::create(&annotated_path)?;
+            file.write_all(&annotated_data)
+        })
+        .await;
+    }
+
+    // Save the video clip to ./evidences/{EVIDENCE_ID}.avi, the evidence is kept without it
+    // if writing fails
+    let clip_path = format!("./evidence/{}.avi", evidence_id);
+    let clip = match clip_data {
+        Some(clip_data) => {
+            let clip_path = clip_path.clone();
+            web::block(move || {
+                let mut file = File::create(&clip_path)?;
+                file.write_all(&clip_data)
+            })
+            .await
+            .ok()
+            .and_then(|r| r.ok())
+            .map(|_| format!("{}.avi", evidence_id))
+        }
+        None => None,
+    };
+
     // Create evidence record
     let evidence = Evidence {
         id: evidence_id,
@@ -120,22 +183,25 @@ pub async fn create_evidence(
         frame_id: evidence_data.frame_id,
         timestamp: evidence_data.timestamp,
         person: evidence_data.person,
+        clip,
+        status: EvidenceStatus::Open,
+        review: Vec::new(),
+        correction: Vec::new(),
+        escalation: Vec::new(),
     };
 
     // Save to database
     match evidence.save(db.get_ref()).await {
         Ok(_) => {
-            // Notify all connected clients about new evidence
-            let payload = serde_json::to_string(&CentralWebSocketResponse::Evidence(
+            // Notify the connected clients of the cluster about new evidence
+            let payload = CentralWebSocketResponse::Evidence(
                 ViewEvidence::from(evidence.clone(), db.get_ref()).await,
-            ))
-            .unwrap();
+            );
+            central::broadcast(&client, &evidence.cluster_id, &payload, db.get_ref()).await;
 
-            let client = client.read().await;
-            for (_, (_, client)) in (*client).iter() {
-                client.do_send(CentralWebSocketMessage(payload.clone()));
+            if let Err(e) = QueuedEvidence::push(&evidence, db.get_ref()).await {
+                println!("QUEUEING FAILED: {:?}", e);
             }
-
             {
                 let mut evidence_queue = queue.write().await;
                 evidence_queue.push_back(evidence);
@@ -146,31 +212,269 @@ pub async fn create_evidence(
         Err(e) => {
             // Delete the image if database save fails
             let _ = fs::remove_file(&file_path);
+            let _ = fs::remove_file(&annotated_path);
+            let _ = fs::remove_file(&clip_path);
             error_handler(e)
         }
     }
 }
 
-#[get("")]
+#[get("", wrap = "UserAuthorization::any()")]
 pub async fn get_evidences(
+    req: HttpRequest,
     query: web::Query<EvidenceQuery>,
     db: web::Data<Database>,
 ) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    let mut query = query.into_inner();
+    if let Some(cluster_id) = &query.cluster_id
+        && !issuer.allows(cluster_id)
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    query.scope = issuer.scope();
+
     match ViewEvidence::find_many(&query, db.get_ref()).await {
         Ok(evidences) => HttpResponse::Ok().json(evidences),
-        Err(_) => HttpResponse::NotFound().body("NOT_FOUND"),
+        Err(e) => error_handler(e),
+    }
+}
+
+// Queue a ZIP archive of the matching evidence, its progress is followed through /exports
+#[post("/export", wrap = "UserAuthorization::any()")]
+pub async fn export_evidences(
+    req: HttpRequest,
+    payload: web::Json<EvidenceQuery>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    let mut query = payload.into_inner();
+    if let Some(cluster_id) = &query.cluster_id
+        && !issuer.allows(cluster_id)
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    query.scope = issuer.scope();
+
+    let export = Export::new(issuer.id.clone(), ExportKind::Evidence, &query);
+    match export.save(db.get_ref()).await {
+        Ok(()) => HttpResponse::Accepted().json(ViewExport::from(export)),
+        Err(e) => error_handler(e),
     }
 }
 
-#[get("/{evidence_id}")]
-pub async fn get_evidence(evidence_id: web::Path<String>, db: web::Data<Database>) -> HttpResponse {
+#[get("/{evidence_id}", wrap = "UserAuthorization::any()")]
+pub async fn get_evidence(
+    req: HttpRequest,
+    evidence_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
     let evidence_id = match evidence_id.parse() {
         Ok(v) => v,
         _ => return HttpResponse::BadRequest().body("INVALID_ID"),
     };
 
     match ViewEvidence::find_by_id(&evidence_id, db.get_ref()).await {
-        Ok(evidence) => HttpResponse::Ok().json(evidence),
+        Ok(evidence) => match cluster_forbidden(&req, &evidence.cluster.id) {
+            Some(response) => response,
+            None => HttpResponse::Ok().json(evidence),
+        },
+        Err(_) => HttpResponse::NotFound().body("NOT_FOUND"),
+    }
+}
+
+// Status and transitions of the evidence's review
+#[get("/{evidence_id}/review", wrap = "UserAuthorization::any()")]
+pub async fn get_evidence_review(
+    req: HttpRequest,
+    evidence_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let evidence_id = match evidence_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+
+    match Evidence::find_by_id(&evidence_id, db.get_ref()).await {
+        Ok(evidence) => match cluster_forbidden(&req, &evidence.cluster_id) {
+            Some(response) => response,
+            None => HttpResponse::Ok().json(ViewEvidenceReview {
+                id: evidence.id,
+                status: evidence.status,
+                review: evidence.review,
+                correction: evidence.correction,
+            }),
+        },
+        Err(_) => HttpResponse::NotFound().body("NOT_FOUND"),
+    }
+}
+
+// Acknowledge, dispute as a false positive, resolve or reopen the evidence. Officers only
+// acknowledge open evidence of their clusters, which is what ends its escalation.
+#[post("/{evidence_id}/review", wrap = "UserAuthorization::any()")]
+pub async fn review_evidence(
+    req: HttpRequest,
+    evidence_id: web::Path<String>,
+    payload: web::Json<EvidenceReviewRequest>,
+    db: web::Data<Database>,
+
+    // Websocket client
+    client: web::Data<CentralClient>,
+) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+    let evidence_id = match evidence_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+
+    let mut evidence = match Evidence::find_by_id(&evidence_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(_) => return HttpResponse::NotFound().body("NOT_FOUND"),
+    };
+    if let Some(response) = cluster_forbidden(&req, &evidence.cluster_id) {
+        return response;
+    }
+    if issuer.role == UserRole::Officer
+        && (evidence.status != EvidenceStatus::Open
+            || payload.status != EvidenceStatus::Acknowledged)
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+
+    let user = match User::find_by_id(&issuer.id, db.get_ref()).await {
+        Ok(v) => UserRef {
+            id: v.id,
+            name: v.name,
+        },
+        Err(e) => return error_handler(e),
+    };
+
+    if let Err(e) = evidence
+        .review(
+            payload.into_inner(),
+            user,
+            Utc::now().timestamp_millis(),
+            db.get_ref(),
+        )
+        .await
+    {
+        return match e {
+            EventKind::InvalidTransition => HttpResponse::Conflict().body(e.to_string()),
+            e => error_handler(e),
+        };
+    }
+
+    let evidence = ViewEvidence::from(evidence, db.get_ref()).await;
+
+    // Notify the connected clients of the cluster about the new status
+    let payload = CentralWebSocketResponse::Review(evidence.clone());
+    central::broadcast(&client, &evidence.cluster.id, &payload, db.get_ref()).await;
+
+    HttpResponse::Ok().json(evidence)
+}
+
+// Evidence image, annotated image or clip, only for users of the evidence's cluster
+#[get("/{file_name}", wrap = "UserAuthorization::any()")]
+pub async fn get_evidence_file(
+    req: HttpRequest,
+    file_name: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let file_name = file_name.into_inner();
+    let evidence_id = match file_name.split_once('.') {
+        Some((id, _)) => id.to_string(),
+        None => return HttpResponse::NotFound().body("NOT_FOUND"),
+    };
+    // Only the evidence's own files are served, never a path outside ./evidence
+    let file = match Evidence::file(&evidence_id)
+        .into_iter()
+        .find(|f| f.strip_prefix("./evidence/") == Some(file_name.as_str()))
+    {
+        Some(v) => v,
+        None => return HttpResponse::NotFound().body("NOT_FOUND"),
+    };
+
+    let evidence = match Evidence::find_by_id(&evidence_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(_) => return HttpResponse::NotFound().body("NOT_FOUND"),
+    };
+    if let Some(response) = cluster_forbidden(&req, &evidence.cluster_id) {
+        return response;
+    }
+
+    match NamedFile::open_async(&file).await {
+        Ok(file) => file.into_response(&req),
         Err(_) => HttpResponse::NotFound().body("NOT_FOUND"),
     }
 }
+
+// Replace the violations of a person, the corrected labels are used for retraining
+#[post(
+    "/{evidence_id}/review/correction",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn correct_evidence(
+    req: HttpRequest,
+    evidence_id: web::Path<String>,
+    payload: web::Json<EvidenceCorrectionRequest>,
+    db: web::Data<Database>,
+
+    // Websocket client
+    client: web::Data<CentralClient>,
+) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+    let evidence_id = match evidence_id.parse() {
+        Ok(v) => v,
+        _ => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+
+    let mut evidence = match Evidence::find_by_id(&evidence_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(_) => return HttpResponse::NotFound().body("NOT_FOUND"),
+    };
+    if let Some(response) = cluster_forbidden(&req, &evidence.cluster_id) {
+        return response;
+    }
+
+    let user = match User::find_by_id(&issuer.id, db.get_ref()).await {
+        Ok(v) => UserRef {
+            id: v.id,
+            name: v.name,
+        },
+        Err(e) => return error_handler(e),
+    };
+
+    if let Err(e) = evidence
+        .correct(
+            payload.into_inner(),
+            user,
+            Utc::now().timestamp_millis(),
+            db.get_ref(),
+        )
+        .await
+    {
+        return error_handler(e);
+    }
+
+    let evidence = ViewEvidence::from(evidence, db.get_ref()).await;
+
+    // Notify the connected clients of the cluster about the new labels
+    let payload = CentralWebSocketResponse::Review(evidence.clone());
+    central::broadcast(&client, &evidence.cluster.id, &payload, db.get_ref()).await;
+
+    HttpResponse::Ok().json(evidence)
+}
@@ -0,0 +1,90 @@
+use actix_files::NamedFile;
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse, get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web,
+};
+use mongodb::Database;
+
+use crate::{
+    helper::error_handler,
+    models::{
+        export::{Export, ExportStatus},
+        user::{UserAuthentication, UserAuthorization},
+    },
+    views::export::ViewExport,
+};
+
+#[get("", wrap = "UserAuthorization::any()")]
+pub async fn get_exports(req: HttpRequest, db: web::Data<Database>) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    match Export::find_many(&issuer.id, db.get_ref()).await {
+        Ok(exports) => HttpResponse::Ok().json(
+            exports
+                .into_iter()
+                .map(ViewExport::from)
+                .collect::<Vec<ViewExport>>(),
+        ),
+        Err(e) => error_handler(e),
+    }
+}
+
+#[get("/{export_id}", wrap = "UserAuthorization::any()")]
+pub async fn get_export(
+    req: HttpRequest,
+    export_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    match find_export(&req, &export_id, db.get_ref()).await {
+        Ok(export) => HttpResponse::Ok().json(ViewExport::from(export)),
+        Err(response) => response,
+    }
+}
+
+// The archive is streamed from disk
+#[get("/{export_id}/download", wrap = "UserAuthorization::any()")]
+pub async fn download_export(
+    req: HttpRequest,
+    export_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let export = match find_export(&req, &export_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(response) => return response,
+    };
+    if export.status != ExportStatus::Completed {
+        return HttpResponse::Conflict().body("EXPORT_NOT_READY");
+    }
+
+    match NamedFile::open_async(export.file()).await {
+        Ok(file) => file
+            .set_content_disposition(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(export.file_name())],
+            })
+            .into_response(&req),
+        Err(_) => HttpResponse::NotFound().body("NOT_FOUND"),
+    }
+}
+
+// Exports are only visible to the user who requested them
+async fn find_export(
+    req: &HttpRequest,
+    export_id: &String,
+    db: &Database,
+) -> Result<Export, HttpResponse> {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return Err(HttpResponse::Unauthorized().body("UNAUTHORIZED")),
+    };
+
+    match Export::find_by_id(export_id, db).await {
+        Ok(export) if export.user_id == issuer.id => Ok(export),
+        Ok(_) => Err(HttpResponse::NotFound().body("NOT_FOUND")),
+        Err(e) => Err(error_handler(e)),
+    }
+}
@@ -1,8 +1,12 @@
 use actix_web::{HttpResponse, get};
 
+pub mod analytics;
 pub mod camera;
 pub mod cluster;
+pub mod dataset;
+pub mod enrollment;
 pub mod evidence;
+pub mod export;
 pub mod processor;
 pub mod subscriber;
 pub mod user;
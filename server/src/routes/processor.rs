@@ -1,17 +1,19 @@
 use std::{collections::HashMap, sync::Arc};
 
-use actix_web::{HttpResponse, delete, get, post, put, web};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, delete, get, post, put, web};
 use chrono::Local;
 use mongodb::Database;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
 use crate::{
-    helper::error_handler,
+    helper::{cluster_forbidden, error_handler},
     models::{
         camera::{Camera, CameraQuery, CameraRequest},
         cluster::Cluster,
-        processor::{Processor, ProcessorQuery, ProcessorRequest},
+        processor::{Processor, ProcessorCredential, ProcessorQuery, ProcessorRequest},
+        signature::{Signature, SignatureDigest},
+        user::{UserAuthentication, UserAuthorization, UserRole},
     },
     views::processor::ViewProcessor,
 };
@@ -22,8 +24,12 @@ struct ProcessorSynchronization {
     camera: Vec<CameraRequest>,
 }
 
-#[delete("/{processor_id}")]
+#[delete(
+    "/{processor_id}",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
 pub async fn delete_processor(
+    req: HttpRequest,
     processor_id: web::Path<String>,
     db: web::Data<Database>,
 ) -> HttpResponse {
@@ -34,6 +40,9 @@ pub async fn delete_processor(
 
     match Processor::find_by_id(&processor_id, db.get_ref()).await {
         Ok(processor) => {
+            if let Some(response) = cluster_forbidden(&req, &processor.cluster_id) {
+                return response;
+            }
             let _ = processor.delete(db.get_ref()).await;
 
             HttpResponse::NoContent().finish()
@@ -42,19 +51,34 @@ pub async fn delete_processor(
     }
 }
 
-#[get("")]
+#[get("", wrap = "UserAuthorization::any()")]
 pub async fn get_processors(
+    req: HttpRequest,
     query: web::Query<ProcessorQuery>,
     db: web::Data<Database>,
 ) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    let mut query = query.into_inner();
+    if let Some(cluster_id) = &query.cluster_id
+        && !issuer.allows(cluster_id)
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    query.scope = issuer.scope();
+
     match ViewProcessor::find_many(&query, db.get_ref()).await {
         Ok(processors) => HttpResponse::Ok().json(processors),
         Err(e) => error_handler(e),
     }
 }
 
-#[get("/{processor_id}")]
+#[get("/{processor_id}", wrap = "UserAuthorization::any()")]
 pub async fn get_processor(
+    req: HttpRequest,
     processor_id: web::Path<String>,
     query: web::Query<ProcessorQuery>,
     db: web::Data<Database>,
@@ -63,6 +87,14 @@ pub async fn get_processor(
         Ok(v) => v,
         _ => return HttpResponse::BadRequest().body("INVALID_ID"),
     };
+    match Processor::find_by_id(&processor_id, db.get_ref()).await {
+        Ok(processor) => {
+            if let Some(response) = cluster_forbidden(&req, &processor.cluster_id) {
+                return response;
+            }
+        }
+        Err(e) => return error_handler(e),
+    }
 
     let mut query = query.into_inner();
     query.processor_id = Some(processor_id);
@@ -76,14 +108,21 @@ pub async fn get_processor(
 #[post("/{cluster_id}")]
 pub async fn sync_processor(
     cluster_id: web::Path<String>,
-    payload: web::Json<ProcessorSynchronization>,
+    body: web::Bytes,
+    req: HttpRequest,
     processor_online: web::Data<Arc<RwLock<HashMap<String, i64>>>>,
+    signature: web::Data<Arc<RwLock<Signature>>>,
     db: web::Data<Database>,
 ) -> HttpResponse {
     let cluster_id = match cluster_id.parse() {
         Ok(v) => v,
         _ => return HttpResponse::BadRequest().body("INVALID_ID"),
     };
+    // Parsed by hand because the signature covers the raw body
+    let payload = match serde_json::from_slice::<ProcessorSynchronization>(&body) {
+        Ok(v) => v,
+        Err(_) => return HttpResponse::BadRequest().body("INVALID_PAYLOAD"),
+    };
 
     if (Cluster::find_by_id(&cluster_id, db.get_ref()).await).is_err() {
         return HttpResponse::NotFound().body("NOT_FOUND");
@@ -91,6 +130,22 @@ pub async fn sync_processor(
 
     let mut processor = match Processor::find_by_id(&payload.processor.id, db.get_ref()).await {
         Ok(v) => {
+            match &v.secret {
+                Some(secret) => {
+                    let mut signature = signature.write().await;
+                    if let Err(e) = signature.verify(secret, &req, &SignatureDigest::body(&body)) {
+                        return error_handler(e);
+                    }
+                }
+                // Registered before requests were signed, the processor has to pair again
+                // through an enrollment code or be given a secret rotated by an admin
+                None => return HttpResponse::Unauthorized().body("PROCESSOR_NOT_ENROLLED"),
+            }
+            // The cluster is set by enrollment, a processor cannot move itself to another one
+            if v.cluster_id != cluster_id {
+                return HttpResponse::Forbidden().body("FORBIDDEN");
+            }
+
             // Saved version is newer or equal, no need to update
             if v.version >= payload.processor.version {
                 {
@@ -105,21 +160,8 @@ pub async fn sync_processor(
             }
             v
         }
-        Err(_) => {
-            let processor = Processor {
-                id: payload.processor.id.clone(),
-                cluster_id: cluster_id.clone(),
-                name: payload.processor.name.clone(),
-                model: payload.processor.model.clone(),
-                address: payload.processor.address.clone(),
-                version: payload.processor.version.clone(),
-            };
-
-            match processor.save(db.get_ref()).await {
-                Ok(()) => processor,
-                Err(e) => return error_handler(e),
-            }
-        }
+        // New processors join through an enrollment code, see routes/enrollment.rs
+        Err(_) => return HttpResponse::Unauthorized().body("PROCESSOR_NOT_ENROLLED"),
     };
 
     // Update cameras
@@ -131,7 +173,9 @@ pub async fn sync_processor(
             date_maximum: None,
             text: None,
             limit: None,
-            skip: None,
+            cursor: None,
+            order: None,
+            scope: None,
         },
         db.get_ref(),
     )
@@ -153,7 +197,7 @@ pub async fn sync_processor(
     for camera in &payload.camera {
         let camera = Camera {
             id: camera.id.clone(),
-            cluster_id: cluster_id.clone(),
+            cluster_id: processor.cluster_id.clone(),
             processor_id: payload.processor.id.clone(),
             name: camera.name.clone(),
             address: camera.address.clone(),
@@ -185,7 +229,9 @@ pub async fn sync_processor(
                         date_maximum: None,
                         text: None,
                         limit: None,
-                        skip: None,
+                        cursor: None,
+                        order: None,
+                        scope: None,
                     },
                     db.get_ref(),
                 )
@@ -197,8 +243,47 @@ pub async fn sync_processor(
     }
 }
 
-#[put("/{processor_id}")]
+// Issues a new signing secret, returned once to be set on the processor by hand. The previous
+// secret stops working right away.
+#[post(
+    "/{processor_id}/secret",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn rotate_processor_secret(
+    req: HttpRequest,
+    processor_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let processor_id = match processor_id.parse() {
+        Ok(processor_id) => processor_id,
+        Err(_) => return HttpResponse::BadRequest().body("INVALID_ID"),
+    };
+    let mut processor = match Processor::find_by_id(&processor_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(e) => return error_handler(e),
+    };
+    if let Some(response) = cluster_forbidden(&req, &processor.cluster_id) {
+        return response;
+    }
+
+    let secret = Signature::secret();
+    processor.secret = Some(secret.clone());
+
+    match processor.update(db.get_ref()).await {
+        Ok(()) => HttpResponse::Created().json(ProcessorCredential {
+            id: processor.id,
+            secret,
+        }),
+        Err(e) => error_handler(e),
+    }
+}
+
+#[put(
+    "/{processor_id}",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
 pub async fn update_processor(
+    req: HttpRequest,
     processor_id: web::Path<String>,
     payload: web::Json<ProcessorRequest>,
     db: web::Data<Database>,
@@ -211,6 +296,9 @@ pub async fn update_processor(
         Ok(v) => v,
         Err(e) => return error_handler(e),
     };
+    if let Some(response) = cluster_forbidden(&req, &processor.cluster_id) {
+        return response;
+    }
 
     let request = payload.into_inner();
 
@@ -229,7 +317,9 @@ pub async fn update_processor(
                     date_maximum: None,
                     text: None,
                     limit: None,
-                    skip: None,
+                    cursor: None,
+                    order: None,
+                    scope: None,
                 },
                 db.get_ref(),
             )
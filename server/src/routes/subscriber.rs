@@ -1,20 +1,34 @@
-use actix_web::{HttpResponse, delete, post, put, web};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, delete, post, put, web};
 use mongodb::Database;
 
 use crate::{
     helper::error_handler,
-    models::subscriber::{
-        Subscriber, SubscriberKind, SubscriberQuery, SubscriberQueryKind, SubscriberRequest,
+    models::{
+        event::EventKind,
+        subscriber::{
+            Subscriber, SubscriberKind, SubscriberLink, SubscriberQuery, SubscriberQueryKind,
+            SubscriberRequest,
+        },
+        user::{User, UserAuthentication, UserAuthorization, UserRole},
     },
+    telegram::{TelegramClient, TelegramUpdate},
 };
 
-#[post("")]
+#[post("", wrap = "UserAuthorization::any()")]
 pub async fn subscribe(
+    req: HttpRequest,
     payload: web::Json<SubscriberRequest>,
     db: web::Data<Database>,
 ) -> HttpResponse {
     let request = payload.into_inner();
 
+    if !subscriber_allowed(&req, &request.user_id) {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    if let SubscriberKind::Telegram(_) = request.kind {
+        return HttpResponse::BadRequest().body("TELEGRAM_LINK_REQUIRED");
+    }
+
     if (Subscriber::find_by_kind(&request.kind, db.get_ref()).await).is_ok() {
         return HttpResponse::Conflict().finish();
     }
@@ -27,8 +41,9 @@ pub async fn subscribe(
     }
 }
 
-#[put("/{subscriber_id}")]
+#[put("/{subscriber_id}", wrap = "UserAuthorization::any()")]
 pub async fn refresh(
+    req: HttpRequest,
     subscriber_id: web::Path<String>,
     payload: web::Json<SubscriberRequest>,
     db: web::Data<Database>,
@@ -44,6 +59,13 @@ pub async fn refresh(
         Ok(v) => v,
         Err(e) => return error_handler(e),
     };
+    if !subscriber_allowed(&req, &subscriber.user_id) || !subscriber_allowed(&req, &request.user_id)
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    if let SubscriberKind::Telegram(_) = request.kind {
+        return HttpResponse::BadRequest().body("TELEGRAM_LINK_REQUIRED");
+    }
 
     subscriber.user_id = request.user_id;
     subscriber.kind = request.kind;
@@ -54,20 +76,25 @@ pub async fn refresh(
     }
 }
 
-#[delete("")]
+#[delete("", wrap = "UserAuthorization::any()")]
 pub async fn unsubscribe(
+    req: HttpRequest,
     query: web::Query<SubscriberQuery>,
     db: web::Data<Database>,
 ) -> HttpResponse {
     let kind = match (&query.kind, &query.token) {
         (Some(kind), Some(token)) => match kind {
             SubscriberQueryKind::Apple => SubscriberKind::Apple(token.clone()),
+            SubscriberQueryKind::Telegram => SubscriberKind::Telegram(token.clone()),
         },
         _ => return HttpResponse::BadRequest().finish(),
     };
 
     match Subscriber::find_by_kind(&kind, db.get_ref()).await {
         Ok(subscriber) => {
+            if !subscriber_allowed(&req, &subscriber.user_id) {
+                return HttpResponse::Forbidden().body("FORBIDDEN");
+            }
             let _ = subscriber.delete(db.get_ref()).await;
 
             HttpResponse::NoContent().finish()
@@ -75,3 +102,100 @@ pub async fn unsubscribe(
         Err(e) => error_handler(e),
     }
 }
+
+// Code the user sends to the Telegram bot as /start <code> to receive alerts in that chat
+#[post("/telegram", wrap = "UserAuthorization::any()")]
+pub async fn link_telegram(req: HttpRequest, db: web::Data<Database>) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    let link = SubscriberLink::new(issuer.id.clone());
+    match link.save(db.get_ref()).await {
+        Ok(()) => HttpResponse::Created().json(link),
+        Err(e) => error_handler(e),
+    }
+}
+
+// Updates pushed by the Telegram bot, authenticated by the secret token given to setWebhook.
+// Telegram retries anything but a success, so handled updates are always acknowledged.
+#[post("/telegram/webhook")]
+pub async fn telegram_webhook(
+    req: HttpRequest,
+    payload: web::Json<TelegramUpdate>,
+    db: web::Data<Database>,
+    telegram: web::Data<Option<TelegramClient>>,
+) -> HttpResponse {
+    let secret = std::env::var("TELEGRAM_SECRET").unwrap_or_default();
+    let token = req
+        .headers()
+        .get("X-Telegram-Bot-Api-Secret-Token")
+        .and_then(|v| v.to_str().ok());
+    if secret.is_empty() || token != Some(secret.as_str()) {
+        return HttpResponse::Unauthorized().body("UNAUTHORIZED");
+    }
+
+    let message = match payload.into_inner().message {
+        Some(v) => v,
+        None => return HttpResponse::Ok().finish(),
+    };
+    let code = match message
+        .text
+        .as_deref()
+        .and_then(|v| v.strip_prefix("/start"))
+        .map(|v| v.trim())
+    {
+        Some(v) => v,
+        None => return HttpResponse::Ok().finish(),
+    };
+    let chat_id = message.chat.id.to_string();
+
+    let reply = match link_chat(code, &chat_id, db.get_ref()).await {
+        Ok(user) => format!(
+            "Terhubung dengan akun {}. Pelanggaran baru akan dikirim ke sini.",
+            user.name
+        ),
+        Err(_) => String::from("Kode tidak valid atau sudah kedaluwarsa."),
+    };
+    if let Some(telegram) = telegram.get_ref()
+        && let Err(e) = telegram.send_message(&chat_id, &reply).await
+    {
+        println!("SENDING FAILED: {}", e);
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+// A chat receives the alerts of a single user, linking it again moves it to the new user
+async fn link_chat(code: &str, chat_id: &String, db: &Database) -> Result<User, EventKind> {
+    let link = SubscriberLink::redeem(code, chat_id, db).await?;
+    let user = User::find_by_id(&link.user_id, db).await?;
+
+    let kind = SubscriberKind::Telegram(chat_id.clone());
+    match Subscriber::find_by_kind(&kind, db).await {
+        Ok(mut subscriber) => {
+            subscriber.user_id = user.id.clone();
+            subscriber.update(db).await?;
+        }
+        Err(EventKind::NotFound) => {
+            Subscriber::from(SubscriberRequest {
+                user_id: user.id.clone(),
+                kind,
+            })
+            .save(db)
+            .await?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(user)
+}
+
+// Users manage their own devices, super admins manage every device
+fn subscriber_allowed(req: &HttpRequest, user_id: &String) -> bool {
+    match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.role == UserRole::SuperAdmin || &issuer.id == user_id,
+        None => false,
+    }
+}
@@ -4,35 +4,72 @@ use mongodb::Database;
 use crate::{
     helper::error_handler,
     models::{
-        cluster::Cluster,
+        preference::{Preference, PreferenceRequest},
         user::{
-            User, UserAuthentication, UserCredential, UserQuery, UserRefreshRequest, UserRequest,
-            UserRole,
+            User, UserAuthentication, UserAuthorization, UserCredential, UserQuery,
+            UserRefreshRequest, UserRequest, UserRole,
         },
     },
     views::user::ViewUser,
 };
 
-#[get("")]
-pub async fn get_users(query: web::Query<UserQuery>, db: web::Data<Database>) -> HttpResponse {
+#[get("", wrap = "UserAuthorization::any()")]
+pub async fn get_users(
+    req: HttpRequest,
+    query: web::Query<UserQuery>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
+    let mut query = query.into_inner();
+    if issuer.role != UserRole::SuperAdmin
+        && [&query.cluster_id, &query.cluster_eid]
+            .into_iter()
+            .flatten()
+            .any(|v| !issuer.allows(v))
+    {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+    query.scope = issuer.scope();
+
     match ViewUser::find_many(&query, db.get_ref()).await {
         Ok(users) => HttpResponse::Ok().json(users),
-        _ => HttpResponse::NotFound().body("USER_NOT_FOUND"),
+        Err(e) => error_handler(e),
     }
 }
-#[get("/{user_id}")]
-pub async fn get_user(user_id: web::Path<String>, db: web::Data<Database>) -> HttpResponse {
+#[get("/{user_id}", wrap = "UserAuthorization::any()")]
+pub async fn get_user(
+    req: HttpRequest,
+    user_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
     let user_id = match user_id.parse() {
         Ok(user_id) => user_id,
         Err(_) => return HttpResponse::BadRequest().body("INVALID_ID"),
     };
 
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
     match User::find_by_id(&user_id, db.get_ref()).await {
-        Ok(user) => HttpResponse::Ok().json(user),
+        // Users see themselves and the members of the clusters they belong to
+        Ok(user)
+            if issuer.id == user.id
+                || issuer.role == UserRole::SuperAdmin
+                || user.cluster_id.iter().any(|v| issuer.allows(v)) =>
+        {
+            HttpResponse::Ok().json(user)
+        }
+        Ok(_) => HttpResponse::Forbidden().body("FORBIDDEN"),
         _ => HttpResponse::NotFound().body("USER_NOT_FOUND"),
     }
 }
-#[put("/{user_id}")]
+#[put("/{user_id}", wrap = "UserAuthorization::any()")]
 pub async fn update_user(
     req: HttpRequest,
     user_id: web::Path<String>,
@@ -48,14 +85,29 @@ pub async fn update_user(
         Some(issuer) => issuer.clone(),
         None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
     };
-    if issuer.id != user_id && issuer.role == UserRole::Officer {
-        return HttpResponse::Unauthorized().body("UNAUTHORIZED");
-    }
 
     match User::find_by_id(&user_id, db.get_ref()).await {
         Ok(mut user) => {
             let payload = payload.into_inner();
 
+            if !issuer.allows_user(&user) {
+                return HttpResponse::Forbidden().body("FORBIDDEN");
+            }
+            // Officers only edit their own profile, managers only hand out their own clusters
+            match issuer.role {
+                UserRole::SuperAdmin => (),
+                UserRole::Manager => {
+                    if payload.cluster_id.iter().any(|v| !issuer.allows(v)) {
+                        return HttpResponse::Forbidden().body("FORBIDDEN");
+                    }
+                }
+                UserRole::Officer => {
+                    if payload.role != user.role || payload.cluster_id != user.cluster_id {
+                        return HttpResponse::Forbidden().body("FORBIDDEN");
+                    }
+                }
+            }
+
             let mut password = None;
             if payload.role == UserRole::SuperAdmin && user.role != UserRole::SuperAdmin {
                 return HttpResponse::BadRequest().body("USER_ROLE_INVALID");
@@ -76,30 +128,61 @@ pub async fn update_user(
         _ => HttpResponse::NotFound().body("USER_NOT_FOUND"),
     }
 }
-#[delete("/{user_id}")]
-pub async fn delete_user(user_id: web::Path<String>, db: web::Data<Database>) -> HttpResponse {
+#[delete(
+    "/{user_id}",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn delete_user(
+    req: HttpRequest,
+    user_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
     let user_id = match user_id.parse() {
         Ok(user_id) => user_id,
         Err(_) => return HttpResponse::BadRequest().body("INVALID_ID"),
     };
 
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+
     match User::find_by_id(&user_id, db.get_ref()).await {
         Ok(user) => {
             if user.role == UserRole::SuperAdmin {
                 return HttpResponse::BadRequest().body("USER_OWNER_CANNOT_BE_DELETED");
             }
+            if !issuer.allows_user(&user) {
+                return HttpResponse::Forbidden().body("FORBIDDEN");
+            }
 
             user.delete(db.get_ref()).await;
+            let _ = Preference::delete(&user.id, db.get_ref()).await;
 
             HttpResponse::NoContent().finish()
         }
         _ => HttpResponse::NotFound().body("ID MEMBER TIDAK TERDAFTAR"),
     }
 }
-#[post("")]
-pub async fn create_user(payload: web::Json<UserRequest>, db: web::Data<Database>) -> HttpResponse {
+#[post(
+    "",
+    wrap = "UserAuthorization::role(&[UserRole::SuperAdmin, UserRole::Manager])"
+)]
+pub async fn create_user(
+    req: HttpRequest,
+    payload: web::Json<UserRequest>,
+    db: web::Data<Database>,
+) -> HttpResponse {
     let payload: UserRequest = payload.into_inner();
 
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return HttpResponse::Unauthorized().body("UNAUTHORIZED"),
+    };
+    if payload.cluster_id.iter().any(|v| !issuer.allows(v)) {
+        return HttpResponse::Forbidden().body("FORBIDDEN");
+    }
+
     if payload.password.len() < 8 {
         return HttpResponse::BadRequest().body("USER_MUST_HAVE_VALID_PASSWORD");
     }
@@ -117,12 +200,6 @@ pub async fn create_user(payload: web::Json<UserRequest>, db: web::Data<Database
         return HttpResponse::BadRequest().body("USER_ALREADY_EXIST");
     }
 
-    if let Ok(mut clusters) = Cluster::find_all(db.get_ref()).await {
-        for cluster in clusters.drain(..) {
-            user.cluster_id.push(cluster.id);
-        }
-    }
-
     match user.save(db.get_ref()).await {
         Ok(_) => HttpResponse::Created().json(user),
         Err(_) => HttpResponse::InternalServerError().body("USER_SAVING_FAILED"),
@@ -159,3 +236,74 @@ pub async fn refresh(
         Err(e) => error_handler(e),
     }
 }
+
+// Notification preference of the user, the default when none was saved
+#[get("/{user_id}/preferences", wrap = "UserAuthorization::any()")]
+pub async fn get_user_preference(
+    req: HttpRequest,
+    user_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let user = match find_preference_user(&req, &user_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(Preference::find_or_default(&user.id, db.get_ref()).await)
+}
+#[put("/{user_id}/preferences", wrap = "UserAuthorization::any()")]
+pub async fn update_user_preference(
+    req: HttpRequest,
+    user_id: web::Path<String>,
+    payload: web::Json<PreferenceRequest>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let user = match find_preference_user(&req, &user_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(response) => return response,
+    };
+
+    let mut preference = match Preference::new(user.id, payload.into_inner()) {
+        Ok(v) => v,
+        Err(e) => return error_handler(e),
+    };
+    match preference.save(db.get_ref()).await {
+        Ok(()) => HttpResponse::Ok().json(preference),
+        Err(e) => error_handler(e),
+    }
+}
+// Back to being notified about everything at once
+#[delete("/{user_id}/preferences", wrap = "UserAuthorization::any()")]
+pub async fn delete_user_preference(
+    req: HttpRequest,
+    user_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> HttpResponse {
+    let user = match find_preference_user(&req, &user_id, db.get_ref()).await {
+        Ok(v) => v,
+        Err(response) => return response,
+    };
+
+    match Preference::delete(&user.id, db.get_ref()).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => error_handler(e),
+    }
+}
+
+// Preferences are managed by whoever may edit the user
+async fn find_preference_user(
+    req: &HttpRequest,
+    user_id: &String,
+    db: &Database,
+) -> Result<User, HttpResponse> {
+    let issuer = match req.extensions().get::<UserAuthentication>() {
+        Some(issuer) => issuer.clone(),
+        None => return Err(HttpResponse::Unauthorized().body("UNAUTHORIZED")),
+    };
+
+    match User::find_by_id(user_id, db).await {
+        Ok(user) if issuer.allows_user(&user) => Ok(user),
+        Ok(_) => Err(HttpResponse::Forbidden().body("FORBIDDEN")),
+        _ => Err(HttpResponse::NotFound().body("USER_NOT_FOUND")),
+    }
+}
@@ -0,0 +1,217 @@
+use std::{fmt, time::Duration};
+
+use reqwest::{
+    Client, RequestBuilder,
+    multipart::{Form, Part},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+// Telegram Bot API client, the base URL is configurable so it can be pointed at a mock server
+#[derive(Debug, Clone)]
+pub struct TelegramClient {
+    url: String,
+    token: String,
+    client: Client,
+}
+
+#[derive(Debug)]
+pub enum TelegramError {
+    Request(reqwest::Error),
+    Response(u16, String), // Error code and description returned by the Bot API
+}
+
+// Update delivered to the webhook, only messages are handled
+#[derive(Debug, Deserialize)]
+pub struct TelegramUpdate {
+    pub message: Option<TelegramMessage>,
+}
+#[derive(Debug, Deserialize)]
+pub struct TelegramMessage {
+    pub chat: TelegramChat,
+    pub text: Option<String>,
+}
+#[derive(Debug, Deserialize)]
+pub struct TelegramChat {
+    pub id: i64,
+}
+
+impl fmt::Display for TelegramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelegramError::Request(e) => write!(f, "{}", e),
+            TelegramError::Response(code, description) => write!(f, "{} {}", code, description),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramResponse {
+    ok: bool,
+    error_code: Option<u16>,
+    description: Option<String>,
+}
+
+impl TelegramClient {
+    pub fn new(url: String, token: String) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            client: Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("TELEGRAM_CLIENT_FAILED"),
+        }
+    }
+    // None when no bot token is configured, Telegram subscribers are then left alone
+    pub fn from_env() -> Option<Self> {
+        let token = std::env::var("TELEGRAM_TOKEN").ok()?;
+        if token.is_empty() {
+            return None;
+        }
+
+        Some(Self::new(std::env::var("TELEGRAM_URL").ok()?, token))
+    }
+
+    pub async fn send_message(&self, chat_id: &str, text: &str) -> Result<(), TelegramError> {
+        Self::send(
+            self.client
+                .post(self.method("sendMessage"))
+                .json(&json!({ "chat_id": chat_id, "text": text })),
+        )
+        .await
+    }
+    pub async fn send_photo(
+        &self,
+        chat_id: &str,
+        caption: &str,
+        photo: Vec<u8>,
+    ) -> Result<(), TelegramError> {
+        let photo = Part::bytes(photo)
+            .file_name("evidence.jpg")
+            .mime_str("image/jpeg")
+            .map_err(TelegramError::Request)?;
+        let form = Form::new()
+            .text("chat_id", chat_id.to_string())
+            .text("caption", caption.to_string())
+            .part("photo", photo);
+
+        Self::send(self.client.post(self.method("sendPhoto")).multipart(form)).await
+    }
+
+    fn method(&self, method: &str) -> String {
+        format!("{}/bot{}/{}", self.url, self.token, method)
+    }
+    async fn send(request: RequestBuilder) -> Result<(), TelegramError> {
+        let response = request.send().await.map_err(TelegramError::Request)?;
+        let status = response.status().as_u16();
+        let response = response
+            .json::<TelegramResponse>()
+            .await
+            .map_err(TelegramError::Request)?;
+
+        if response.ok {
+            Ok(())
+        } else {
+            Err(TelegramError::Response(
+                response.error_code.unwrap_or(status),
+                response.description.unwrap_or_default(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::{TelegramClient, TelegramError};
+
+    // Answers a single request with the given status and body, returns the request it received
+    async fn mock(status: &'static str, body: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            loop {
+                let n = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..n]);
+
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, content)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length: ")?
+                                .parse()
+                                .ok()
+                        })
+                        .unwrap_or(0);
+                    if n == 0 || content.len() >= length {
+                        break;
+                    }
+                }
+            }
+
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn send_photo_posts_to_the_bot() {
+        let (url, handle) = mock("200 OK", r#"{"ok":true,"result":{}}"#).await;
+        let client = TelegramClient::new(url, String::from("123:token"));
+
+        client
+            .send_photo("42", "caption", b"jpeg".to_vec())
+            .await
+            .unwrap();
+
+        let request = handle.await.unwrap();
+        assert!(request.starts_with("POST /bot123:token/sendPhoto "));
+        assert!(request.contains("name=\"chat_id\"\r\n\r\n42"));
+        assert!(request.contains("name=\"caption\"\r\n\r\ncaption"));
+        assert!(request.contains("filename=\"evidence.jpg\""));
+    }
+
+    #[tokio::test]
+    async fn send_message_returns_the_error_code() {
+        let (url, handle) = mock(
+            "403 Forbidden",
+            r#"{"ok":false,"error_code":403,"description":"Forbidden: bot was blocked by the user"}"#,
+        )
+        .await;
+        let client = TelegramClient::new(url, String::from("123:token"));
+
+        match client.send_message("42", "text").await {
+            Err(TelegramError::Response(code, _)) => assert_eq!(code, 403),
+            v => panic!("unexpected result: {:?}", v),
+        }
+
+        let request = handle.await.unwrap();
+        assert!(request.starts_with("POST /bot123:token/sendMessage "));
+        assert!(request.contains(r#""chat_id":"42""#));
+    }
+}
@@ -0,0 +1,493 @@
+use futures::StreamExt;
+use mongodb::{
+    Collection, Database,
+    bson::{Bson, Document, doc, from_document},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    analytics::{AnalyticsBucket, AnalyticsGroup, AnalyticsQuery},
+    camera::Camera,
+    cluster::Cluster,
+    event::EventKind,
+    evidence::{Evidence, EvidenceQuery},
+    processor::Processor,
+};
+
+const COLLECTION: &str = "evidences";
+
+#[derive(Debug, Serialize)]
+pub struct ViewAnalytics {
+    pub bucket: AnalyticsBucket,
+    pub group: Option<AnalyticsGroup>,
+    pub date_minimum: i64,
+    pub date_maximum: i64,
+    pub series: Vec<ViewAnalyticsSeries>,
+    pub compliance: ViewAnalyticsCompliance,
+    pub camera: Vec<ViewAnalyticsCamera>, // Top offending cameras, most violations first
+    pub trend: ViewAnalyticsTrend,
+}
+#[derive(Debug, Serialize)]
+pub struct ViewAnalyticsSeries {
+    pub id: Option<String>, // Id or violation kind of the group, None when not grouped
+    pub name: Option<String>,
+    pub point: Vec<ViewAnalyticsPoint>,
+}
+// Buckets without evidence are left out
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ViewAnalyticsPoint {
+    pub timestamp: i64, // Start of the bucket
+    pub evidence_count: u64,
+    pub person_count: u64, // In the evidence of the point
+    pub violation_count: u64,
+}
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ViewAnalyticsCompliance {
+    pub evidence_count: u64,
+    pub person_count: u64,
+    pub person_violating_count: u64,
+    pub violation_count: u64,
+    pub rate: Option<f64>, // Share of persons seen without a violation, None without persons
+}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ViewAnalyticsCamera {
+    pub id: String,
+    pub name: String,
+    pub violation_count: u64,
+}
+// Current period minus the previous period of the same length
+#[derive(Debug, Serialize)]
+pub struct ViewAnalyticsTrend {
+    pub previous: ViewAnalyticsCompliance,
+    pub evidence_count: i64,
+    pub person_violating_count: i64,
+    pub violation_count: i64,
+    pub rate: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnalyticsSeries {
+    #[serde(rename = "_id")]
+    id: Option<String>,
+    point: Vec<ViewAnalyticsPoint>,
+}
+
+impl ViewAnalytics {
+    pub async fn find(query: &AnalyticsQuery, db: &Database) -> Result<Self, EventKind> {
+        let collection = db.collection::<Document>(COLLECTION);
+
+        let (date_minimum, date_maximum) = query.period()?;
+        let current = query.evidence_query(date_minimum, date_maximum);
+        let previous = query.evidence_query(
+            date_minimum - (date_maximum - date_minimum),
+            date_minimum - 1,
+        );
+
+        let series = Self::find_series(&collection, query, &current, db).await?;
+        let compliance = Self::find_compliance(&collection, &current).await?;
+        let camera = Self::find_camera(&collection, &current, query.top(), db).await?;
+        let previous = Self::find_compliance(&collection, &previous).await?;
+
+        let trend = ViewAnalyticsTrend {
+            evidence_count: compliance.evidence_count as i64 - previous.evidence_count as i64,
+            person_violating_count: compliance.person_violating_count as i64
+                - previous.person_violating_count as i64,
+            violation_count: compliance.violation_count as i64 - previous.violation_count as i64,
+            rate: match (compliance.rate, previous.rate) {
+                (Some(current), Some(previous)) => Some(current - previous),
+                _ => None,
+            },
+            previous,
+        };
+
+        Ok(Self {
+            bucket: query.bucket(),
+            group: query.group,
+            date_minimum,
+            date_maximum,
+            series,
+            compliance,
+            camera,
+            trend,
+        })
+    }
+
+    async fn find_series(
+        collection: &Collection<Document>,
+        query: &AnalyticsQuery,
+        evidence_query: &EvidenceQuery,
+        db: &Database,
+    ) -> Result<Vec<ViewAnalyticsSeries>, EventKind> {
+        let mut date_trunc = doc! {
+            "date": { "$toDate": "$timestamp" },
+            "unit": query.bucket().unit(),
+        };
+        if query.bucket() == AnalyticsBucket::Week {
+            date_trunc.insert("startOfWeek", "monday");
+        }
+        if let Some(timezone) = &query.timezone {
+            date_trunc.insert("timezone", timezone);
+        }
+
+        let mut pipeline = vec![doc! { "$match": Evidence::create_filter(evidence_query) }];
+        match query.group {
+            // One entry per person and violation kind, counted in the bucket of its kind. Persons
+            // are those of the evidence with the kind, each evidence counted once, as in the
+            // other series.
+            Some(AnalyticsGroup::Violation) => {
+                pipeline.push(doc! { "$set": { "person_count": { "$size": "$person" } } });
+                pipeline.push(doc! { "$unwind": "$person" });
+                pipeline.push(doc! { "$unwind": "$person.violation" });
+                pipeline.push(doc! {
+                    "$group": {
+                        "_id": {
+                            "key": "$person.violation",
+                            "timestamp": { "$dateTrunc": date_trunc },
+                        },
+                        "evidence": {
+                            "$addToSet": { "id": "$id", "person_count": "$person_count" }
+                        },
+                        "violation_count": { "$sum": 1 },
+                    }
+                });
+                pipeline.push(doc! {
+                    "$set": {
+                        "evidence_count": { "$size": "$evidence" },
+                        "person_count": { "$sum": "$evidence.person_count" },
+                    }
+                });
+            }
+            group => {
+                let key = match group {
+                    Some(AnalyticsGroup::Cluster) => Bson::from("$cluster_id"),
+                    Some(AnalyticsGroup::Processor) => Bson::from("$processor_id"),
+                    Some(AnalyticsGroup::Camera) => Bson::from("$camera_id"),
+                    _ => Bson::Null,
+                };
+                pipeline.push(Self::create_count_stage());
+                pipeline.push(doc! {
+                    "$group": {
+                        "_id": {
+                            "key": key,
+                            "timestamp": { "$dateTrunc": date_trunc },
+                        },
+                        "evidence_count": { "$sum": 1 },
+                        "person_count": { "$sum": "$person_count" },
+                        "violation_count": { "$sum": "$violation_count" },
+                    }
+                });
+            }
+        }
+        pipeline.push(doc! { "$sort": { "_id.timestamp": 1 } });
+        pipeline.push(doc! {
+            "$group": {
+                "_id": "$_id.key",
+                "point": {
+                    "$push": {
+                        "timestamp": { "$toLong": "$_id.timestamp" },
+                        "evidence_count": "$evidence_count",
+                        "person_count": "$person_count",
+                        "violation_count": "$violation_count",
+                    }
+                },
+            }
+        });
+        pipeline.push(doc! { "$sort": { "_id": 1 } });
+
+        let mut series = Vec::new();
+        for v in Self::aggregate::<AnalyticsSeries>(collection, pipeline).await? {
+            let name = match &v.id {
+                Some(id) => Some(Self::find_name(query.group, id, db).await),
+                None => None,
+            };
+            series.push(ViewAnalyticsSeries {
+                id: v.id,
+                name,
+                point: v.point,
+            });
+        }
+
+        Ok(series)
+    }
+
+    async fn find_compliance(
+        collection: &Collection<Document>,
+        evidence_query: &EvidenceQuery,
+    ) -> Result<ViewAnalyticsCompliance, EventKind> {
+        let pipeline = vec![
+            doc! { "$match": Evidence::create_filter(evidence_query) },
+            Self::create_count_stage(),
+            doc! {
+                "$group": {
+                    "_id": null,
+                    "evidence_count": { "$sum": 1 },
+                    "person_count": { "$sum": "$person_count" },
+                    "person_violating_count": { "$sum": "$person_violating_count" },
+                    "violation_count": { "$sum": "$violation_count" },
+                }
+            },
+        ];
+
+        let mut compliance = Self::aggregate::<ViewAnalyticsCompliance>(collection, pipeline)
+            .await?
+            .pop()
+            .unwrap_or_default();
+        if compliance.person_count > 0 {
+            compliance.rate = Some(
+                1.0 - compliance.person_violating_count as f64 / compliance.person_count as f64,
+            );
+        }
+
+        Ok(compliance)
+    }
+
+    async fn find_camera(
+        collection: &Collection<Document>,
+        evidence_query: &EvidenceQuery,
+        top: usize,
+        db: &Database,
+    ) -> Result<Vec<ViewAnalyticsCamera>, EventKind> {
+        let pipeline = vec![
+            doc! { "$match": Evidence::create_filter(evidence_query) },
+            Self::create_count_stage(),
+            doc! {
+                "$group": {
+                    "_id": "$camera_id",
+                    "violation_count": { "$sum": "$violation_count" },
+                }
+            },
+            doc! { "$match": { "violation_count": { "$gt": 0 } } },
+            doc! { "$sort": { "violation_count": -1, "_id": 1 } },
+            doc! { "$limit": top as i64 },
+            doc! {
+                "$project": {
+                    "id": "$_id",
+                    "name": "$_id",
+                    "violation_count": "$violation_count",
+                }
+            },
+        ];
+
+        let mut camera = Self::aggregate::<ViewAnalyticsCamera>(collection, pipeline).await?;
+        for v in camera.iter_mut() {
+            if let Ok(c) = Camera::find_by_id(&v.id, db).await {
+                v.name = c.name;
+            }
+        }
+
+        Ok(camera)
+    }
+
+    // Name of a series, the id itself when the entity is gone or the series is a violation kind
+    async fn find_name(group: Option<AnalyticsGroup>, id: &String, db: &Database) -> String {
+        let name = match group {
+            Some(AnalyticsGroup::Cluster) => Cluster::find_by_id(id, db).await.map(|v| v.name),
+            Some(AnalyticsGroup::Processor) => Processor::find_by_id(id, db).await.map(|v| v.name),
+            Some(AnalyticsGroup::Camera) => Camera::find_by_id(id, db).await.map(|v| v.name),
+            _ => Err(EventKind::NotFound),
+        };
+        name.unwrap_or(id.clone())
+    }
+
+    async fn aggregate<T: for<'de> Deserialize<'de>>(
+        collection: &Collection<Document>,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<T>, EventKind> {
+        match collection.aggregate(pipeline, None).await {
+            Ok(mut cursor) => {
+                let mut result = Vec::new();
+                while let Some(Ok(doc)) = cursor.next().await {
+                    match from_document::<T>(doc) {
+                        Ok(v) => result.push(v),
+                        Err(e) => println!("ERROR: {:?}", e),
+                    }
+                }
+                Ok(result)
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+
+    // Helper functions to create aggregation stages
+    fn create_count_stage() -> Document {
+        doc! {
+            "$project": {
+                "cluster_id": "$cluster_id",
+                "processor_id": "$processor_id",
+                "camera_id": "$camera_id",
+                "timestamp": "$timestamp",
+                "person_count": { "$size": "$person" },
+                "person_violating_count": {
+                    "$size": {
+                        "$filter": {
+                            "input": "$person",
+                            "cond": { "$gt": [{ "$size": "$$this.violation" }, 0] }
+                        }
+                    }
+                },
+                "violation_count": {
+                    "$sum": {
+                        "$map": {
+                            "input": "$person",
+                            "in": { "$size": "$$this.violation" }
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database,
+        models::{
+            analytics::{AnalyticsBucket, AnalyticsGroup, AnalyticsQuery},
+            event::EventKind,
+            evidence::{Evidence, EvidencePerson, EvidencePersonViolation, EvidenceStatus},
+        },
+    };
+
+    use super::ViewAnalytics;
+
+    const DAY: i64 = 24 * 60 * 60 * 1000;
+
+    fn query(group: Option<AnalyticsGroup>) -> AnalyticsQuery {
+        AnalyticsQuery {
+            cluster_id: None,
+            processor_id: None,
+            camera_id: None,
+            date_minimum: Some(10 * DAY),
+            date_maximum: Some(12 * DAY),
+            bucket: Some(AnalyticsBucket::Day),
+            group,
+            timezone: None,
+            top: None,
+            scope: None,
+        }
+    }
+
+    #[test]
+    fn period_rejects_too_many_buckets() {
+        let mut query = query(None);
+        assert_eq!(query.period().ok(), Some((10 * DAY, 12 * DAY)));
+
+        query.bucket = Some(AnalyticsBucket::Hour);
+        query.date_maximum = Some(60 * DAY);
+        assert!(query.period().is_err());
+        query.bucket = Some(AnalyticsBucket::Day);
+        assert!(query.period().is_ok());
+
+        query.date_minimum = Some(60 * DAY);
+        assert!(query.period().is_err());
+    }
+
+    #[test]
+    fn period_rejects_unknown_timezones() {
+        let mut query = query(None);
+        query.timezone = Some(String::from("Asia/Jakarta"));
+        assert!(query.period().is_ok());
+
+        query.timezone = Some(String::from("Mars/Olympus"));
+        assert!(matches!(query.period(), Err(EventKind::InvalidTimezone)));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn find_buckets_and_compares_periods() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+
+        let person = |violation: Vec<EvidencePersonViolation>| EvidencePerson {
+            id: String::from("001"),
+            bbox: [0.0, 0.0, 1.0, 1.0],
+            confidence: 0.9,
+            part: Vec::new(),
+            equipment: Vec::new(),
+            violation,
+            confirmation: Vec::new(),
+        };
+        let evidence = [
+            // Previous period
+            ("a", 9 * DAY, vec![person(Vec::new())]),
+            // Current period, two days
+            (
+                "b",
+                10 * DAY + 1000,
+                vec![
+                    person(vec![EvidencePersonViolation::MissingHardhat]),
+                    person(Vec::new()),
+                ],
+            ),
+            (
+                "c",
+                11 * DAY + 1000,
+                vec![person(vec![
+                    EvidencePersonViolation::MissingHardhat,
+                    EvidencePersonViolation::MissingGloves,
+                ])],
+            ),
+        ];
+        for (id, timestamp, person) in evidence {
+            let evidence = Evidence {
+                id: id.to_string(),
+                cluster_id: String::from("north"),
+                processor_id: String::from("north_processor"),
+                camera_id: String::from("north_camera"),
+                frame_id: String::from("000001"),
+                timestamp,
+                person,
+                clip: None,
+                status: EvidenceStatus::Open,
+                review: Vec::new(),
+                correction: Vec::new(),
+                escalation: Vec::new(),
+            };
+            evidence.save(&db).await.unwrap();
+        }
+
+        let analytics = ViewAnalytics::find(&query(None), &db).await.unwrap();
+        assert_eq!(analytics.series.len(), 1);
+        let point = &analytics.series[0].point;
+        assert_eq!(
+            point.iter().map(|v| v.timestamp).collect::<Vec<_>>(),
+            [10 * DAY, 11 * DAY]
+        );
+        assert_eq!(point[0].person_count, 2);
+        assert_eq!(point[1].violation_count, 2);
+
+        assert_eq!(analytics.compliance.person_count, 3);
+        assert_eq!(analytics.compliance.person_violating_count, 2);
+        assert_eq!(analytics.compliance.rate, Some(1.0 - 2.0 / 3.0));
+        assert_eq!(analytics.trend.previous.rate, Some(1.0));
+        assert_eq!(analytics.trend.violation_count, 3);
+
+        assert_eq!(analytics.camera.len(), 1);
+        assert_eq!(analytics.camera[0].name, "north_camera");
+        assert_eq!(analytics.camera[0].violation_count, 3);
+
+        let analytics = ViewAnalytics::find(&query(Some(AnalyticsGroup::Violation)), &db)
+            .await
+            .unwrap();
+        assert_eq!(
+            analytics
+                .series
+                .iter()
+                .map(|v| (v.id.clone().unwrap(), v.point.len()))
+                .collect::<Vec<_>>(),
+            [
+                (String::from("missing_gloves"), 1),
+                (String::from("missing_hardhat"), 2)
+            ]
+        );
+        // Both persons of the evidence are counted, not just the one with the violation
+        let point = &analytics.series[1].point[0];
+        assert_eq!((point.person_count, point.violation_count), (2, 1));
+
+        db.drop(None).await.unwrap();
+    }
+}
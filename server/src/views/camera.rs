@@ -11,7 +11,11 @@ use crate::{
         event::EventKind,
         evidence::{Evidence, EvidenceQuery},
     },
-    views::{cluster::ClusterRef, processor::ProcessorRef},
+    views::{
+        cluster::ClusterRef,
+        page::{PageCursor, PageOrder, ViewPage, page_limit, page_total},
+        processor::ProcessorRef,
+    },
 };
 
 const COLLECTION: &str = "cameras";
@@ -34,9 +38,92 @@ pub struct ViewCamera {
 }
 
 impl ViewCamera {
-    pub async fn find_many(query: &CameraQuery, db: &Database) -> Result<Vec<Self>, EventKind> {
+    pub async fn find_many(
+        query: &CameraQuery,
+        db: &Database,
+    ) -> Result<ViewPage<Self>, EventKind> {
         let collection = db.collection::<Self>(COLLECTION);
 
+        let limit = page_limit(query.limit);
+        let cursor = match &query.cursor {
+            Some(cursor) => Some(PageCursor::<String>::decode(cursor)?),
+            None => None,
+        };
+
+        let (camera_query, violation_query) = Self::create_queries(query);
+
+        let total = page_total(&collection, vec![Self::create_match_stage(&camera_query)]).await?;
+
+        // Build aggregation pipeline
+        let mut pipeline = vec![Self::create_match_stage(&camera_query)];
+        pipeline.extend(PageCursor::create_page_stages(
+            "name",
+            query.order.unwrap_or(PageOrder::Asc),
+            cursor,
+            limit,
+        ));
+        pipeline.extend([
+            Self::create_cluster_lookup_stage(),
+            Self::create_processor_lookup_stage(),
+            Self::create_notification_count_stage(&violation_query),
+            Self::create_violation_count_stage(&violation_query),
+            Self::create_project_stage(),
+        ]);
+
+        match collection.aggregate(pipeline, None).await {
+            Ok(mut cursor) => {
+                let mut cameras = Vec::new();
+                while let Some(Ok(doc)) = cursor.next().await {
+                    let camera = from_document::<Self>(doc).unwrap();
+                    cameras.push(camera);
+                }
+                Ok(ViewPage::from(cameras, total, limit, |v| {
+                    (v.name.clone(), v.id.clone())
+                }))
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+
+    // Every camera matching the query in one list, sorted by name. Used where the cameras are
+    // nested in another view, the limit and cursor of the query are ignored.
+    pub async fn find_all(query: &CameraQuery, db: &Database) -> Result<Vec<Self>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let (camera_query, violation_query) = Self::create_queries(query);
+
+        let pipeline = vec![
+            Self::create_match_stage(&camera_query),
+            doc! { "$sort": { "name": 1, "id": 1 } },
+            Self::create_cluster_lookup_stage(),
+            Self::create_processor_lookup_stage(),
+            Self::create_notification_count_stage(&violation_query),
+            Self::create_violation_count_stage(&violation_query),
+            Self::create_project_stage(),
+        ];
+
+        match collection.aggregate(pipeline, None).await {
+            Ok(mut cursor) => {
+                let mut cameras = Vec::new();
+                while let Some(Ok(doc)) = cursor.next().await {
+                    let camera = from_document::<Self>(doc).unwrap();
+                    cameras.push(camera);
+                }
+                Ok(cameras)
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+
+    // Helper functions to create aggregation stages
+    // Camera and violation filters of the query
+    fn create_queries(query: &CameraQuery) -> (Vec<Document>, Vec<Document>) {
         let mut camera_query = Vec::new();
         let mut violation_query = Vec::from([doc! {
             "$eq": ["$camera_id", "$$camera_id"]
@@ -57,6 +144,11 @@ impl ViewCamera {
                 "$eq": ["$cluster_id", to_bson::<String>(cluster_id).unwrap()]
             });
         }
+        if let Some(scope) = &query.scope {
+            camera_query.push(doc! {
+                "$in": ["$cluster_id", to_bson::<Vec<String>>(scope).unwrap()]
+            });
+        }
         if let Some(processor_id) = &query.processor_id {
             camera_query.push(doc! {
                 "$eq": ["$processor_id", to_bson::<String>(processor_id).unwrap()]
@@ -75,48 +167,8 @@ impl ViewCamera {
             });
         }
 
-        // Build aggregation pipeline
-        let mut pipeline = vec![
-            Self::create_match_stage(&camera_query),
-            Self::create_cluster_lookup_stage(),
-            Self::create_processor_lookup_stage(),
-            Self::create_notification_count_stage(&violation_query),
-            Self::create_violation_count_stage(&violation_query),
-            Self::create_project_stage(),
-        ];
-
-        if let Some(skip) = query.skip {
-            pipeline.push(doc! {
-                "$skip": to_bson::<usize>(&skip).unwrap()
-            });
-        }
-        if let Some(limit) = query.limit {
-            pipeline.push(doc! {
-                "$limit": to_bson::<usize>(&limit).unwrap()
-            });
-        }
-
-        match collection.aggregate(pipeline, None).await {
-            Ok(mut cursor) => {
-                let mut cameras = Vec::new();
-                while let Some(Ok(doc)) = cursor.next().await {
-                    let camera = from_document::<Self>(doc).unwrap();
-                    cameras.push(camera);
-                }
-                if !cameras.is_empty() {
-                    Ok(cameras)
-                } else {
-                    Err(EventKind::NotFound)
-                }
-            }
-            Err(e) => {
-                println!("ERROR: {:?}", e);
-                Err(EventKind::FindingFailed)
-            }
-        }
+        (camera_query, violation_query)
     }
-
-    // Helper functions to create aggregation stages
     fn create_match_stage(query: &Vec<Document>) -> Document {
         doc! {
             "$match": {
@@ -276,3 +328,56 @@ impl ViewCamera {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{database, models::camera::CameraQuery};
+
+    use super::ViewCamera;
+
+    fn query(cluster_id: Option<&str>, scope: Option<Vec<&str>>) -> CameraQuery {
+        CameraQuery {
+            cluster_id: cluster_id.map(|v| v.to_string()),
+            processor_id: None,
+            date_minimum: None,
+            date_maximum: None,
+            text: None,
+            limit: None,
+            cursor: None,
+            order: None,
+            scope: scope.map(|v| v.into_iter().map(|v| v.to_string()).collect()),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn find_many_is_limited_to_scope() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+        database::test::seed("south", &db).await;
+
+        let cameras = ViewCamera::find_many(&query(None, None), &db)
+            .await
+            .unwrap()
+            .data;
+        assert_eq!(cameras.len(), 2);
+
+        let cameras = ViewCamera::find_many(&query(None, Some(vec!["north"])), &db)
+            .await
+            .unwrap()
+            .data;
+        assert_eq!(cameras.len(), 1);
+        assert_eq!(cameras[0].id, "north_camera");
+
+        // An explicit cluster outside the scope matches nothing
+        assert!(
+            ViewCamera::find_many(&query(Some("south"), Some(vec!["north"])), &db)
+                .await
+                .unwrap()
+                .data
+                .is_empty()
+        );
+
+        db.drop(None).await.unwrap();
+    }
+}
@@ -6,8 +6,9 @@ use mongodb::{
 use serde::{Deserialize, Serialize};
 
 use crate::models::{
-    cluster::{Cluster, ClusterQuery},
+    cluster::{Cluster, ClusterEscalation, ClusterQuery},
     event::EventKind,
+    report::ReportSchedule,
 };
 
 const COLLECTION: &str = "clusters";
@@ -22,6 +23,11 @@ pub struct ClusterRef {
 pub struct ViewCluster {
     pub id: String,
     pub name: String,
+    pub retention: Option<i64>,
+    #[serde(default)]
+    pub report: Vec<ReportSchedule>,
+    #[serde(default)]
+    pub escalation: Vec<ClusterEscalation>,
     pub processor_count: usize,
     pub notification_count: usize,
     pub violation_count: usize,
@@ -247,6 +253,9 @@ impl ViewCluster {
             "$project": {
                 "id": "$id",
                 "name": "$name",
+                "retention": "$retention",
+                "report": "$report",
+                "escalation": "$escalation",
                 "processor_count": {
                     "$cond": [
                         { "$first": "$processor" },
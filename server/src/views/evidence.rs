@@ -1,6 +1,6 @@
 use futures::StreamExt;
 use mongodb::{
-    Database,
+    Cursor, Database,
     bson::{Document, doc, from_document},
 };
 use serde::{Deserialize, Serialize};
@@ -10,10 +10,18 @@ use crate::{
         camera::Camera,
         cluster::Cluster,
         event::EventKind,
-        evidence::{Evidence, EvidencePerson, EvidenceQuery},
+        evidence::{
+            Evidence, EvidenceCorrection, EvidenceEscalation, EvidencePerson, EvidenceQuery,
+            EvidenceReview, EvidenceStatus,
+        },
         processor::Processor,
     },
-    views::{camera::CameraRef, cluster::ClusterRef, processor::ProcessorRef},
+    views::{
+        camera::CameraRef,
+        cluster::ClusterRef,
+        page::{PageCursor, PageOrder, ViewPage, page_limit, page_total},
+        processor::ProcessorRef,
+    },
 };
 
 const COLLECTION: &str = "evidences";
@@ -26,6 +34,23 @@ pub struct ViewEvidence {
     pub camera: CameraRef,
     pub timestamp: i64,
     pub person: Vec<EvidencePerson>,
+    pub clip: Option<String>,
+    #[serde(default)]
+    pub status: EvidenceStatus,
+    #[serde(default)]
+    pub review: Vec<EvidenceReview>,
+    #[serde(default)]
+    pub correction: Vec<EvidenceCorrection>,
+    #[serde(default)]
+    pub escalation: Vec<EvidenceEscalation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ViewEvidenceReview {
+    pub id: String,
+    pub status: EvidenceStatus,
+    pub review: Vec<EvidenceReview>, // Oldest first
+    pub correction: Vec<EvidenceCorrection>,
 }
 
 impl ViewEvidence {
@@ -68,46 +93,42 @@ impl ViewEvidence {
             camera,
             timestamp: evidence.timestamp,
             person: evidence.person,
+            clip: evidence.clip,
+            status: evidence.status,
+            review: evidence.review,
+            correction: evidence.correction,
+            escalation: evidence.escalation,
         }
     }
-    pub async fn find_many(query: &EvidenceQuery, db: &Database) -> Result<Vec<Self>, EventKind> {
+    pub async fn find_many(
+        query: &EvidenceQuery,
+        db: &Database,
+    ) -> Result<ViewPage<Self>, EventKind> {
         let collection = db.collection::<Self>(COLLECTION);
 
-        let mut evidence_query = Vec::new();
+        let limit = page_limit(query.limit);
+        let cursor = match &query.cursor {
+            Some(cursor) => Some(PageCursor::<i64>::decode(cursor)?),
+            None => None,
+        };
 
-        if let Some(cluster_id) = &query.cluster_id {
-            evidence_query.push(doc! {
-                "$eq": ["$cluster_id", &cluster_id]
-            });
-        }
-        if let Some(processor_id) = &query.processor_id {
-            evidence_query.push(doc! {
-                "$eq": ["$processor_id", &processor_id]
-            });
-        }
-        if let Some(camera_id) = &query.camera_id {
-            evidence_query.push(doc! {
-                "$eq": ["$camera_id", &camera_id]
-            });
-        }
-        if let Some(date) = &query.date_minimum {
-            evidence_query.push(doc! {
-                "$gte": ["$timestamp", date]
-            });
-        }
-        if let Some(date) = &query.date_maximum {
-            evidence_query.push(doc! {
-                "$lte": ["$timestamp", date]
-            });
-        }
+        // Filters run before the lookups as a plain match so the evidence indexes apply
+        let filter = doc! { "$match": Evidence::create_filter(query) };
+        let total = page_total(&collection, vec![filter.clone()]).await?;
 
-        let pipeline = vec![
-            Self::create_match_stage(&evidence_query),
+        let mut pipeline = vec![filter];
+        pipeline.extend(PageCursor::create_page_stages(
+            "timestamp",
+            query.order.unwrap_or(PageOrder::Desc),
+            cursor,
+            limit,
+        ));
+        pipeline.extend([
             Self::create_cluster_lookup_stage(),
             Self::create_processor_lookup_stage(),
             Self::create_camera_lookup_stage(),
             Self::create_project_stage(),
-        ];
+        ]);
 
         match collection.aggregate(pipeline, None).await {
             Ok(mut cursor) => {
@@ -116,11 +137,9 @@ impl ViewEvidence {
                     let evidence = from_document::<ViewEvidence>(doc).unwrap();
                     evidences.push(evidence);
                 }
-                if !evidences.is_empty() {
-                    Ok(evidences)
-                } else {
-                    Err(EventKind::NotFound)
-                }
+                Ok(ViewPage::from(evidences, total, limit, |v| {
+                    (v.timestamp, v.id.clone())
+                }))
             }
             Err(e) => {
                 println!("ERROR: {:?}", e);
@@ -159,6 +178,32 @@ impl ViewEvidence {
         }
     }
 
+    // Every evidence matching the filter, oldest first, read one document at a time so exports do
+    // not hold the whole result in memory
+    pub async fn find_cursor(
+        filter: Document,
+        db: &Database,
+    ) -> Result<Cursor<Document>, EventKind> {
+        let collection = db.collection::<Self>(COLLECTION);
+
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$sort": { "timestamp": 1, "id": 1 } },
+            Self::create_cluster_lookup_stage(),
+            Self::create_processor_lookup_stage(),
+            Self::create_camera_lookup_stage(),
+            Self::create_project_stage(),
+        ];
+
+        match collection.aggregate(pipeline, None).await {
+            Ok(cursor) => Ok(cursor),
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+
     // Helper functions to create aggregation stages
     fn create_match_stage(query: &Vec<Document>) -> Document {
         doc! {
@@ -259,7 +304,254 @@ impl ViewEvidence {
                 },
                 "timestamp": "$timestamp",
                 "person": "$person",
+                "clip": "$clip",
+                "status": "$status",
+                "review": "$review",
+                "correction": "$correction",
+                "escalation": "$escalation",
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+
+    use crate::{
+        database,
+        models::evidence::{
+            Evidence, EvidencePerson, EvidencePersonEquipment, EvidencePersonEquipmentLabel,
+            EvidencePersonViolation, EvidenceQuery, EvidenceQueryMatch, EvidenceStatus,
+        },
+        views::page::PageOrder,
+    };
+
+    use super::ViewEvidence;
+
+    fn query(cluster_id: Option<&str>, scope: Option<Vec<&str>>) -> EvidenceQuery {
+        EvidenceQuery {
+            cluster_id: cluster_id.map(|v| v.to_string()),
+            scope: scope.map(|v| v.into_iter().map(|v| v.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn find_many_is_limited_to_scope() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+        database::test::seed("south", &db).await;
+
+        let evidences = ViewEvidence::find_many(&query(None, None), &db)
+            .await
+            .unwrap()
+            .data;
+        assert_eq!(evidences.len(), 2);
+
+        let evidences = ViewEvidence::find_many(&query(None, Some(vec!["north"])), &db)
+            .await
+            .unwrap()
+            .data;
+        assert_eq!(evidences.len(), 1);
+        assert_eq!(evidences[0].cluster.id, "north");
+
+        // An explicit cluster outside the scope matches nothing
+        assert!(
+            ViewEvidence::find_many(&query(Some("south"), Some(vec!["north"])), &db)
+                .await
+                .unwrap()
+                .data
+                .is_empty()
+        );
+        assert!(
+            ViewEvidence::find_many(&query(None, Some(Vec::new())), &db)
+                .await
+                .unwrap()
+                .data
+                .is_empty()
+        );
+
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn find_many_pages_by_timestamp() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+        // Two evidences share a timestamp so the id has to break the tie
+        for (id, timestamp) in [("a", 10), ("b", 20), ("c", 20), ("d", 30)] {
+            let evidence = Evidence {
+                id: id.to_string(),
+                cluster_id: String::from("north"),
+                processor_id: String::from("north_processor"),
+                camera_id: String::from("north_camera"),
+                frame_id: String::from("000001"),
+                timestamp,
+                person: Vec::new(),
+                clip: None,
+                status: EvidenceStatus::Open,
+                review: Vec::new(),
+                correction: Vec::new(),
+                escalation: Vec::new(),
+            };
+            evidence.save(&db).await.unwrap();
+        }
+
+        let mut query = query(None, None);
+        query.limit = Some(2);
+
+        let mut id = Vec::new();
+        loop {
+            let page = ViewEvidence::find_many(&query, &db).await.unwrap();
+            assert_eq!(page.total, 5);
+            assert!(page.data.len() <= 2);
+            id.extend(page.data.into_iter().map(|v| v.id));
+            match page.cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(id, ["d", "c", "b", "a", "north_evidence"]);
+
+        query.cursor = None;
+        query.order = Some(PageOrder::Asc);
+        let page = ViewEvidence::find_many(&query, &db).await.unwrap();
+        assert_eq!(
+            page.data.iter().map(|v| v.id.as_str()).collect::<Vec<_>>(),
+            ["north_evidence", "a"]
+        );
+
+        query.cursor = Some(String::from("not a cursor"));
+        assert!(ViewEvidence::find_many(&query, &db).await.is_err());
+
+        db.drop(None).await.unwrap();
+    }
+
+    #[test]
+    fn query_parses_comma_separated_lists() {
+        let query = web::Query::<EvidenceQuery>::from_query(
+            "violation=missing_hardhat,missing_gloves&violation_match=all&equipment_missing=gloves",
+        )
+        .unwrap()
+        .into_inner();
+
+        assert_eq!(query.violation.map(|v| v.len()), Some(2));
+        assert_eq!(query.violation_match, Some(EvidenceQueryMatch::All));
+        assert_eq!(query.equipment_missing.map(|v| v.len()), Some(1));
+        assert!(query.equipment.is_none());
+
+        assert!(web::Query::<EvidenceQuery>::from_query("violation=missing_helmet").is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn find_many_filters_by_person() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+
+        let person = |violation: Vec<EvidencePersonViolation>,
+                      equipment: Vec<EvidencePersonEquipmentLabel>,
+                      confidence: f32| EvidencePerson {
+            id: String::from("001"),
+            bbox: [0.0, 0.0, 1.0, 1.0],
+            confidence,
+            part: Vec::new(),
+            equipment: equipment
+                .into_iter()
+                .map(|label| EvidencePersonEquipment {
+                    label,
+                    bbox: [0.0, 0.0, 1.0, 1.0],
+                    confidence,
+                })
+                .collect(),
+            violation,
+            confirmation: Vec::new(),
+        };
+        let evidence = [
+            (
+                "x",
+                vec![person(
+                    vec![EvidencePersonViolation::MissingHardhat],
+                    vec![EvidencePersonEquipmentLabel::Gloves],
+                    0.9,
+                )],
+            ),
+            (
+                "y",
+                vec![
+                    person(
+                        vec![EvidencePersonViolation::MissingHardhat],
+                        Vec::new(),
+                        0.5,
+                    ),
+                    person(
+                        vec![EvidencePersonViolation::MissingGloves],
+                        Vec::new(),
+                        0.5,
+                    ),
+                ],
+            ),
+        ];
+        for (id, person) in evidence {
+            let evidence = Evidence {
+                id: id.to_string(),
+                cluster_id: String::from("north"),
+                processor_id: String::from("north_processor"),
+                camera_id: String::from("north_camera"),
+                frame_id: String::from("000001"),
+                timestamp: 10,
+                person,
+                clip: None,
+                status: EvidenceStatus::Open,
+                review: Vec::new(),
+                correction: Vec::new(),
+                escalation: Vec::new(),
+            };
+            evidence.save(&db).await.unwrap();
+        }
+
+        let find = async |query: &EvidenceQuery| {
+            let mut id = ViewEvidence::find_many(query, &db)
+                .await
+                .unwrap()
+                .data
+                .into_iter()
+                .map(|v| v.id)
+                .collect::<Vec<_>>();
+            id.sort();
+            id
+        };
+
+        let mut q = query(None, None);
+        q.violation = Some(vec![EvidencePersonViolation::MissingHardhat]);
+        assert_eq!(find(&q).await, ["x", "y"]);
+
+        q.violation = Some(vec![
+            EvidencePersonViolation::MissingHardhat,
+            EvidencePersonViolation::MissingGloves,
+        ]);
+        q.violation_match = Some(EvidenceQueryMatch::All);
+        assert_eq!(find(&q).await, ["y"]);
+
+        let mut q = query(None, None);
+        q.equipment = Some(vec![EvidencePersonEquipmentLabel::Gloves]);
+        assert_eq!(find(&q).await, ["x"]);
+
+        let mut q = query(None, None);
+        q.equipment_missing = Some(vec![EvidencePersonEquipmentLabel::Gloves]);
+        assert_eq!(find(&q).await, ["y"]);
+
+        let mut q = query(None, None);
+        q.person_minimum = Some(2);
+        assert_eq!(find(&q).await, ["y"]);
+
+        let mut q = query(None, None);
+        q.confidence_minimum = Some(0.8);
+        assert_eq!(find(&q).await, ["x"]);
+
+        db.drop(None).await.unwrap();
+    }
+}
@@ -0,0 +1,43 @@
+use serde::Serialize;
+
+use crate::models::export::{Export, ExportKind, ExportStatus};
+
+#[derive(Debug, Serialize)]
+pub struct ViewExport {
+    pub id: String,
+    pub kind: ExportKind,
+    pub status: ExportStatus,
+    pub evidence_count: u64,
+    pub skipped_count: u64,
+    pub size: u64,
+    pub timestamp: i64,
+    pub completed_timestamp: Option<i64>,
+    pub expiry: Option<i64>,
+    pub path: Option<String>, // Download path, set once the archive is ready
+}
+
+impl From<Export> for ViewExport {
+    fn from(export: Export) -> Self {
+        let path = match export.status {
+            ExportStatus::Completed => Some(format!(
+                "{}/exports/{}/download",
+                std::env::var("BASE_PATH").unwrap(),
+                export.id
+            )),
+            _ => None,
+        };
+
+        Self {
+            id: export.id,
+            kind: export.kind,
+            status: export.status,
+            evidence_count: export.evidence_count,
+            skipped_count: export.skipped_count,
+            size: export.size,
+            timestamp: export.timestamp,
+            completed_timestamp: export.completed_timestamp,
+            expiry: export.expiry,
+            path,
+        }
+    }
+}
@@ -1,5 +1,9 @@
+pub mod analytics;
 pub mod camera;
 pub mod cluster;
 pub mod evidence;
+pub mod export;
+pub mod page;
 pub mod processor;
+pub mod report;
 pub mod user;
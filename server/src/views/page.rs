@@ -0,0 +1,138 @@
+use futures::StreamExt;
+use mongodb::{
+    Collection,
+    bson::{Bson, Document, doc},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::models::event::EventKind;
+
+// Items per page when the query does not say
+pub const PAGE_LIMIT: usize = 50;
+pub const PAGE_LIMIT_MAXIMUM: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageOrder {
+    Asc,
+    Desc,
+}
+
+// Listings are returned one page at a time, pass `cursor` back to get the next page
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ViewPage<T> {
+    pub data: Vec<T>,
+    pub total: u64,             // Items matching the query across every page
+    pub cursor: Option<String>, // None on the last page
+}
+
+// Position after the last item of a page, the sort key and id of that item encoded as hex JSON
+// so clients treat it as opaque
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PageCursor<K> {
+    pub key: K,
+    pub id: String,
+}
+
+impl<K: Serialize + DeserializeOwned + Into<Bson>> PageCursor<K> {
+    pub fn encode(key: K, id: String) -> String {
+        hex::encode(serde_json::to_vec(&Self { key, id }).unwrap())
+    }
+    pub fn decode(cursor: &str) -> Result<Self, EventKind> {
+        hex::decode(cursor)
+            .ok()
+            .and_then(|v| serde_json::from_slice::<Self>(&v).ok())
+            .ok_or(EventKind::InvalidCursor)
+    }
+
+    // Stages that sort by the key then the id, continue after the cursor and cut the page. One
+    // item more than the limit is fetched so the caller knows whether another page follows.
+    pub fn create_page_stages(
+        field: &str,
+        order: PageOrder,
+        cursor: Option<Self>,
+        limit: usize,
+    ) -> Vec<Document> {
+        let (direction, comparison) = match order {
+            PageOrder::Asc => (1, "$gt"),
+            PageOrder::Desc => (-1, "$lt"),
+        };
+
+        let mut stages = Vec::new();
+        if let Some(cursor) = cursor {
+            let key: Bson = cursor.key.into();
+            stages.push(doc! {
+                "$match": {
+                    "$or": [
+                        { field: { comparison: key.clone() } },
+                        { field: key, "id": { comparison: cursor.id } }
+                    ]
+                }
+            });
+        }
+        stages.push(doc! {
+            "$sort": { field: direction, "id": direction }
+        });
+        stages.push(doc! {
+            "$limit": (limit + 1) as i64
+        });
+
+        stages
+    }
+}
+
+impl<T> ViewPage<T> {
+    // Cut the extra item fetched by the page stages and point the cursor at the last item
+    pub fn from<K>(
+        mut data: Vec<T>,
+        total: u64,
+        limit: usize,
+        key: impl Fn(&T) -> (K, String),
+    ) -> Self
+    where
+        K: Serialize + DeserializeOwned + Into<Bson>,
+    {
+        let cursor = if data.len() > limit {
+            data.truncate(limit);
+            data.last().map(|v| {
+                let (k, id) = key(v);
+                PageCursor::encode(k, id)
+            })
+        } else {
+            None
+        };
+
+        Self {
+            data,
+            total,
+            cursor,
+        }
+    }
+}
+
+// Page size requested by a query, kept within bounds
+pub fn page_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(PAGE_LIMIT).clamp(1, PAGE_LIMIT_MAXIMUM)
+}
+
+// Documents matching the filter stages, ignoring the cursor and page size
+pub async fn page_total<T>(
+    collection: &Collection<T>,
+    mut pipeline: Vec<Document>,
+) -> Result<u64, EventKind> {
+    pipeline.push(doc! { "$count": "total" });
+
+    match collection.aggregate(pipeline, None).await {
+        Ok(mut cursor) => match cursor.next().await {
+            Some(Ok(doc)) => Ok(doc
+                .get("total")
+                .and_then(|v| v.as_i32().map(i64::from).or(v.as_i64()))
+                .unwrap_or(0) as u64),
+            _ => Ok(0),
+        },
+        Err(e) => {
+            println!("ERROR: {:?}", e);
+            Err(EventKind::FindingFailed)
+        }
+    }
+}
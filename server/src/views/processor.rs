@@ -1,7 +1,7 @@
 use futures::StreamExt;
 use mongodb::{
     Database,
-    bson::{Document, doc, from_document, to_bson},
+    bson::{Document, doc, from_document},
 };
 use serde::{Deserialize, Serialize};
 
@@ -11,7 +11,11 @@ use crate::{
         event::EventKind,
         processor::{ProcessorAddress, ProcessorQuery},
     },
-    views::{camera::ViewCamera, cluster::ClusterRef},
+    views::{
+        camera::ViewCamera,
+        cluster::ClusterRef,
+        page::{PageCursor, PageOrder, ViewPage, page_limit, page_total},
+    },
 };
 
 const COLLECTION: &str = "processors";
@@ -34,9 +38,18 @@ pub struct ViewProcessor {
 }
 
 impl ViewProcessor {
-    pub async fn find_many(query: &ProcessorQuery, db: &Database) -> Result<Vec<Self>, EventKind> {
+    pub async fn find_many(
+        query: &ProcessorQuery,
+        db: &Database,
+    ) -> Result<ViewPage<Self>, EventKind> {
         let collection = db.collection::<Self>(COLLECTION);
 
+        let limit = page_limit(query.limit);
+        let cursor = match &query.cursor {
+            Some(cursor) => Some(PageCursor::<String>::decode(cursor)?),
+            None => None,
+        };
+
         let mut processor_query = Vec::new();
         let mut violation_query = Vec::from([doc! {
             "$eq": ["$processor_id", "$$processor_id"]
@@ -57,6 +70,11 @@ impl ViewProcessor {
                 "$eq": ["$cluster_id", cluster_id]
             });
         }
+        if let Some(scope) = &query.scope {
+            processor_query.push(doc! {
+                "$in": ["$cluster_id", scope]
+            });
+        }
         if let Some(processor_id) = &query.processor_id {
             processor_query.push(doc! {
                 "$eq": ["$id", processor_id]
@@ -75,31 +93,32 @@ impl ViewProcessor {
             });
         }
 
-        let mut pipeline = vec![
-            Self::create_match_stage(&processor_query),
+        let total = page_total(
+            &collection,
+            vec![Self::create_match_stage(&processor_query)],
+        )
+        .await?;
+
+        let mut pipeline = vec![Self::create_match_stage(&processor_query)];
+        pipeline.extend(PageCursor::create_page_stages(
+            "name",
+            query.order.unwrap_or(PageOrder::Asc),
+            cursor,
+            limit,
+        ));
+        pipeline.extend([
             Self::create_cluster_lookup_stage(),
             Self::create_notification_count_stage(&violation_query),
             Self::create_violation_count_stage(&violation_query),
             Self::create_project_stage(),
-        ];
-
-        if let Some(skip) = query.skip {
-            pipeline.push(doc! {
-                "$skip": to_bson::<usize>(&skip).unwrap()
-            });
-        }
-        if let Some(limit) = query.limit {
-            pipeline.push(doc! {
-                "$limit": to_bson::<usize>(&limit).unwrap()
-            });
-        }
+        ]);
 
         match collection.aggregate(pipeline, None).await {
             Ok(mut cursor) => {
                 let mut processors = Vec::new();
                 while let Some(Ok(doc)) = cursor.next().await {
                     let mut processor = from_document::<Self>(doc).unwrap();
-                    if let Ok(camera) = ViewCamera::find_many(
+                    if let Ok(camera) = ViewCamera::find_all(
                         &CameraQuery {
                             cluster_id: query.cluster_id.clone(),
                             processor_id: Some(processor.id.clone()),
@@ -107,7 +126,9 @@ impl ViewProcessor {
                             date_maximum: query.date_maximum,
                             text: None,
                             limit: None,
-                            skip: None,
+                            cursor: None,
+                            order: None,
+                            scope: query.scope.clone(),
                         },
                         db,
                     )
@@ -117,11 +138,9 @@ impl ViewProcessor {
                     }
                     processors.push(processor);
                 }
-                if !processors.is_empty() {
-                    Ok(processors)
-                } else {
-                    Err(EventKind::NotFound)
-                }
+                Ok(ViewPage::from(processors, total, limit, |v| {
+                    (v.name.clone(), v.id.clone())
+                }))
             }
             Err(e) => {
                 println!("ERROR: {:?}", e);
@@ -182,7 +201,7 @@ impl ViewProcessor {
             Ok(mut cursor) => {
                 if let Some(Ok(doc)) = cursor.next().await {
                     let mut processor = from_document::<Self>(doc).unwrap();
-                    if let Ok(camera) = ViewCamera::find_many(
+                    if let Ok(camera) = ViewCamera::find_all(
                         &CameraQuery {
                             cluster_id: None,
                             processor_id: Some(processor.id.clone()),
@@ -190,7 +209,9 @@ impl ViewProcessor {
                             date_maximum: None,
                             text: None,
                             limit: None,
-                            skip: None,
+                            cursor: None,
+                            order: None,
+                            scope: None,
                         },
                         db,
                     )
@@ -331,3 +352,58 @@ impl ViewProcessor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{database, models::processor::ProcessorQuery};
+
+    use super::ViewProcessor;
+
+    fn query(cluster_id: Option<&str>, scope: Option<Vec<&str>>) -> ProcessorQuery {
+        ProcessorQuery {
+            cluster_id: cluster_id.map(|v| v.to_string()),
+            processor_id: None,
+            date_minimum: None,
+            date_maximum: None,
+            text: None,
+            limit: None,
+            cursor: None,
+            order: None,
+            scope: scope.map(|v| v.into_iter().map(|v| v.to_string()).collect()),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local MongoDB"]
+    async fn find_many_is_limited_to_scope() {
+        let db = database::test::connect().await;
+        database::test::seed("north", &db).await;
+        database::test::seed("south", &db).await;
+
+        let processors = ViewProcessor::find_many(&query(None, None), &db)
+            .await
+            .unwrap()
+            .data;
+        assert_eq!(processors.len(), 2);
+
+        let processors = ViewProcessor::find_many(&query(None, Some(vec!["north"])), &db)
+            .await
+            .unwrap()
+            .data;
+        assert_eq!(processors.len(), 1);
+        assert_eq!(processors[0].id, "north_processor");
+        assert_eq!(processors[0].cluster.id, "north");
+        assert!(processors[0].camera.iter().all(|v| v.cluster.id == "north"));
+
+        // An explicit cluster outside the scope matches nothing
+        assert!(
+            ViewProcessor::find_many(&query(Some("south"), Some(vec!["north"])), &db)
+                .await
+                .unwrap()
+                .data
+                .is_empty()
+        );
+
+        db.drop(None).await.unwrap();
+    }
+}
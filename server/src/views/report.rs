@@ -0,0 +1,461 @@
+use std::{cmp::Reverse, collections::HashMap, fs::read};
+
+use chrono::DateTime;
+use futures::StreamExt;
+use mongodb::{
+    Database,
+    bson::{doc, from_document},
+};
+use printpdf::{
+    BuiltinFont, Image, ImageTransform, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, image_crate,
+};
+use serde::Serialize;
+
+use crate::{
+    models::{
+        analytics::{AnalyticsBucket, AnalyticsGroup, AnalyticsQuery},
+        camera::Camera,
+        cluster::Cluster,
+        event::EventKind,
+        evidence::{Evidence, EvidenceQuery},
+        report::ReportFormat,
+    },
+    views::{
+        analytics::{ViewAnalytics, ViewAnalyticsCamera, ViewAnalyticsCompliance},
+        cluster::ClusterRef,
+    },
+};
+
+const COLLECTION: &str = "evidences";
+// Latest violating evidence embedded as thumbnails in the PDF
+const REPORT_EVIDENCE: i64 = 12;
+// Cameras listed in the report, every camera with a violation in practice
+const REPORT_CAMERA: usize = 100;
+
+// A4 portrait, in millimeters
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const PAGE_MARGIN: f32 = 20.0;
+const THUMBNAIL_COLUMN: usize = 3;
+const THUMBNAIL_WIDTH: f32 = 54.0;
+const THUMBNAIL_GAP: f32 = 4.0;
+
+#[derive(Debug, Serialize)]
+pub struct ViewReport {
+    pub cluster: ClusterRef,
+    pub date_minimum: i64,
+    pub date_maximum: i64,
+    pub compliance: ViewAnalyticsCompliance,
+    pub violation: Vec<ViewReportViolation>, // Most violations first
+    pub camera: Vec<ViewAnalyticsCamera>,    // Most violations first
+    pub evidence: Vec<ViewReportEvidence>,   // Newest first
+}
+#[derive(Debug, Serialize)]
+pub struct ViewReportViolation {
+    pub kind: String,
+    pub violation_count: u64,
+}
+#[derive(Debug, Serialize)]
+pub struct ViewReportEvidence {
+    pub id: String,
+    pub camera: String,
+    pub timestamp: i64,
+    pub violation_count: usize,
+}
+
+// Page being written, a new one is added when the content reaches the bottom margin
+struct ReportPage {
+    document: PdfDocumentReference,
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    bold: IndirectFontRef,
+    y: f32,
+}
+
+impl ViewReport {
+    pub async fn find(
+        cluster: &Cluster,
+        date_minimum: i64,
+        date_maximum: i64,
+        db: &Database,
+    ) -> Result<Self, EventKind> {
+        let query = AnalyticsQuery {
+            cluster_id: Some(cluster.id.clone()),
+            processor_id: None,
+            camera_id: None,
+            date_minimum: Some(date_minimum),
+            date_maximum: Some(date_maximum),
+            bucket: Some(AnalyticsBucket::Day),
+            group: Some(AnalyticsGroup::Violation),
+            timezone: None,
+            top: Some(REPORT_CAMERA),
+            scope: None,
+        };
+        let analytics = ViewAnalytics::find(&query, db).await?;
+
+        let mut violation = analytics
+            .series
+            .into_iter()
+            .map(|v| ViewReportViolation {
+                kind: v.id.unwrap_or_default(),
+                violation_count: v.point.iter().map(|p| p.violation_count).sum(),
+            })
+            .collect::<Vec<_>>();
+        violation.sort_by_key(|v| Reverse(v.violation_count));
+
+        let evidence_query = query.evidence_query(date_minimum, date_maximum);
+        let evidence = Self::find_evidence(&evidence_query, db).await?;
+
+        Ok(Self {
+            cluster: ClusterRef {
+                id: cluster.id.clone(),
+                name: cluster.name.clone(),
+            },
+            date_minimum,
+            date_maximum,
+            compliance: analytics.compliance,
+            violation,
+            camera: analytics.camera,
+            evidence,
+        })
+    }
+
+    async fn find_evidence(
+        query: &EvidenceQuery,
+        db: &Database,
+    ) -> Result<Vec<ViewReportEvidence>, EventKind> {
+        let collection = db.collection::<Evidence>(COLLECTION);
+
+        let mut filter = Evidence::create_filter(query);
+        filter.insert("person.violation.0", doc! { "$exists": true });
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$sort": { "timestamp": -1, "id": -1 } },
+            doc! { "$limit": REPORT_EVIDENCE },
+        ];
+
+        let mut camera = HashMap::<String, String>::new();
+        match collection.aggregate(pipeline, None).await {
+            Ok(mut cursor) => {
+                let mut evidences = Vec::new();
+                while let Some(Ok(doc)) = cursor.next().await {
+                    let evidence = match from_document::<Evidence>(doc) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            println!("ERROR: {:?}", e);
+                            continue;
+                        }
+                    };
+                    if !camera.contains_key(&evidence.camera_id) {
+                        let name = match Camera::find_by_id(&evidence.camera_id, db).await {
+                            Ok(v) => v.name,
+                            Err(_) => evidence.camera_id.clone(),
+                        };
+                        camera.insert(evidence.camera_id.clone(), name);
+                    }
+                    evidences.push(ViewReportEvidence {
+                        camera: camera[&evidence.camera_id].clone(),
+                        timestamp: evidence.timestamp,
+                        violation_count: evidence.person.iter().map(|p| p.violation.len()).sum(),
+                        id: evidence.id,
+                    });
+                }
+                Ok(evidences)
+            }
+            Err(e) => {
+                println!("ERROR: {:?}", e);
+                Err(EventKind::FindingFailed)
+            }
+        }
+    }
+
+    pub fn render(&self, format: ReportFormat) -> Result<Vec<u8>, EventKind> {
+        match format {
+            ReportFormat::Csv => self.to_csv(),
+            ReportFormat::Pdf => self.to_pdf(),
+        }
+    }
+
+    // One row per figure: the summary, then the counts by violation kind and by camera
+    pub fn to_csv(&self) -> Result<Vec<u8>, EventKind> {
+        let compliance = &self.compliance;
+        let summary = [
+            ("date_minimum", format_date(self.date_minimum)),
+            ("date_maximum", format_date(self.date_maximum)),
+            ("evidence_count", compliance.evidence_count.to_string()),
+            ("person_count", compliance.person_count.to_string()),
+            (
+                "person_violating_count",
+                compliance.person_violating_count.to_string(),
+            ),
+            ("violation_count", compliance.violation_count.to_string()),
+            (
+                "compliance_rate",
+                compliance
+                    .rate
+                    .map(|v| format!("{:.4}", v))
+                    .unwrap_or_default(),
+            ),
+        ];
+
+        let mut rows =
+            vec![["summary", "cluster", &self.cluster.name, &self.cluster.id].map(String::from)];
+        for (id, value) in summary {
+            rows.push(["summary", id, "", &value].map(String::from));
+        }
+        for v in self.violation.iter() {
+            rows.push(
+                [
+                    "violation",
+                    &v.kind,
+                    &v.kind,
+                    &v.violation_count.to_string(),
+                ]
+                .map(String::from),
+            );
+        }
+        for v in self.camera.iter() {
+            rows.push(["camera", &v.id, &v.name, &v.violation_count.to_string()].map(String::from));
+        }
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let _ = writer.write_record(["section", "id", "name", "value"]);
+        for row in rows {
+            if writer.write_record(&row).is_err() {
+                return Err(EventKind::RenderingFailed);
+            }
+        }
+
+        writer.into_inner().map_err(|_| EventKind::RenderingFailed)
+    }
+
+    // A4 summary with the counts and thumbnails of the latest violating evidence
+    pub fn to_pdf(&self) -> Result<Vec<u8>, EventKind> {
+        let title = format!("Compliance report {}", self.cluster.name);
+        let (document, page, layer) =
+            PdfDocument::new(&title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        let layer = document.get_page(page).get_layer(layer);
+        let font = document
+            .add_builtin_font(BuiltinFont::Helvetica)
+            .map_err(|_| EventKind::RenderingFailed)?;
+        let bold = document
+            .add_builtin_font(BuiltinFont::HelveticaBold)
+            .map_err(|_| EventKind::RenderingFailed)?;
+
+        let mut page = ReportPage {
+            document,
+            layer,
+            font,
+            bold,
+            y: PAGE_HEIGHT - PAGE_MARGIN,
+        };
+
+        let compliance = &self.compliance;
+        page.heading(&title, 16.0);
+        page.text(&format!(
+            "{} to {}",
+            format_date(self.date_minimum),
+            format_date(self.date_maximum)
+        ));
+        page.space();
+
+        page.heading("Summary", 12.0);
+        page.text(&format!(
+            "Compliance rate: {}",
+            compliance
+                .rate
+                .map(|v| format!("{:.1}%", v * 100.0))
+                .unwrap_or(String::from("-"))
+        ));
+        page.text(&format!("Evidence: {}", compliance.evidence_count));
+        page.text(&format!(
+            "Persons: {} ({} violating)",
+            compliance.person_count, compliance.person_violating_count
+        ));
+        page.text(&format!("Violations: {}", compliance.violation_count));
+        page.space();
+
+        page.heading("Violations by kind", 12.0);
+        if self.violation.is_empty() {
+            page.text("-");
+        }
+        for v in self.violation.iter() {
+            page.text(&format!("{}: {}", v.kind, v.violation_count));
+        }
+        page.space();
+
+        page.heading("Violations by camera", 12.0);
+        if self.camera.is_empty() {
+            page.text("-");
+        }
+        for v in self.camera.iter() {
+            page.text(&format!("{}: {}", v.name, v.violation_count));
+        }
+        page.space();
+
+        if !self.evidence.is_empty() {
+            page.heading("Latest evidence", 12.0);
+            for row in self.evidence.chunks(THUMBNAIL_COLUMN) {
+                page.thumbnails(row);
+            }
+        }
+
+        page.document
+            .save_to_bytes()
+            .map_err(|_| EventKind::RenderingFailed)
+    }
+}
+
+impl ReportPage {
+    fn heading(&mut self, text: &str, size: f32) {
+        self.reserve(size * 0.6);
+        self.layer
+            .use_text(text, size, Mm(PAGE_MARGIN), Mm(self.y), &self.bold);
+        self.y -= size * 0.6;
+    }
+    fn text(&mut self, text: &str) {
+        self.reserve(5.0);
+        self.layer
+            .use_text(text, 10.0, Mm(PAGE_MARGIN), Mm(self.y), &self.font);
+        self.y -= 5.0;
+    }
+    fn space(&mut self) {
+        self.y -= 4.0;
+    }
+    // A row of evidence thumbnails with their camera and time underneath, evidence without a
+    // readable image keeps its caption only
+    fn thumbnails(&mut self, row: &[ViewReportEvidence]) {
+        let image = row
+            .iter()
+            .map(|v| {
+                read(format!("./evidence/{}.annotated.jpg", v.id))
+                    .or_else(|_| read(format!("./evidence/{}.jpg", v.id)))
+                    .ok()
+                    .and_then(|data| image_crate::load_from_memory(&data).ok())
+                    .map(|image| image.thumbnail(480, 480).to_rgb8())
+            })
+            .collect::<Vec<_>>();
+        let height = image
+            .iter()
+            .flatten()
+            .map(|v| THUMBNAIL_WIDTH * v.height() as f32 / v.width() as f32)
+            .fold(0.0, f32::max);
+
+        self.reserve(height + 10.0);
+        for (i, (evidence, image)) in row.iter().zip(image).enumerate() {
+            let x = PAGE_MARGIN + i as f32 * (THUMBNAIL_WIDTH + THUMBNAIL_GAP);
+            if let Some(image) = image {
+                let dpi = image.width() as f32 * 25.4 / THUMBNAIL_WIDTH;
+                Image::from_dynamic_image(&image_crate::DynamicImage::ImageRgb8(image))
+                    .add_to_layer(
+                        self.layer.clone(),
+                        ImageTransform {
+                            translate_x: Some(Mm(x)),
+                            translate_y: Some(Mm(self.y - height)),
+                            dpi: Some(dpi),
+                            ..Default::default()
+                        },
+                    );
+            }
+            self.layer.use_text(
+                &evidence.camera,
+                8.0,
+                Mm(x),
+                Mm(self.y - height - 4.0),
+                &self.font,
+            );
+            self.layer.use_text(
+                format!(
+                    "{} ({} violations)",
+                    format_date(evidence.timestamp),
+                    evidence.violation_count
+                ),
+                8.0,
+                Mm(x),
+                Mm(self.y - height - 8.0),
+                &self.font,
+            );
+        }
+        self.y -= height + 12.0;
+    }
+    // Continue on a new page when the next block does not fit above the margin
+    fn reserve(&mut self, height: f32) {
+        if self.y - height >= PAGE_MARGIN {
+            return;
+        }
+        let (page, layer) = self
+            .document
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        self.layer = self.document.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - PAGE_MARGIN;
+    }
+}
+
+fn format_date(timestamp: i64) -> String {
+    DateTime::from_timestamp_millis(timestamp)
+        .map(|v| v.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::views::{
+        analytics::{ViewAnalyticsCamera, ViewAnalyticsCompliance},
+        cluster::ClusterRef,
+    };
+
+    use super::{ViewReport, ViewReportEvidence, ViewReportViolation};
+
+    fn report() -> ViewReport {
+        ViewReport {
+            cluster: ClusterRef {
+                id: String::from("north"),
+                name: String::from("North, Site"),
+            },
+            date_minimum: 0,
+            date_maximum: 24 * 60 * 60 * 1000 - 1,
+            compliance: ViewAnalyticsCompliance {
+                evidence_count: 2,
+                person_count: 4,
+                person_violating_count: 1,
+                violation_count: 3,
+                rate: Some(0.75),
+            },
+            violation: vec![ViewReportViolation {
+                kind: String::from("missing_hardhat"),
+                violation_count: 3,
+            }],
+            camera: vec![ViewAnalyticsCamera {
+                id: String::from("north_camera"),
+                name: String::from("Gate"),
+                violation_count: 3,
+            }],
+            evidence: vec![ViewReportEvidence {
+                id: String::from("missing"),
+                camera: String::from("Gate"),
+                timestamp: 1000,
+                violation_count: 3,
+            }],
+        }
+    }
+
+    #[test]
+    fn csv_lists_summary_and_counts() {
+        let csv = String::from_utf8(report().to_csv().unwrap()).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "section,id,name,value");
+        assert_eq!(lines[1], "summary,cluster,\"North, Site\",north");
+        assert!(lines.contains(&"summary,compliance_rate,,0.7500"));
+        assert!(lines.contains(&"violation,missing_hardhat,missing_hardhat,3"));
+        assert!(lines.contains(&"camera,north_camera,Gate,3"));
+    }
+
+    #[test]
+    fn pdf_renders_without_evidence_images() {
+        let pdf = report().to_pdf().unwrap();
+
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -10,7 +10,10 @@ use crate::{
         event::EventKind,
         user::{User, UserQuery, UserRole},
     },
-    views::cluster::{ClusterRef, ViewCluster},
+    views::{
+        cluster::{ClusterRef, ViewCluster},
+        page::{PageCursor, PageOrder, ViewPage, page_limit, page_total},
+    },
 };
 
 const COLLECTION: &str = "users";
@@ -60,9 +63,15 @@ impl ViewUser {
             role: user.role,
         }
     }
-    pub async fn find_many(query: &UserQuery, db: &Database) -> Result<Vec<Self>, EventKind> {
+    pub async fn find_many(query: &UserQuery, db: &Database) -> Result<ViewPage<Self>, EventKind> {
         let collection = db.collection::<Self>(COLLECTION);
 
+        let limit = page_limit(query.limit);
+        let cursor = match &query.cursor {
+            Some(cursor) => Some(PageCursor::<String>::decode(cursor)?),
+            None => None,
+        };
+
         let mut user_query = Vec::new();
 
         if let Some(text) = &query.text {
@@ -92,23 +101,28 @@ impl ViewUser {
                 "$ne": [ "$role", "super_admin" ]
             });
         }
+        if let Some(scope) = &query.scope {
+            user_query.push(doc! {
+                "$gt": [
+                    { "$size": { "$setIntersection": ["$cluster_id", to_bson::<Vec<String>>(scope).unwrap()] } },
+                    0
+                ]
+            });
+        }
+
+        let total = page_total(&collection, vec![Self::create_match_stage(&user_query)]).await?;
 
-        let mut pipeline = vec![
-            Self::create_match_stage(&user_query),
+        let mut pipeline = vec![Self::create_match_stage(&user_query)];
+        pipeline.extend(PageCursor::create_page_stages(
+            "name",
+            query.order.unwrap_or(PageOrder::Asc),
+            cursor,
+            limit,
+        ));
+        pipeline.extend([
             Self::create_cluster_lookup_stage(),
             Self::create_project_stage(),
-        ];
-
-        if let Some(skip) = query.skip {
-            pipeline.push(doc! {
-                "$skip": to_bson::<usize>(&skip).unwrap()
-            });
-        }
-        if let Some(limit) = query.limit {
-            pipeline.push(doc! {
-                "$limit": to_bson::<usize>(&limit).unwrap()
-            });
-        }
+        ]);
 
         match collection.aggregate(pipeline, None).await {
             Ok(mut cursor) => {
@@ -117,11 +131,9 @@ impl ViewUser {
                     let user = from_document::<Self>(doc).unwrap();
                     users.push(user);
                 }
-                if !users.is_empty() {
-                    Ok(users)
-                } else {
-                    Err(EventKind::NotFound)
-                }
+                Ok(ViewPage::from(users, total, limit, |v| {
+                    (v.name.clone(), v.id.clone())
+                }))
             }
             Err(e) => {
                 println!("ERROR: {:?}", e);